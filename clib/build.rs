@@ -1,12 +1,25 @@
 extern crate cbindgen;
 
 use std::env;
+use std::path::Path;
 
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-    cbindgen::Builder::new().with_crate(crate_dir)
-        .with_language(cbindgen::Language::C)
+    let config = cbindgen::Config::from_file(Path::new(&crate_dir).join("cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+    cbindgen::Builder::new().with_crate(&crate_dir)
+        .with_config(config)
         .generate()
         .expect("Unable to generate bindings")
         .write_to_file("dt_core_clib.h");
-}
\ No newline at end of file
+
+    // Opt-in, syncs the header of Go wrapper, e.g. `DT_SYNC_GO_HEADER=1 cargo build -p clib`.
+    if env::var_os("DT_SYNC_GO_HEADER").is_some_and(|it| it != "0") {
+        let go_include = Path::new(&crate_dir).join("../go/dt_core_golang/include");
+        std::fs::copy("dt_core_clib.h", go_include.join("dt_core_clib.h")).expect("Unable to copy bindings");
+    }
+
+    println!("cargo:rerun-if-env-changed=DT_SYNC_GO_HEADER");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
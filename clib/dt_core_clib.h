@@ -1,14 +1,277 @@
+#ifndef DT_CORE_CLIB_H
+#define DT_CORE_CLIB_H
+
+/* Generated by cbindgen from clib/src, do not edit manually. */
+
 #include <stdarg.h>
 #include <stdbool.h>
 #include <stdint.h>
 #include <stdlib.h>
 
+#define DT_LOG_DEBUG 0
+
+#define DT_LOG_INFO 1
+
+#define DT_LOG_WARNING 2
+
+#define DT_LOG_ERROR 3
+
+/**
+ * Disables even the errors.
+ */
+#define DT_LOG_SILENT 4
+
+/**
+ * No error.
+ */
+#define DT_OK 0
+
+/**
+ * Given data is invalid, e.g. event failed the verification.
+ */
+#define DT_ERR_VERIFY 1
+
+/**
+ * Due to internal reason.
+ */
+#define DT_ERR_INTERNAL 2
+
+/**
+ * Error other than others, e.g. SDK not initialized.
+ */
+#define DT_ERR_RUNTIME 3
+
+/**
+ * Due to network reason.
+ */
+#define DT_ERR_NETWORK 4
+
+/**
+ * Due to remote reason.
+ */
+#define DT_ERR_REMOTE 5
+
+/**
+ * Caused by the host, e.g. invalid init config, or the argument can't be parsed.
+ */
+#define DT_ERR_HOST 6
+
+/**
+ * Given argument can't be parsed, e.g. NULL, non UTF-8 or malformed JSON.
+ * It's the same code as `DT_ERR_HOST`, since the invalid config is also given by the argument.
+ */
+#define DT_ERR_ARGUMENT DT_ERR_HOST
+
+/**
+ * Context to add events of a user, see `dt_with_user`.
+ */
+typedef struct DTUserContext DTUserContext;
+
+/**
+ * Initializes the SDK by the config in JSON object string.
+ * Each function returning `int8_t` returns 1 if succeed, otherwise 0 and the detail is in `dt_last_error`.
+ */
 int8_t dt_init(const char *raw_config);
 
+/**
+ * Adds the event in JSON object string.
+ */
 int8_t dt_add_event(const char *raw_event);
 
-void dt_flush(void);
+/**
+ * Tracks the event of the user, the properties are in JSON object string. `raw_acid` is nullable.
+ */
+int8_t dt_track(const char *raw_dt_id,
+                const char *raw_acid,
+                const char *raw_event_name,
+                const char *raw_props,
+                const char *raw_sdk_type);
+
+int8_t dt_user_set(const char *raw_dt_id,
+                   const char *raw_acid,
+                   const char *raw_props,
+                   const char *raw_sdk_type);
+
+int8_t dt_user_set_once(const char *raw_dt_id,
+                        const char *raw_acid,
+                        const char *raw_props,
+                        const char *raw_sdk_type);
+
+int8_t dt_user_add(const char *raw_dt_id,
+                   const char *raw_acid,
+                   const char *raw_props,
+                   const char *raw_sdk_type);
+
+int8_t dt_user_unset(const char *raw_dt_id,
+                     const char *raw_acid,
+                     const char *raw_props,
+                     const char *raw_sdk_type);
+
+int8_t dt_user_delete(const char *raw_dt_id,
+                      const char *raw_acid,
+                      const char *raw_props,
+                      const char *raw_sdk_type);
+
+int8_t dt_user_append(const char *raw_dt_id,
+                      const char *raw_acid,
+                      const char *raw_props,
+                      const char *raw_sdk_type);
+
+int8_t dt_user_uniq_append(const char *raw_dt_id,
+                           const char *raw_acid,
+                           const char *raw_props,
+                           const char *raw_sdk_type);
+
+int8_t dt_flush(void);
 
-void dt_close(void);
+int8_t dt_close(void);
+
+/**
+ * Closes within the timeout, the events not sent in time are dropped and reported to the error hook,
+ * e.g. by a hung upload. The numbers of events sent and remaining while closing are written to `sent` and
+ * `remaining`, which could be NULL. The timeout only applies to the consumers uploading by workers, the log
+ * consumer writes all the events to the file before returning.
+ *
+ * # Safety
+ * `sent` and `remaining` must be NULL or valid to write.
+ */
+int8_t dt_close_with_timeout(uint64_t timeout_ms,
+                             uint64_t *sent,
+                             uint64_t *remaining);
+
+/**
+ * Returns the snapshot of the runtime metrics in JSON object string, which should be released by `dt_free_string`,
+ * e.g. "events_accepted", "events_rejected" (by reason), "bytes_written", "upload_failures", "queue_depth" and
+ * "last_flush_time" (milliseconds since epoch, 0 if never). Counters are monotonic since the process started.
+ */
+char *dt_stats(void);
+
+/**
+ * Returns the runtime metrics in Prometheus text exposition format, which should be released by `dt_free_string`.
+ */
+char *dt_prometheus_metrics(void);
+
+/**
+ * Serves the runtime metrics at "/metrics" by a tiny HTTP server in background, e.g. on "127.0.0.1:9464".
+ * Only one server is allowed, which can also be started by "metrics_addr" in the init config.
+ */
+int8_t dt_serve_metrics(const char *raw_addr);
 
 void dt_toggle_logger(uint8_t enable);
+
+/**
+ * Sets the minimum level of logs, `DT_LOG_*`, `DT_LOG_SILENT` disables even the errors.
+ * Logs below warning are only written if the logging is enabled.
+ */
+void dt_set_log_level(int8_t level);
+
+/**
+ * Writes the logs to stderr, which is the default.
+ */
+void dt_log_to_stderr(void);
+
+/**
+ * Writes the logs to the file, which is rotated by `max_bytes`, with at most `max_files` rotated ones kept.
+ * The rotated files are named "{path}.1" (the latest) to "{path}.{max_files}".
+ */
+int8_t dt_log_to_file(const char *raw_path,
+                      uint64_t max_bytes,
+                      uint32_t max_files);
+
+/**
+ * Sets the format of the logs written to stderr and file, "text" (default) or "json", which writes a JSON object
+ * per line with "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+ */
+int8_t dt_set_log_format(const char *raw_format);
+
+/**
+ * Forwards the logs to the callback, NULL to write them to stderr.
+ * The callback receives the level (`DT_LOG_*`) and the message,
+ * which is only valid during the invocation. It may be invoked by any thread.
+ */
+void dt_set_log_callback(void (*callback)(int8_t, const char*));
+
+/**
+ * Replaces all the static common properties by the given JSON object string.
+ */
+int8_t dt_set_static_common_props(const char *raw_props);
+
+/**
+ * Merges the given JSON object string into the static common properties.
+ */
+int8_t dt_merge_static_common_props(const char *raw_props);
+
+int8_t dt_unset_static_common_prop(const char *raw_key);
+
+/**
+ * Returns the static common properties in JSON object string, which should be released by `dt_free_string`.
+ * Returns NULL if failed.
+ */
+char *dt_get_static_common_props(void);
+
+int8_t dt_clear_static_common_props(void);
+
+/**
+ * Releases the string returned by this library.
+ *
+ * # Safety
+ * The pointer must be returned by this library and not be released yet.
+ */
+void dt_free_string(char *raw);
+
+/**
+ * Creates the context of the user, which fills "#dt_id", "#acid" and the user-scoped common properties
+ * (in JSON object string) of events added through it. Both `raw_acid` and `raw_props` are nullable.
+ * Returns NULL if failed, otherwise it should be released by `dt_release_user`.
+ */
+struct DTUserContext *dt_with_user(const char *raw_dt_id,
+                                   const char *raw_acid,
+                                   const char *raw_props);
+
+/**
+ * Adds the event of given name and type ("track" or "user") of the user,
+ * the properties are in JSON object string.
+ *
+ * # Safety
+ * The context must be created by `dt_with_user` and not be released yet.
+ */
+int8_t dt_user_add_event(const struct DTUserContext *ctx,
+                         const char *raw_event_name,
+                         const char *raw_event_type,
+                         const char *raw_props,
+                         const char *raw_sdk_type);
+
+/**
+ * # Safety
+ * The context must be created by `dt_with_user` and not be released yet.
+ */
+void dt_release_user(struct DTUserContext *ctx);
+
+/**
+ * Sets the callback of dynamic common properties, NULL to clear.
+ * The callback returns properties in JSON object string, which should be kept valid until the next invocation.
+ */
+int8_t dt_set_dynamic_common_props(const char *(*callback)(void));
+
+/**
+ * Sets the hook receiving each error, NULL to clear.
+ * The hook receives the code (`DT_ERR_*`), the message, the event in JSON object string (NULL if absent)
+ * and the category, i.e. "add", "flush" or "write". The strings are only valid during the invocation.
+ * The hook may be invoked by any thread, it should be fast and must not call the functions of this library.
+ * If `redact` is not 0, the event only keeps meta properties identifying it, like "#event_name".
+ */
+int8_t dt_set_error_hook(void (*hook)(int32_t, const char*, const char*, const char*),
+                         uint8_t redact);
+
+/**
+ * Returns the code (`DT_ERR_*`) of the last failed call on this thread, or `DT_OK` if it succeeded.
+ */
+int32_t dt_last_error_code(void);
+
+/**
+ * Returns the message of the last failed call on this thread, or NULL if it succeeded.
+ * The buffer is owned by this library and stays valid until the next call on this thread, hence don't release it.
+ */
+const char *dt_last_error(void);
+
+#endif /* DT_CORE_CLIB_H */
@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use common::log_error;
+use common::util::error::DTError;
+
+// Same as the codes of `DTError`.
+
+/// No error.
+pub const DT_OK: i32 = 0;
+/// Given data is invalid, e.g. event failed the verification.
+pub const DT_ERR_VERIFY: i32 = 1;
+/// Due to internal reason.
+pub const DT_ERR_INTERNAL: i32 = 2;
+/// Error other than others, e.g. SDK not initialized.
+pub const DT_ERR_RUNTIME: i32 = 3;
+/// Due to network reason.
+pub const DT_ERR_NETWORK: i32 = 4;
+/// Due to remote reason.
+pub const DT_ERR_REMOTE: i32 = 5;
+/// Caused by the host, e.g. invalid init config, or the argument can't be parsed.
+pub const DT_ERR_HOST: i32 = 6;
+/// Given argument can't be parsed, e.g. NULL, non UTF-8 or malformed JSON.
+/// It's the same code as `DT_ERR_HOST`, since the invalid config is also given by the argument.
+pub const DT_ERR_ARGUMENT: i32 = DT_ERR_HOST;
+
+thread_local! {
+    // Error of the last call on this thread.
+    static LAST_ERROR: RefCell<Option<(i32, CString)>> = const { RefCell::new(None) };
+}
+
+/// Records the result of the call, returns 1 if succeed, otherwise 0.
+pub(crate) fn report<T>(result: common::util::error::Result<T>) -> i8 {
+    match result {
+        Ok(_) => {
+            clear();
+            1
+        },
+        Err(e) => {
+            set(&e);
+            0
+        }
+    }
+}
+
+pub(crate) fn clear() {
+    LAST_ERROR.with(|it| it.replace(None));
+}
+
+pub(crate) fn set(e: &DTError) {
+    log_error!("{e}");
+    // Interior NUL would be the only reason of failure, which is unlikely in error messages.
+    let msg = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|it| it.replace(Some((e.code(), msg))));
+}
+
+/// Returns the code (`DT_ERR_*`) of the last failed call on this thread, or `DT_OK` if it succeeded.
+#[no_mangle]
+pub extern "C" fn dt_last_error_code() -> i32 {
+    LAST_ERROR.with(|it| it.borrow().as_ref().map(|(code, _)| *code).unwrap_or(DT_OK))
+}
+
+/// Returns the message of the last failed call on this thread, or NULL if it succeeded.
+/// The buffer is owned by this library and stays valid until the next call on this thread, hence don't release it.
+#[no_mangle]
+pub extern "C" fn dt_last_error() -> *const c_char {
+    LAST_ERROR.with(|it| it.borrow().as_ref().map(|(_, msg)| msg.as_ptr()).unwrap_or(std::ptr::null()))
+}
@@ -1,82 +1,364 @@
-use std::ffi::{c_char, CStr};
+mod last_error;
+
+use std::ffi::{c_char, CStr, CString};
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use serde_json::{Map, Value};
-use common::log_error;
-use common::util::error::DTError;
+use common::event::tracking::assemble;
 use common::util::error::DTError::HostError;
-use common::util::result::{dissolve, dissolve_bool};
 use common::util::error::Result;
+use common::util::logger::{install_default_logger, Level, LogFormat, LogSink, set_log_sink};
+use crate::last_error::report;
+
+pub use crate::last_error::*;
+
+// Levels of logs, same as `common::util::logger::LEVEL_*`.
+
+pub const DT_LOG_DEBUG: i8 = 0;
+pub const DT_LOG_INFO: i8 = 1;
+pub const DT_LOG_WARNING: i8 = 2;
+pub const DT_LOG_ERROR: i8 = 3;
+/// Disables even the errors.
+pub const DT_LOG_SILENT: i8 = 4;
 
+/// Initializes the SDK by the config in JSON object string.
+/// Each function returning `int8_t` returns 1 if succeed, otherwise 0 and the detail is in `dt_last_error`.
 #[no_mangle]
 pub extern "C" fn dt_init(raw_config: *const c_char) -> i8 {
-    let mut map = match cchar2map(raw_config) {
-        Ok(map) => map,
-        Err(e) => {
-            log_error!("{e}");
-            return 0;
+    install_default_logger();
+    report(cchar2map(raw_config).and_then(|mut map| {
+        if let Some(Value::Number(number)) = map.get("_debug") {
+            if let Some(number) = number.as_u64() {
+                map.insert(String::from("_debug"), Value::from(number != 0));
+            }
         }
+        common::init_by_config(map)
+    }))
+}
+
+/// Adds the event in JSON object string.
+#[no_mangle]
+pub extern "C" fn dt_add_event(raw_event: *const c_char) -> i8 {
+    report(cchar2map(raw_event).and_then(common::add))
+}
+
+/// Tracks the event of the user, the properties are in JSON object string. `raw_acid` is nullable.
+#[no_mangle]
+pub extern "C" fn dt_track(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_event_name: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(cchar2str(raw_event_name).and_then(|event_name| {
+        with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, |dt_id, acid, props, sdk_type| {
+            common::track(dt_id, acid, event_name, props, sdk_type)
+        })
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_set(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_set))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_set_once(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_set_once))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_add(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_add))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_unset(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_unset))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_delete(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_delete))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_append(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_append))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_user_uniq_append(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    report(with_user_args(raw_dt_id, raw_acid, raw_props, raw_sdk_type, common::user_uniq_append))
+}
+
+fn with_user_args<F>(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char, f: F) -> Result<()>
+    where F: FnOnce(String, Option<String>, Map<String, Value>, &str) -> Result<()>
+{
+    let dt_id = cchar2str(raw_dt_id)?.to_string();
+    let acid = if raw_acid.is_null() { None } else { Some(cchar2str(raw_acid)?.to_string()) };
+    f(dt_id, acid, cchar2map(raw_props)?, cchar2str(raw_sdk_type)?)
+}
+
+#[no_mangle]
+pub extern "C" fn dt_flush() -> i8 {
+    report(common::flush())
+}
+
+#[no_mangle]
+pub extern "C" fn dt_close() -> i8 {
+    report(common::close())
+}
+
+/// Closes within the timeout, the events not sent in time are dropped and reported to the error hook,
+/// e.g. by a hung upload. The numbers of events sent and remaining while closing are written to `sent` and
+/// `remaining`, which could be NULL. The timeout only applies to the consumers uploading by workers, the log
+/// consumer writes all the events to the file before returning.
+///
+/// # Safety
+/// `sent` and `remaining` must be NULL or valid to write.
+#[no_mangle]
+pub unsafe extern "C" fn dt_close_with_timeout(timeout_ms: u64, sent: *mut u64, remaining: *mut u64) -> i8 {
+    let result = common::close_with_timeout(Duration::from_millis(timeout_ms));
+    if let Ok(outcome) = &result {
+        if let Some(sent) = sent.as_mut() {
+            *sent = outcome.sent;
+        }
+        if let Some(remaining) = remaining.as_mut() {
+            *remaining = outcome.remaining;
+        }
+    }
+    report(result)
+}
+
+/// Returns the snapshot of the runtime metrics in JSON object string, which should be released by `dt_free_string`,
+/// e.g. "events_accepted", "events_rejected" (by reason), "bytes_written", "upload_failures", "queue_depth" and
+/// "last_flush_time" (milliseconds since epoch, 0 if never). Counters are monotonic since the process started.
+#[no_mangle]
+pub extern "C" fn dt_stats() -> *mut c_char {
+    // JSON string has no interior NUL.
+    CString::new(Value::Object(common::stats()).to_string()).unwrap_or_default().into_raw()
+}
+
+/// Returns the runtime metrics in Prometheus text exposition format, which should be released by `dt_free_string`.
+#[no_mangle]
+pub extern "C" fn dt_prometheus_metrics() -> *mut c_char {
+    // Metric names and values have no interior NUL.
+    CString::new(common::prometheus_metrics()).unwrap_or_default().into_raw()
+}
+
+/// Serves the runtime metrics at "/metrics" by a tiny HTTP server in background, e.g. on "127.0.0.1:9464".
+/// Only one server is allowed, which can also be started by "metrics_addr" in the init config.
+#[no_mangle]
+pub extern "C" fn dt_serve_metrics(raw_addr: *const c_char) -> i8 {
+    report(cchar2str(raw_addr).and_then(common::serve_metrics))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_toggle_logger(enable: u8) {
+    common::util::logger::LOG_ENABLED.store(enable != 0, Ordering::Relaxed);
+}
+
+/// Sets the minimum level of logs, `DT_LOG_*`, `DT_LOG_SILENT` disables even the errors.
+/// Logs below warning are only written if the logging is enabled.
+#[no_mangle]
+pub extern "C" fn dt_set_log_level(level: i8) {
+    common::util::logger::set_log_level(level);
+}
+
+/// Writes the logs to stderr, which is the default.
+#[no_mangle]
+pub extern "C" fn dt_log_to_stderr() {
+    set_log_sink(LogSink::Stderr);
+}
+
+/// Writes the logs to the file, which is rotated by `max_bytes`, with at most `max_files` rotated ones kept.
+/// The rotated files are named "{path}.1" (the latest) to "{path}.{max_files}".
+#[no_mangle]
+pub extern "C" fn dt_log_to_file(raw_path: *const c_char, max_bytes: u64, max_files: u32) -> i8 {
+    let result = cchar2str(raw_path).and_then(|path| LogSink::file(path, max_bytes, max_files as usize));
+    report(result.map(set_log_sink))
+}
+
+/// Sets the format of the logs written to stderr and file, "text" (default) or "json", which writes a JSON object
+/// per line with "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+#[no_mangle]
+pub extern "C" fn dt_set_log_format(raw_format: *const c_char) -> i8 {
+    report(cchar2str(raw_format).and_then(LogFormat::parse).map(common::util::logger::set_log_format))
+}
+
+/// Forwards the logs to the callback, NULL to write them to stderr.
+/// The callback receives the level (`DT_LOG_*`) and the message,
+/// which is only valid during the invocation. It may be invoked by any thread.
+#[no_mangle]
+pub extern "C" fn dt_set_log_callback(callback: Option<extern "C" fn(i8, *const c_char)>) {
+    let Some(callback) = callback else {
+        set_log_sink(LogSink::Stderr);
+        return;
     };
+    set_log_sink(LogSink::Callback(Box::new(move |level, msg| {
+        let level = match level {
+            Level::Error => DT_LOG_ERROR,
+            Level::Warn => DT_LOG_WARNING,
+            Level::Info => DT_LOG_INFO,
+            Level::Debug | Level::Trace => DT_LOG_DEBUG,
+        };
+        let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+        callback(level, msg.as_ptr());
+    })));
+}
+
+/// Replaces all the static common properties by the given JSON object string.
+#[no_mangle]
+pub extern "C" fn dt_set_static_common_props(raw_props: *const c_char) -> i8 {
+    report(cchar2map(raw_props).and_then(common::set_static_common_props))
+}
 
-    if let Some(Value::Number(number)) = map.get("_debug") {
-        if let Some(number) = number.as_u64() {
-            map.insert(String::from("_debug"), Value::from(number != 0));
+/// Merges the given JSON object string into the static common properties.
+#[no_mangle]
+pub extern "C" fn dt_merge_static_common_props(raw_props: *const c_char) -> i8 {
+    report(cchar2map(raw_props).and_then(common::merge_static_common_props))
+}
+
+#[no_mangle]
+pub extern "C" fn dt_unset_static_common_prop(raw_key: *const c_char) -> i8 {
+    report(cchar2str(raw_key).and_then(common::unset_static_common_prop))
+}
+
+/// Returns the static common properties in JSON object string, which should be released by `dt_free_string`.
+/// Returns NULL if failed.
+#[no_mangle]
+pub extern "C" fn dt_get_static_common_props() -> *mut c_char {
+    let result = common::get_static_common_props().and_then(|props| {
+        CString::new(Value::Object(props).to_string()).map_err(|e| HostError(e.to_string()))
+    });
+    match result {
+        Ok(cs) => {
+            last_error::clear();
+            cs.into_raw()
+        },
+        Err(e) => {
+            last_error::set(&e);
+            std::ptr::null_mut()
         }
     }
+}
 
-    let success = dissolve_bool::<(), DTError>(common::init_by_config(map)).unwrap();
-    if success {
-        1
-    } else {
-        0
+#[no_mangle]
+pub extern "C" fn dt_clear_static_common_props() -> i8 {
+    report(common::clear_static_common_props())
+}
+
+/// Releases the string returned by this library.
+///
+/// # Safety
+/// The pointer must be returned by this library and not be released yet.
+#[no_mangle]
+pub unsafe extern "C" fn dt_free_string(raw: *mut c_char) {
+    if !raw.is_null() {
+        drop(CString::from_raw(raw));
     }
 }
 
+/// Context to add events of a user, see `dt_with_user`.
+pub struct DTUserContext(common::event::user_context::UserContext);
+
+/// Creates the context of the user, which fills "#dt_id", "#acid" and the user-scoped common properties
+/// (in JSON object string) of events added through it. Both `raw_acid` and `raw_props` are nullable.
+/// Returns NULL if failed, otherwise it should be released by `dt_release_user`.
 #[no_mangle]
-pub extern "C" fn dt_add_event(raw_event: *const c_char) -> i8 {
-    let map = match cchar2map(raw_event) {
-        Ok(map) => map,
+pub extern "C" fn dt_with_user(raw_dt_id: *const c_char, raw_acid: *const c_char, raw_props: *const c_char) -> *mut DTUserContext {
+    let result = cchar2str(raw_dt_id).and_then(|dt_id| {
+        let acid = if raw_acid.is_null() { None } else { Some(cchar2str(raw_acid)?.to_string()) };
+        let props = if raw_props.is_null() { Map::new() } else { cchar2map(raw_props)? };
+        common::with_user(dt_id.to_string(), acid, props)
+    });
+    match result {
+        Ok(ctx) => {
+            last_error::clear();
+            Box::into_raw(Box::new(DTUserContext(ctx)))
+        },
         Err(e) => {
-            log_error!("{e}");
-            return 0;
+            last_error::set(&e);
+            std::ptr::null_mut()
         }
+    }
+}
+
+/// Adds the event of given name and type ("track" or "user") of the user,
+/// the properties are in JSON object string.
+///
+/// # Safety
+/// The context must be created by `dt_with_user` and not be released yet.
+#[no_mangle]
+pub unsafe extern "C" fn dt_user_add_event(ctx: *const DTUserContext, raw_event_name: *const c_char, raw_event_type: *const c_char, raw_props: *const c_char, raw_sdk_type: *const c_char) -> i8 {
+    let Some(ctx) = ctx.as_ref() else {
+        return report(Err::<(), _>(HostError(String::from("User context is NULL!"))));
     };
+    report((|| {
+        let event = assemble(cchar2str(raw_event_name)?, cchar2str(raw_event_type)?, cchar2map(raw_props)?, cchar2str(raw_sdk_type)?);
+        ctx.0.add(event)
+    })())
+}
 
-    let success = dissolve_bool::<(), DTError>(common::add(map)).unwrap();
-    if success {
-        1
-    } else {
-        0
+/// # Safety
+/// The context must be created by `dt_with_user` and not be released yet.
+#[no_mangle]
+pub unsafe extern "C" fn dt_release_user(ctx: *mut DTUserContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
     }
 }
 
+/// Sets the callback of dynamic common properties, NULL to clear.
+/// The callback returns properties in JSON object string, which should be kept valid until the next invocation.
 #[no_mangle]
-pub extern "C" fn dt_flush() {
-    dissolve::<(), DTError>(common::flush()).unwrap();
+pub extern "C" fn dt_set_dynamic_common_props(callback: Option<extern "C" fn() -> *const c_char>) -> i8 {
+    let result = if let Some(callback) = callback {
+        common::set_dynamic_common_props(move || {
+            let props = callback();
+            if props.is_null() {
+                Ok(Map::new())
+            } else {
+                cchar2map(props)
+            }
+        })
+    } else {
+        common::clear_dynamic_common_props()
+    };
+    report(result)
 }
 
+/// Sets the hook receiving each error, NULL to clear.
+/// The hook receives the code (`DT_ERR_*`), the message, the event in JSON object string (NULL if absent)
+/// and the category, i.e. "add", "flush" or "write". The strings are only valid during the invocation.
+/// The hook may be invoked by any thread, it should be fast and must not call the functions of this library.
+/// If `redact` is not 0, the event only keeps meta properties identifying it, like "#event_name".
 #[no_mangle]
-pub extern "C" fn dt_close() {
-    dissolve::<(), DTError>(common::close()).unwrap();
+pub extern "C" fn dt_set_error_hook(hook: Option<extern "C" fn(i32, *const c_char, *const c_char, *const c_char)>, redact: u8) -> i8 {
+    let result = if let Some(hook) = hook {
+        common::set_error_hook(move |e, event, category| {
+            let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+            let event = event.and_then(|event| CString::new(Value::from(event.clone()).to_string()).ok());
+            let category = CString::new(category.as_str()).unwrap_or_default();
+            hook(e.code(), message.as_ptr(), event.as_ref().map_or(std::ptr::null(), |it| it.as_ptr()), category.as_ptr());
+        }, redact != 0)
+    } else {
+        common::clear_error_hook()
+    };
+    report(result)
 }
 
-#[no_mangle]
-pub extern "C" fn dt_toggle_logger(enable: u8) {
-    common::util::logger::LOG_ENABLED.store(enable != 0, Ordering::Relaxed);
+fn cchar2str<'a>(cc: *const c_char) -> Result<&'a str> {
+    if cc.is_null() {
+        return Err(HostError(String::from("Given string is NULL!")));
+    }
+    let cstr = unsafe { CStr::from_ptr(cc) };
+    cstr.to_str().map_err(|e| HostError(e.to_string()))
 }
 
 fn cchar2map(cc: *const c_char) -> Result<Map<String, Value>> {
-    let cstr = unsafe { CStr::from_ptr(cc) };
-    let ss = match cstr.to_str() {
-        Ok(config) => config,
-        Err(e) => {
-            return Err(HostError(e.to_string()));
-        }
-    };
+    let ss = cchar2str(cc)?;
     let json_result = serde_json::from_str(ss);
     match json_result {
         Ok(result) => match result {
             Value::Object(map) => Ok(map),
-            _ => return Err(HostError(format!("Failed to parse init config! Given: {:?}", result))),
+            _ => return Err(HostError(format!("Given JSON is not an object! Given: {:?}", result))),
         },
         Err(e) => {
             return Err(HostError(format!("{}", e.to_string())));
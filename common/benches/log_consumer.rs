@@ -0,0 +1,50 @@
+use std::env::temp_dir;
+use std::fs;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::json;
+use common::bench::expire_file_time;
+use common::consumer::Consumer;
+use common::consumer::log::LogConsumer;
+
+#[derive(Clone, Copy, Debug)]
+enum Rotation {
+    None,
+    Size,
+    Time,
+}
+
+fn bench_write(c: &mut Criterion) {
+    let event = json!({
+        "#app_id": "appid_1234567890",
+        "#dt_id": "1234567890987654321",
+        "#event_name": "test_event",
+        "#event_type": "track",
+        "properties": {"productNames": ["Lua", "hello"], "productType": "Lua book", "producePrice": 80}
+    }).as_object().unwrap().to_owned();
+    let event_bytes = serde_json::to_string(&event).unwrap().len() as u64 + 1;
+
+    let mut group = c.benchmark_group("log_consumer");
+    group.throughput(Throughput::Bytes(event_bytes));
+    for rotation in [Rotation::None, Rotation::Size, Rotation::Time] {
+        let path = temp_dir().join(format!("dt_bench_log_consumer_{rotation:?}").to_lowercase());
+        let _ = fs::remove_dir_all(&path);
+        // Rotates by size every 10 events
+        let max_file_size_bytes = match rotation {
+            Rotation::Size => Some(event_bytes * 10),
+            _ => None,
+        };
+        let mut consumer = LogConsumer::new(path.to_string_lossy().to_string(), 100, None, max_file_size_bytes);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{rotation:?}").to_lowercase()), &rotation, |b, rotation| b.iter(|| {
+            if let Rotation::Time = rotation {
+                expire_file_time(&mut consumer);
+            }
+            consumer.add(Box::new(black_box(event.clone()))).expect("Failed to add");
+        }));
+        consumer.close().expect("Failed to close");
+        let _ = fs::remove_dir_all(&path);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_write);
+criterion_main!(benches);
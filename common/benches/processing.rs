@@ -0,0 +1,100 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use serde_json::{json, Map, Value};
+use common::bench::{eventify, fulfill_by_comm_props, init, verify_event};
+use common::event::Event;
+
+fn to_event(value: Value) -> Event {
+    value.as_object().unwrap().to_owned()
+}
+
+fn flat_event() -> Event {
+    to_event(json!({
+        "#app_id": "appid_1234567890",
+        "#dt_id": "1234567890987654321",
+        "#bundle_id": "com.example",
+        "#event_name": "test_event",
+        "#event_type": "track",
+        "#sdk_type": "rust",
+        "#sdk_version_name": "0.0.0",
+        "productNames": ["Lua", "hello"],
+        "productType": "Lua book",
+        "producePrice": 80,
+        "shop": "xx-shop",
+        "#os": "1.1.1.1",
+        "sex": "female"
+    }))
+}
+
+fn nested_event(event_name: &str, event_type: &str, properties: Value) -> Event {
+    to_event(json!({
+        "#app_id": "appid_1234567890",
+        "#dt_id": "1234567890987654321",
+        "#bundle_id": "com.example",
+        "#event_name": event_name,
+        "#event_type": event_type,
+        "#event_time": 1700000000000u64,
+        "#event_syn": "syn_1234567890",
+        "properties": properties
+    }))
+}
+
+fn bench_eventify(c: &mut Criterion) {
+    let event = flat_event();
+    c.bench_function("eventify", |b| b.iter_batched(|| event.clone(), |event| eventify(black_box(event)), BatchSize::SmallInput));
+}
+
+fn bench_verify_event(c: &mut Criterion) {
+    init().expect("Failed to init");
+    let events = [
+        ("preset", nested_event("#session_start", "track", json!({
+            "#sdk_type": "rust",
+            "#sdk_version_name": "0.0.0",
+            "#is_first_time": true,
+            "#resume_from_background": false,
+            "#start_reason": "launch",
+            "#background_duration": 1200
+        }))),
+        ("custom", nested_event("test_event", "track", json!({
+            "#sdk_type": "rust",
+            "#sdk_version_name": "0.0.0",
+            "productNames": ["Lua", "hello"],
+            "productType": "Lua book",
+            "producePrice": 80,
+            "shop": "xx-shop",
+            "sex": "female"
+        }))),
+        ("user", nested_event("#user_set", "user", json!({
+            "#sdk_type": "rust",
+            "#sdk_version_name": "0.0.0",
+            "level": 10,
+            "vip": true,
+            "nickname": "someone"
+        }))),
+    ];
+    let mut group = c.benchmark_group("verify_event");
+    for (name, event) in events.iter() {
+        verify_event(event).expect("This event is not valid");
+        group.bench_with_input(BenchmarkId::from_parameter(name), event, |b, event| b.iter(|| verify_event(black_box(event))));
+    }
+    group.finish();
+}
+
+fn bench_fulfill_by_comm_props(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fulfill_by_comm_props");
+    for size in [10, 100, 1000] {
+        let static_props: Map<String, Value> = (0..size).map(|i| (format!("static_{i}"), json!(i))).collect();
+        common::set_static_common_props(static_props).expect("Failed to set static common properties");
+        let user_props: Map<String, Value> = (0..size).map(|i| (format!("user_{i}"), json!(format!("value_{i}")))).collect();
+        let event = nested_event("test_event", "track", json!({"productType": "Lua book"}));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| b.iter_batched(
+            || event.clone(),
+            |mut event| fulfill_by_comm_props(black_box(&mut event), Some(&user_props), None),
+            BatchSize::SmallInput,
+        ));
+    }
+    group.finish();
+    common::clear_static_common_props().expect("Failed to clear static common properties");
+}
+
+criterion_group!(benches, bench_eventify, bench_verify_event, bench_fulfill_by_comm_props);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use common::bench::WorkerManager;
+
+const TASKS: usize = 1000;
+
+fn bench_schedule(c: &mut Criterion) {
+    let mut group = c.benchmark_group("worker_manager");
+    group.throughput(Throughput::Elements(TASKS as u64));
+    let done = Arc::new(AtomicUsize::new(0));
+    for size in [1, 4] {
+        let mut wm = WorkerManager::new(format!("bench-{size}"), size);
+        group.bench_with_input(BenchmarkId::new("schedule", size), &size, |b, _| b.iter(|| {
+            for _ in 0..TASKS {
+                let done = done.clone();
+                wm.schedule(move || {
+                    done.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            wm.place_barrier();
+        }));
+    }
+
+    for size in [1, 4] {
+        let mut wm = WorkerManager::new(format!("bench-delayed-{size}"), size);
+        group.bench_with_input(BenchmarkId::new("schedule_delayed", size), &size, |b, _| b.iter(|| {
+            // Out of order, to cover the sorting by time
+            for i in 0..TASKS {
+                let done = done.clone();
+                wm.schedule_delayed(move || {
+                    done.fetch_add(1, Ordering::Relaxed);
+                }, (TASKS - i) as u128 % 3);
+            }
+            wm.place_barrier();
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_schedule);
+criterion_main!(benches);
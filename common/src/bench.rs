@@ -0,0 +1,31 @@
+//! Internals exposed to the benches of `common/benches`, which is NOT a part of the API.
+
+use crate::consumer::log::LogConsumer;
+use crate::event::Event;
+use crate::event::common_properties::Props;
+use crate::util::error::Result;
+
+pub use crate::util::worker::message_queue::Task;
+pub use crate::util::worker::worker::WorkerManager;
+
+/// Initializes the rules of verification.
+pub fn init() -> Result<()> {
+    crate::event::init()
+}
+
+pub fn eventify(event: Event) -> Result<Event> {
+    crate::event::processing::eventify(event)
+}
+
+pub fn verify_event(event: &Event) -> Result<()> {
+    crate::event::data_verification::verify_event(event)
+}
+
+pub fn fulfill_by_comm_props(event: &mut Event, user_props: Option<&Props>, dynamic_props: Option<&Props>) -> Result<()> {
+    crate::event::common_properties::fulfill_by_comm_props(event, user_props, dynamic_props)
+}
+
+/// Makes the next add of the consumer rotate the file by time.
+pub fn expire_file_time(consumer: &mut LogConsumer) {
+    consumer.expire_file_time();
+}
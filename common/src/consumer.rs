@@ -1,3 +1,5 @@
+use std::time::Duration;
+use serde_json::{Map, Value};
 use crate::event::BoxedEvent;
 use crate::util::error::Result;
 
@@ -19,4 +21,27 @@ pub trait Consumer {
     fn flush(self: &mut Self) -> Result<()>;
 
     fn close(self: &mut Self) -> Result<()>;
+
+    /// Closes within the timeout: sends what can be sent in time, the remaining events are reported to the error
+    /// hook as dropped.
+    fn close_timeout(self: &mut Self, timeout: Duration) -> Result<CloseOutcome>;
+}
+
+/// Outcome of [Consumer::close_timeout].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CloseOutcome {
+    /// Events sent (or written) while closing.
+    pub sent: u64,
+    /// Events not sent, which are dropped. An upper bound, the ones still being sent by the consumer may get delivered.
+    pub remaining: u64,
+}
+
+impl CloseOutcome {
+    /// e.g. {"sent": 100, "remaining": 0}
+    pub fn to_map(&self) -> Map<String, Value> {
+        Map::from_iter([
+            (String::from("sent"), Value::from(self.sent)),
+            (String::from("remaining"), Value::from(self.remaining)),
+        ])
+    }
 }
\ No newline at end of file
@@ -1,101 +1,175 @@
-use std::cmp::{max, min};
+use std::cmp::min;
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::thread::sleep;
 use std::time::Duration;
-use crate::consumer::Consumer;
-use crate::{log_error};
+use crate::consumer::{CloseOutcome, Consumer};
+use crate::{log_debug, log_error, log_warning};
 use crate::event::BoxedEvent;
+use crate::util::worker::message_queue::{Priority, TaskHandle};
 use crate::util::worker::worker::WorkerManager;
-use crate::util::error::Result;
+use crate::util::error::{DTError, Result};
+use crate::util::error_hook;
+use crate::util::error_hook::ErrorCategory;
+use crate::util::metrics;
+
+/// Interval of the periodic flush, which also retries the failed uploads.
+const FLUSH_INTERVAL_MS: u128 = 5000;
+
+/// Uploads a batch of events.
+type Upload = dyn Fn(&[BoxedEvent]) -> Result<()> + Send + Sync;
 
 struct AsyncUploadConsumer {
-    cache: Arc<Mutex<VecDeque<BoxedEvent>>>,
+    uploader: Uploader,
     worker_manager: WorkerManager,
     flushing_process_count: Arc<Mutex<USizeHolder>>,
-    max_batch_size: usize
+    periodic_flush: TaskHandle,
+    closed: bool,
 }
 
 struct USizeHolder(usize);
 
+/// Takes a batch from the cache and uploads it, shared by the tasks on workers.
+#[derive(Clone)]
+struct Uploader {
+    cache: Arc<Mutex<VecDeque<BoxedEvent>>>,
+    max_batch_size: usize,
+    upload: Arc<Upload>,
+    // Events uploaded, and taken from the cache but not uploaded yet.
+    sent: Arc<AtomicU64>,
+    in_flight: Arc<AtomicU64>,
+    // Set on closing, the detached workers take nothing more to upload.
+    aborted: Arc<AtomicBool>,
+}
+
+impl Uploader {
+    fn upload_batch(&self) {
+        let batch: Vec<BoxedEvent> = if let Ok(mut cache) = self.cache.lock() {
+            if cache.is_empty() || self.aborted.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let size = min(cache.len(), self.max_batch_size);
+            let batch: Vec<BoxedEvent> = cache.drain(..size).collect();
+            metrics::set_queue_depth(cache.len());
+            self.in_flight.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            batch
+        } else {
+            // nothing to sent
+            return;
+        };
+
+        let result = (self.upload)(&batch);
+        let len = batch.len() as u64;
+        match result {
+            Ok(_) => {
+                self.sent.fetch_add(len, Ordering::Relaxed);
+            }
+            Err(e) => {
+                // Put back in order, retried by the next flush.
+                log_warning!("Failed to upload {len} events, will retry, {e}");
+                if let Ok(mut cache) = self.cache.lock() {
+                    for event in batch.into_iter().rev() {
+                        cache.push_front(event);
+                    }
+                    metrics::set_queue_depth(cache.len());
+                }
+            }
+        }
+        self.in_flight.fetch_sub(len, Ordering::Relaxed);
+    }
+}
+
+fn upload_stub(batch: &[BoxedEvent]) -> Result<()> {
+    let data_json = batch.iter()
+        .filter_map(|it| {
+            if let Ok(json) = serde_json::to_string(it) {
+                Some(json)
+            } else {
+                log_error!("Failed to jsonify the given event: {:?}", it);
+                None
+            }
+        }).collect::<Vec<String>>()
+        .join(",");
+    let data = format!("[{}]", data_json);
+    log_debug!("data: ({}) {}", batch.len(), data);
+    // upload!
+    sleep(Duration::from_millis(100));
+    Ok(())
+}
+
 impl AsyncUploadConsumer {
     fn new(num_threads: usize, max_batch_size: usize) -> Self {
-        AsyncUploadConsumer {
+        Self::with_upload(num_threads, max_batch_size, FLUSH_INTERVAL_MS, upload_stub)
+    }
+
+    fn with_upload<F>(num_threads: usize, max_batch_size: usize, flush_interval_ms: u128, upload: F) -> Self
+        where F: Fn(&[BoxedEvent]) -> Result<()> + Send + Sync + 'static
+    {
+        assert!(max_batch_size > 0, "max_batch_size should be positive!");
+        let uploader = Uploader {
             cache: Arc::new(Mutex::new(VecDeque::new())),
-            worker_manager: WorkerManager::new(
-                String::from("AsyncUploadConsumer#uploader"),
-                min(1, num_threads)
-            ),
-            flushing_process_count: Arc::new(Mutex::new(USizeHolder(0))),
             max_batch_size,
+            upload: Arc::new(upload),
+            sent: Arc::new(AtomicU64::new(0)),
+            in_flight: Arc::new(AtomicU64::new(0)),
+            aborted: Arc::new(AtomicBool::new(false)),
+        };
+        let mut worker_manager = WorkerManager::new(
+            String::from("AsyncUploadConsumer#uploader"),
+            min(1, num_threads)
+        );
+        let periodic = uploader.clone();
+        let periodic_flush = worker_manager.schedule_recurring(
+            move || periodic.upload_batch(), flush_interval_ms, flush_interval_ms, Priority::Low
+        );
+        AsyncUploadConsumer {
+            uploader,
+            worker_manager,
+            flushing_process_count: Arc::new(Mutex::new(USizeHolder(0))),
+            periodic_flush,
+            closed: false,
         }
     }
 
     fn add_to_cache(self: &mut Self, event: BoxedEvent) -> Result<()> {
         {
-            self.cache.lock().unwrap().push_back(event);
+            let mut cache = self.uploader.cache.lock().unwrap();
+            cache.push_back(event);
+            metrics::set_queue_depth(cache.len());
         }
 
         let fc = self.flushing_process_count.clone();
         if let Ok(mut count) = fc.lock() {
             if count.0 < self.worker_manager.size() {
                 count.0 += 1;
-                let _ = self.flush();
+                self.upload_cache();
             } else {
                 // Eliminates unnecessary duplicated flush() calls.
             }
         } else {
-            let _ = self.flush();
+            self.upload_cache();
         }
 
         Ok(())
     }
 
     fn upload_cache(self: &mut Self) {
-        let cache = self.cache.clone();
+        self.upload_cache_with_priority(Priority::Normal);
+    }
+
+    /// Uploads by the workers, the flush by host goes before the uploads triggered by adding events.
+    fn upload_cache_with_priority(self: &mut Self, priority: Priority) {
+        let uploader = self.uploader.clone();
         let count = self.flushing_process_count.clone();
-        let max_batch_size = self.max_batch_size;
 
-        self.worker_manager.schedule(move || {
+        self.worker_manager.schedule_with_priority(move || {
             if let Ok(mut count) = count.lock() {
-                count.0 = max(0, count.0 - 1);
+                count.0 = count.0.saturating_sub(1);
             }
-
-            let cache: Vec<BoxedEvent> = if let Ok(mut cache) = cache.lock() {
-                if cache.is_empty() {
-                    return;
-                }
-
-                let size = min(cache.len(), max_batch_size);
-                let mut tmp = Vec::with_capacity(size);
-                for _ in 0..size {
-                    if let Some(event) = cache.pop_front() {
-                        tmp.push(event)
-                    } else {
-                        break
-                    }
-                }
-                tmp
-            } else {
-                // nothing to sent
-                return;
-            };
-
-            let data_json = cache.iter()
-                .filter_map(|it| {
-                    if let Ok(json) = serde_json::to_string(it) {
-                        Some(json)
-                    } else {
-                        log_error!("Failed to jsonify the given event: {:?}", it);
-                        None
-                    }
-                }).collect::<Vec<String>>()
-                .join(",");
-            let data = format!("[{}]", data_json);
-            println!("data: ({}) {}", cache.len(), data);
-            // upload!
-            sleep(Duration::from_millis(100));
-        });
+            uploader.upload_batch();
+        }, 0, priority);
     }
 }
 
@@ -105,13 +179,59 @@ impl Consumer for AsyncUploadConsumer {
     }
 
     fn flush(self: &mut Self) -> Result<()> {
-        self.upload_cache();
+        self.upload_cache_with_priority(Priority::High);
         Ok(())
     }
 
     fn close(self: &mut Self) -> Result<()> {
-        self.worker_manager.shutdown();
-        Ok(())
+        self.close_timeout(Duration::MAX).map(|_| ())
+    }
+
+    /// Uploads the cached events till the deadline, the workers stuck in uploading are detached.
+    /// The detached workers upload nothing more, but the batches they are uploading may still be delivered,
+    /// which are counted in the remaining.
+    fn close_timeout(self: &mut Self, timeout: Duration) -> Result<CloseOutcome> {
+        if self.closed {
+            return Ok(CloseOutcome::default());
+        }
+        self.closed = true;
+        // The cached events (with the failed ones) are uploaded right now, rather than retried by the next period.
+        self.periodic_flush.cancel();
+        let sent_before = self.uploader.sent.load(Ordering::Relaxed);
+        let batches = self.uploader.cache.lock()
+            .map(|cache| cache.len().div_ceil(self.uploader.max_batch_size))
+            .unwrap_or(0);
+        for _ in 0..batches {
+            self.upload_cache_with_priority(Priority::High);
+        }
+        let in_time = self.worker_manager.shutdown_timeout(timeout);
+
+        let remaining: Vec<BoxedEvent> = match self.uploader.cache.lock() {
+            Ok(mut cache) => {
+                if !in_time {
+                    // Under the lock, so no batch is taken by the detached workers after draining.
+                    self.uploader.aborted.store(true, Ordering::Relaxed);
+                }
+                cache.drain(..).collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        metrics::set_queue_depth(0);
+        if error_hook::is_hooked() {
+            let e = DTError::RuntimeError(format!("Not uploaded in {timeout:?} on closing"));
+            for event in remaining.iter() {
+                error_hook::report(&e, Some(&error_hook::keep_event(event)), ErrorCategory::Write);
+            }
+        }
+
+        let outcome = CloseOutcome {
+            sent: self.uploader.sent.load(Ordering::Relaxed) - sent_before,
+            remaining: remaining.len() as u64 + self.uploader.in_flight.load(Ordering::Relaxed),
+        };
+        if outcome.remaining > 0 {
+            log_warning!(sent = outcome.sent, remaining = outcome.remaining; "{} events are not uploaded in {:?} on closing, dropped!", outcome.remaining, timeout);
+        }
+        Ok(outcome)
     }
 }
 
@@ -126,9 +246,13 @@ unsafe impl Sync for AsyncUploadConsumer {}
 
 #[cfg(test)]
 mod test {
+    use std::sync::{Arc, mpsc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
     use serde_json::{json, Value};
     use crate::consumer::async_upload::AsyncUploadConsumer;
-    use crate::consumer::Consumer;
+    use crate::consumer::{CloseOutcome, Consumer};
+    use crate::util::error::DTError;
 
     #[test]
     fn it_works() {
@@ -155,4 +279,72 @@ mod test {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn close_timeout() {
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let uploads = Arc::new(AtomicUsize::new(0));
+        let counter = uploads.clone();
+        let blocking = Mutex::new((entered_tx, release_rx));
+        let mut c = AsyncUploadConsumer::with_upload(1, 2, 60_000, move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            let channels = blocking.lock().unwrap();
+            channels.0.send(()).unwrap();
+            channels.1.recv().unwrap();
+            Ok(())
+        });
+        for i in 0..10 {
+            let j = json!({"#event_time": i, "#event_name": "test_event"});
+            if let Value::Object(m) = j {
+                c.uploader.cache.lock().unwrap().push_back(Box::new(m));
+            }
+        }
+        c.flush().unwrap();
+        entered_rx.recv().unwrap();
+
+        // The first batch is stuck in uploading
+        let outcome = c.close_timeout(Duration::ZERO).unwrap();
+        assert_eq!(outcome, CloseOutcome { sent: 0, remaining: 10 });
+        assert!(c.uploader.cache.lock().unwrap().is_empty());
+        assert_eq!(c.close_timeout(Duration::ZERO).unwrap(), CloseOutcome::default());
+
+        // The detached worker uploads nothing more once released, the upload is dropped as it exits
+        let sent = c.uploader.sent.clone();
+        drop(c);
+        release_tx.send(()).unwrap();
+        assert!(entered_rx.recv().is_err());
+        assert_eq!(uploads.load(Ordering::Relaxed), 1);
+        assert_eq!(sent.load(Ordering::Relaxed), 2);
+
+        let mut c = AsyncUploadConsumer::with_upload(1, 2, 60_000, |_| Ok(()));
+        for i in 0..10 {
+            c.uploader.cache.lock().unwrap().push_back(Box::new(json!({"#event_time": i}).as_object().unwrap().to_owned()));
+        }
+        assert_eq!(c.close_timeout(Duration::MAX).unwrap(), CloseOutcome { sent: 10, remaining: 0 });
+    }
+
+    #[test]
+    fn retry_by_periodic_flush() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (uploaded_tx, uploaded_rx) = mpsc::channel();
+        let counter = attempts.clone();
+        let uploaded_tx = Mutex::new(uploaded_tx);
+        let mut c = AsyncUploadConsumer::with_upload(1, 10, 10, move |batch| {
+            if counter.fetch_add(1, Ordering::Relaxed) == 0 {
+                return Err(DTError::NetworkError(String::from("timeout")));
+            }
+            uploaded_tx.lock().unwrap().send(batch.len()).unwrap();
+            Ok(())
+        });
+        for i in 0..3 {
+            c.uploader.cache.lock().unwrap().push_back(Box::new(json!({"#event_time": i}).as_object().unwrap().to_owned()));
+        }
+        c.flush().unwrap();
+        // Failed at first, then retried by the periodic flush.
+        assert_eq!(uploaded_rx.recv().unwrap(), 3);
+        assert!(attempts.load(Ordering::Relaxed) >= 2);
+        let outcome = c.close_timeout(Duration::MAX).unwrap();
+        assert_eq!(outcome.remaining, 0);
+    }
+}
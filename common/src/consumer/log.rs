@@ -3,16 +3,21 @@ use std::fs;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::path::Path;
+use std::time::Duration;
 
 use regex::Regex;
 use serde_json::{Map, Value};
 
 use crate::{log_error, log_info};
-use crate::consumer::Consumer;
-use crate::event::BoxedEvent;
+use crate::consumer::{CloseOutcome, Consumer};
+use crate::event::{BoxedEvent, Event};
 use crate::util::datetime::get_hour_since_epoch;
 use crate::util::error::macros::{host_error, runtime_error};
-use crate::util::error::Result;
+use crate::util::error::{DTError, Result};
+use crate::util::error_hook;
+use crate::util::error_hook::ErrorCategory;
+use crate::util::metrics;
+use crate::util::metrics::Counter;
 
 /**
  * Should be run in a single thread for current implementation.
@@ -126,6 +131,12 @@ impl LogConsumer {
         (revision, file_size_byte)
     }
 
+    /// Makes the next add rotate the file by time, as if the hour has passed.
+    #[cfg(feature = "bench")]
+    pub(crate) fn expire_file_time(self: &mut Self) {
+        self.file_time = self.file_time.saturating_sub(1);
+    }
+
     fn is_time_changed(self: &Self) -> bool {
         let crt_in_hour = get_hour_since_epoch();
         crt_in_hour > self.file_time
@@ -148,7 +159,9 @@ impl LogConsumer {
     ///     - 0: No need to refresh.
     ///     - 1: Refresh by time.
     ///     - 2: Refresh by size.
-    fn write_to_file(self: &mut Self, refresh_mode: u8) {
+    /// Returns the number of events written.
+    fn write_to_file(self: &mut Self, refresh_mode: u8) -> u64 {
+        let mut written = 0;
         // Once threading support needed, wrap this with a mutex!
         if !self.batch.is_empty() {
             #[cfg(feature = "benchmark")]
@@ -160,29 +173,45 @@ impl LogConsumer {
 
             let file_path = path.join(filename);
 
-            let mut file = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&file_path)
-                .unwrap();
+            let mut file = match OpenOptions::new().append(true).create(true).open(&file_path) {
+                Ok(file) => file,
+                Err(e) => {
+                    let e = DTError::InternalError(format!("Couldn't open file {file_path:?}: {e}"));
+                    log_error!(error_kind = e.kind(), batch_size = self.batch.len(), file_path = file_path.to_str().unwrap_or_default(); "{e}, {} events dropped!", self.batch.len());
+                    metrics::incr(Counter::EventsWriteFailed, self.batch.len() as u64);
+                    for s in self.batch.drain(..) {
+                        report_dropped(&e, &s);
+                    }
+                    return 0;
+                }
+            };
 
             let mut n = 0;
             while let Some(s) = self.batch.pop_front() {
                 if let Err(e) = writeln!(file, "{}", s) {
-                    log_error!("Couldn't write to file: {}", e);
+                    log_error!(error_kind = "internal", file_path = file_path.to_str().unwrap_or_default(); "Couldn't write to file: {}", e);
+                    report_dropped(&DTError::InternalError(format!("Couldn't write to file: {e}")), &s);
+                    metrics::incr(Counter::EventsWriteFailed, 1);
                 } else {
                     n += 1;
+                    metrics::incr(Counter::BytesWritten, s.len() as u64 + 1);
                 }
             }
+            metrics::incr(Counter::EventsWritten, n);
+            metrics::mark_flushed();
             file.sync_all().expect("File sync failed");
             self.crt_size_bytes = file.metadata().unwrap().len();
-            log_info!("Flushed {} events!", n);
+            log_info!(batch_size = n, file_path = file_path.to_str().unwrap_or_default(); "Flushed {} events!", n);
+            written = n;
             #[cfg(feature = "benchmark")]
-            (&crate::util::benchmark_tracer::BM_TRACER).add("Time used to flush", st.elapsed().as_micros());
+            (&crate::util::benchmark_tracer::BM_TRACER).add("file_write", st.elapsed().as_micros());
         }
 
         // Once threading support needed, wrap this with a mutex!
         // Updating naming factors.
+        if refresh_mode != 0 {
+            metrics::incr(Counter::FilesRotated, 1);
+        }
         match refresh_mode {
             1 => {
                 self.file_time = get_hour_since_epoch();
@@ -195,6 +224,15 @@ impl LogConsumer {
             },
             _ => {},
         }
+        written
+    }
+}
+
+/// Passes the dropped event to the error hook, which is parsed back only if the hook is set.
+fn report_dropped(e: &DTError, line: &str) {
+    if error_hook::is_hooked() {
+        let event = serde_json::from_str::<Event>(line).ok().map(|it| error_hook::keep_event(&it));
+        error_hook::report(e, event.as_ref(), ErrorCategory::Write);
     }
 }
 
@@ -204,7 +242,12 @@ impl Consumer for LogConsumer {
             self.write_to_file(1);
         }
 
-        if let Ok(json) = serde_json::to_string(&event) {
+        #[cfg(feature = "benchmark")]
+        let st = std::time::Instant::now();
+        let json = serde_json::to_string(&event);
+        #[cfg(feature = "benchmark")]
+        (&crate::util::benchmark_tracer::BM_TRACER).add("serialization", st.elapsed().as_micros());
+        if let Ok(json) = json {
             let json_size = json.len() as u64;
             if let Some(max_file_size_bytes) = self.max_file_size_bytes {
                 if self.crt_size_bytes + json_size > max_file_size_bytes {
@@ -220,6 +263,7 @@ impl Consumer for LogConsumer {
         if self.is_need_flush() {
             self.write_to_file(0);
         }
+        metrics::set_queue_depth(self.batch.len());
         Ok(())
     }
 
@@ -227,14 +271,24 @@ impl Consumer for LogConsumer {
         while !self.batch.is_empty() {
             self.write_to_file(0);
         }
+        metrics::set_queue_depth(0);
         Ok(())
     }
 
     fn close(self: &mut Self) -> Result<()> {
+        self.close_timeout(Duration::MAX).map(|_| ())
+    }
+
+    /// Writing to the file is never given up, so the timeout is not applied. The events failed to be written are
+    /// counted as remaining.
+    fn close_timeout(self: &mut Self, _timeout: Duration) -> Result<CloseOutcome> {
+        let pending = self.batch.len() as u64;
+        let mut sent = 0;
         while !self.batch.is_empty() {
-            self.write_to_file(0);
+            sent += self.write_to_file(0);
         }
-        Ok(())
+        metrics::set_queue_depth(0);
+        Ok(CloseOutcome { sent, remaining: pending - sent })
     }
 }
 
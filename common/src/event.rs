@@ -1,8 +1,13 @@
 use crate::util::error::Result;
 
-mod data_verification;
+pub(crate) mod data_verification;
 pub(crate) mod processing;
 pub(crate) mod common_properties;
+pub(crate) mod deduplication;
+pub(crate) mod sampling;
+pub mod user_context;
+pub mod builder;
+pub mod tracking;
 
 pub type Event = serde_json::Map<String, serde_json::Value>;
 pub type BoxedEvent = Box<Event>;
@@ -12,3 +17,36 @@ pub(crate) fn init() -> Result<()> {
     data_verification::init()?;
     Ok(())
 }
+
+/// Settings of the event processor in the init config, validated (with the files loaded) before any of them is
+/// applied, so applying them never fails.
+pub(crate) struct EventConfig {
+    props_limits: data_verification::PropsLimits,
+    event_time_rules: data_verification::EventTimeRules,
+    processing: processing::ProcessingConfig,
+    dedup: deduplication::DedupConfig,
+    sampling: sampling::SamplingConfig,
+    common_props: common_properties::CommonPropsConfig,
+}
+
+impl EventConfig {
+    pub(crate) fn apply(self) {
+        data_verification::set_props_limits(self.props_limits);
+        data_verification::set_event_time_rules(self.event_time_rules);
+        self.processing.apply();
+        self.dedup.apply();
+        self.sampling.apply();
+        self.common_props.apply();
+    }
+}
+
+pub(crate) fn parse_config(config: &serde_json::Map<String, serde_json::Value>) -> Result<EventConfig> {
+    Ok(EventConfig {
+        props_limits: data_verification::parse_props_limits(config)?,
+        event_time_rules: data_verification::parse_event_time_rules(config)?,
+        processing: processing::parse_config(config)?,
+        dedup: deduplication::parse_config(config)?,
+        sampling: sampling::parse_config(config)?,
+        common_props: common_properties::parse_config(config)?,
+    })
+}
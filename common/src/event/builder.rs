@@ -0,0 +1,406 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+
+use crate::event::Event;
+use crate::util::error::Result;
+
+/// "#sdk_type" of events built by [EventBuilder], unless it's given by [EventBuilder::sdk_type].
+pub const SDK_TYPE: &str = "dt_server_sdk_rust";
+
+/// Builds the [Event] with typed meta properties, instead of assembling the map with magic keys.
+/// Created by [EventBuilderExt], e.g. `Event::track("login").dt_id("xxx").property("level", 3).add()`.
+#[derive(Debug, Clone)]
+pub struct EventBuilder {
+    event: Event,
+    properties: Map<String, Value>,
+}
+
+/// Entries of [EventBuilder] on [Event].
+pub trait EventBuilderExt {
+    fn track(event_name: impl Into<String>) -> EventBuilder;
+    /// Track event of the preset event, with the properties filled.
+    fn preset<P: PresetEvent>(preset: P) -> EventBuilder;
+    fn user_set() -> EventBuilder;
+    fn user_set_once() -> EventBuilder;
+    fn user_add() -> EventBuilder;
+    fn user_unset() -> EventBuilder;
+    fn user_delete() -> EventBuilder;
+    fn user_append() -> EventBuilder;
+    fn user_uniq_append() -> EventBuilder;
+}
+
+impl EventBuilderExt for Event {
+    fn track(event_name: impl Into<String>) -> EventBuilder {
+        EventBuilder::new(event_name.into(), "track")
+    }
+
+    fn preset<P: PresetEvent>(preset: P) -> EventBuilder {
+        let event_name = preset.event_name();
+        EventBuilder::new(String::from(event_name), "track").properties(preset.into_properties())
+    }
+
+    fn user_set() -> EventBuilder {
+        EventBuilder::new(String::from("#user_set"), "user")
+    }
+
+    fn user_set_once() -> EventBuilder {
+        EventBuilder::new(String::from("#user_set_once"), "user")
+    }
+
+    fn user_add() -> EventBuilder {
+        EventBuilder::new(String::from("#user_add"), "user")
+    }
+
+    fn user_unset() -> EventBuilder {
+        EventBuilder::new(String::from("#user_unset"), "user")
+    }
+
+    fn user_delete() -> EventBuilder {
+        EventBuilder::new(String::from("#user_delete"), "user")
+    }
+
+    fn user_append() -> EventBuilder {
+        EventBuilder::new(String::from("#user_append"), "user")
+    }
+
+    fn user_uniq_append() -> EventBuilder {
+        EventBuilder::new(String::from("#user_uniq_append"), "user")
+    }
+}
+
+impl EventBuilder {
+    fn new(event_name: String, event_type: &str) -> Self {
+        let mut event = Map::with_capacity(8);
+        event.insert(String::from("#event_name"), Value::from(event_name));
+        event.insert(String::from("#event_type"), Value::from(event_type));
+        let mut properties = Map::new();
+        properties.insert(String::from("#sdk_type"), Value::from(SDK_TYPE));
+        EventBuilder { event, properties }
+    }
+
+    fn meta(mut self, key: &str, value: Value) -> Self {
+        self.event.insert(String::from(key), value);
+        self
+    }
+
+    pub fn dt_id(self, dt_id: impl Into<String>) -> Self {
+        self.meta("#dt_id", Value::from(dt_id.into()))
+    }
+
+    pub fn acid(self, acid: impl Into<String>) -> Self {
+        self.meta("#acid", Value::from(acid.into()))
+    }
+
+    pub fn app_id(self, app_id: impl Into<String>) -> Self {
+        self.meta("#app_id", Value::from(app_id.into()))
+    }
+
+    pub fn bundle_id(self, bundle_id: impl Into<String>) -> Self {
+        self.meta("#bundle_id", Value::from(bundle_id.into()))
+    }
+
+    /// Defaults to the time of adding the event.
+    pub fn event_time(self, time: SystemTime) -> Self {
+        let millis = time.duration_since(UNIX_EPOCH).map(|it| it.as_millis() as u64).unwrap_or_default();
+        self.event_time_millis(millis)
+    }
+
+    pub fn event_time_millis(self, millis: u64) -> Self {
+        self.meta("#event_time", Value::from(millis))
+    }
+
+    /// Defaults to a random UUID, given one could be used for deduplication.
+    pub fn event_syn(self, event_syn: impl Into<String>) -> Self {
+        self.meta("#event_syn", Value::from(event_syn.into()))
+    }
+
+    /// Defaults to [SDK_TYPE].
+    pub fn sdk_type(self, sdk_type: impl Into<String>) -> Self {
+        self.property("#sdk_type", sdk_type.into())
+    }
+
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    /// Extends the properties, overriding the ones with the same key.
+    pub fn properties(mut self, properties: Map<String, Value>) -> Self {
+        self.properties.extend(properties);
+        self
+    }
+
+    pub fn build(mut self) -> Event {
+        self.event.insert(String::from("properties"), Value::Object(self.properties));
+        self.event
+    }
+
+    pub fn add(self) -> Result<()> {
+        crate::add(self.build())
+    }
+}
+
+/// Preset event with typed properties, serialised to the "properties" of the event.
+pub trait PresetEvent {
+    fn event_name(&self) -> &'static str;
+    fn into_properties(self) -> Map<String, Value>;
+}
+
+struct PropsWriter(Map<String, Value>);
+
+impl PropsWriter {
+    fn new() -> Self {
+        PropsWriter(Map::new())
+    }
+
+    fn put(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.0.insert(String::from(key), value.into());
+        self
+    }
+
+    fn put_opt(self, key: &str, value: Option<impl Into<Value>>) -> Self {
+        match value {
+            Some(value) => self.put(key, value),
+            None => self,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdAction {
+    LoadBegin,
+    LoadEnd { result: bool, duration: f64, error_code: Option<i64>, error_message: Option<String> },
+    ToShow,
+    Show,
+    ShowFailed { error_code: i64, error_message: String },
+    Close,
+    Click,
+    Rewarded,
+    Conversion { source: String },
+    Paid { value: f64, currency: String, precision: Option<String>, country_code: Option<String> },
+}
+
+/// The "#ad_*" events, "entrance" and "location" are ignored for [AdAction::LoadBegin] and [AdAction::LoadEnd].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdEvent {
+    pub action: AdAction,
+    pub ad_seq: String,
+    pub ad_id: String,
+    pub ad_type_code: i64,
+    pub ad_platform_code: i64,
+    pub ad_mediation_code: Option<i64>,
+    pub ad_mediation_id: Option<String>,
+    pub entrance: Option<String>,
+    pub location: Option<String>,
+}
+
+impl PresetEvent for AdEvent {
+    fn event_name(&self) -> &'static str {
+        match self.action {
+            AdAction::LoadBegin => "#ad_load_begin",
+            AdAction::LoadEnd { .. } => "#ad_load_end",
+            AdAction::ToShow => "#ad_to_show",
+            AdAction::Show => "#ad_show",
+            AdAction::ShowFailed { .. } => "#ad_show_failed",
+            AdAction::Close => "#ad_close",
+            AdAction::Click => "#ad_click",
+            AdAction::Rewarded => "#ad_rewarded",
+            AdAction::Conversion { .. } => "#ad_conversion",
+            AdAction::Paid { .. } => "#ad_paid",
+        }
+    }
+
+    fn into_properties(self) -> Map<String, Value> {
+        let mut writer = PropsWriter::new()
+            .put("#ad_seq", self.ad_seq)
+            .put("#ad_id", self.ad_id)
+            .put("#ad_type_code", self.ad_type_code)
+            .put("#ad_platform_code", self.ad_platform_code)
+            .put_opt("#ad_mediation_code", self.ad_mediation_code)
+            .put_opt("#ad_mediation_id", self.ad_mediation_id);
+        if !matches!(self.action, AdAction::LoadBegin | AdAction::LoadEnd { .. }) {
+            writer = writer.put_opt("#ad_entrance", self.entrance).put_opt("#ad_location", self.location);
+        }
+        let writer = match self.action {
+            AdAction::LoadEnd { result, duration, error_code, error_message } => writer
+                .put("#load_result", result)
+                .put("#load_duration", duration)
+                .put_opt("#error_code", error_code)
+                .put_opt("#error_message", error_message),
+            AdAction::ShowFailed { error_code, error_message } => writer
+                .put("#error_code", error_code)
+                .put("#error_message", error_message),
+            AdAction::Conversion { source } => writer.put("#ad_conversion_source", source),
+            AdAction::Paid { value, currency, precision, country_code } => writer
+                .put("#ad_value", value)
+                .put("#ad_currency", currency)
+                .put_opt("#ad_precision", precision)
+                .put_opt("#ad_country_code", country_code),
+            _ => writer,
+        };
+        writer.0
+    }
+}
+
+/// The "#iap_purchase_success" event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IapPurchaseSuccess {
+    pub order: String,
+    pub sku: String,
+    pub price: f64,
+    pub currency: String,
+}
+
+impl PresetEvent for IapPurchaseSuccess {
+    fn event_name(&self) -> &'static str {
+        "#iap_purchase_success"
+    }
+
+    fn into_properties(self) -> Map<String, Value> {
+        PropsWriter::new()
+            .put("#iap_order", self.order)
+            .put("#iap_sku", self.sku)
+            .put("#iap_price", self.price)
+            .put("#iap_currency", self.currency)
+            .0
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IasAction {
+    Success,
+    Notify,
+}
+
+/// The "#ias_subscribe_success" and "#ias_subscribe_notify" events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IasSubscribe {
+    pub action: IasAction,
+    pub original_order: String,
+    pub order: String,
+    pub sku: String,
+    pub price: f64,
+    pub currency: String,
+}
+
+impl PresetEvent for IasSubscribe {
+    fn event_name(&self) -> &'static str {
+        match self.action {
+            IasAction::Success => "#ias_subscribe_success",
+            IasAction::Notify => "#ias_subscribe_notify",
+        }
+    }
+
+    fn into_properties(self) -> Map<String, Value> {
+        PropsWriter::new()
+            .put("#ias_original_order", self.original_order)
+            .put("#ias_order", self.order)
+            .put("#ias_sku", self.sku)
+            .put("#ias_price", self.price)
+            .put("#ias_currency", self.currency)
+            .0
+    }
+}
+
+/// The "#session_start" event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionStart {
+    pub is_first_time: Option<bool>,
+    pub resume_from_background: Option<bool>,
+    pub start_reason: Option<String>,
+    pub background_duration: Option<i64>,
+}
+
+impl PresetEvent for SessionStart {
+    fn event_name(&self) -> &'static str {
+        "#session_start"
+    }
+
+    fn into_properties(self) -> Map<String, Value> {
+        PropsWriter::new()
+            .put_opt("#is_first_time", self.is_first_time)
+            .put_opt("#resume_from_background", self.resume_from_background)
+            .put_opt("#start_reason", self.start_reason)
+            .put_opt("#background_duration", self.background_duration)
+            .0
+    }
+}
+
+/// The "#session_end" event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionEnd {
+    pub session_duration: i64,
+}
+
+impl PresetEvent for SessionEnd {
+    fn event_name(&self) -> &'static str {
+        "#session_end"
+    }
+
+    fn into_properties(self) -> Map<String, Value> {
+        PropsWriter::new().put("#session_duration", self.session_duration).0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use super::{AdAction, AdEvent, EventBuilderExt, IapPurchaseSuccess, IasAction, IasSubscribe, SessionEnd, SessionStart, SDK_TYPE};
+    use crate::event::Event;
+    use crate::event::processing::process_event;
+
+    #[test]
+    fn build() {
+        let event = Event::track("login")
+            .dt_id("dt1").acid("ac1").app_id("app").bundle_id("com.x")
+            .event_time_millis(1700000000000).event_syn("syn1")
+            .property("level", 3)
+            .build();
+        assert_eq!(Value::Object(event), json!({
+            "#event_name": "login", "#event_type": "track",
+            "#dt_id": "dt1", "#acid": "ac1", "#app_id": "app", "#bundle_id": "com.x",
+            "#event_time": 1700000000000u64, "#event_syn": "syn1",
+            "properties": {"#sdk_type": SDK_TYPE, "level": 3}
+        }));
+
+        let event = Event::user_add().dt_id("dt1").property("coins", 10).build();
+        assert_eq!(event["#event_name"], json!("#user_add"));
+        assert_eq!(event["#event_type"], json!("user"));
+    }
+
+    #[test]
+    fn preset_events_are_valid() {
+        let ad = AdEvent {
+            action: AdAction::LoadEnd { result: false, duration: 12.5, error_code: Some(3), error_message: None },
+            ad_seq: String::from("seq"), ad_id: String::from("ad"), ad_type_code: 1, ad_platform_code: 2,
+            ad_mediation_code: None, ad_mediation_id: None,
+            entrance: Some(String::from("main")), location: None,
+        };
+        let iap = IapPurchaseSuccess {
+            order: String::from("o1"), sku: String::from("sku"), price: 0.99, currency: String::from("USD"),
+        };
+        let ias = IasSubscribe {
+            action: IasAction::Notify, original_order: String::from("o0"), order: String::from("o1"),
+            sku: String::from("sku"), price: 4.99, currency: String::from("USD"),
+        };
+        let session_start = SessionStart {
+            is_first_time: Some(false), resume_from_background: Some(true),
+            start_reason: Some(String::from("push")), background_duration: Some(30),
+        };
+        let builders = [
+            Event::preset(ad),
+            Event::preset(iap),
+            Event::preset(ias),
+            Event::preset(session_start),
+            Event::preset(SessionStart::default()),
+            Event::preset(SessionEnd { session_duration: 60 }),
+        ];
+        for builder in builders {
+            let event = builder.dt_id("dt1").app_id("app").bundle_id("com.x").build();
+            assert!(!event["properties"].as_object().unwrap().contains_key("#ad_entrance"));
+            process_event(event, None, None).expect("Preset event should be valid");
+        }
+    }
+}
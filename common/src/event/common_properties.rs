@@ -1,58 +1,384 @@
 use std::cell::Cell;
-use std::sync::Mutex;
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use serde_json::{Map, Value};
 
+use crate::event::common_properties::storage::PropsStorage;
+use crate::event::data_verification::verify_common_properties;
 use crate::event::Event;
-use crate::util::error::macros::internal_error;
+use crate::{log_error, log_info, log_warning};
+use crate::util::error::macros::{error_with, host_error, internal_error};
 use crate::util::error::Result;
 
+mod storage;
+
 pub(crate) type Props = Map<String, Value>;
+/// Provides the dynamic common properties, invoked for each event.
+pub type DynamicPropsProvider = dyn Fn() -> Result<Props> + Send + Sync;
 
 static STATIC_COMMON_PROPS: Mutex<Cell<Option<Props>>> = Mutex::new(Cell::new(None));
+static DYNAMIC_COMMON_PROPS: RwLock<Option<Arc<DynamicPropsProvider>>> = RwLock::new(None);
+static PROPS_STORAGE: Mutex<Option<Box<dyn PropsStorage>>> = Mutex::new(None);
 
-// Currently not implemented with persist storage yet.
-pub(crate) fn set_static_comm_props(props: Props) -> Result<()> {
-    let Ok(scp) = STATIC_COMMON_PROPS.lock() else {
-        return internal_error!("Failed to get lock for static_common_properties!");
+// Time budget of each invocation of the provider, it will be removed once exceeded too many times in a row.
+static DYNAMIC_PROPS_BUDGET_MS: AtomicU64 = AtomicU64::new(10);
+static DYNAMIC_PROPS_OVERRUNS: AtomicU32 = AtomicU32::new(0);
+const MAX_DYNAMIC_PROPS_OVERRUNS: u32 = 3;
+// Whether the provider is running. The invocations are serialized (e.g. the C callback keeps the result valid until
+// the next invocation), the others wait no longer than the budget.
+static DYNAMIC_PROPS_CALLING: Mutex<bool> = Mutex::new(false);
+static DYNAMIC_PROPS_CALLED: Condvar = Condvar::new();
+
+/// Settings of common properties in the init config, applied only if the init succeeds.
+pub(crate) struct CommonPropsConfig {
+    budget_ms: Option<u64>,
+    // With the persisted static common properties loaded.
+    storage: Option<(Box<dyn PropsStorage>, Props)>,
+}
+
+impl CommonPropsConfig {
+    pub(crate) fn apply(self) {
+        if let Some(budget_ms) = self.budget_ms {
+            DYNAMIC_PROPS_BUDGET_MS.store(budget_ms, Ordering::Relaxed);
+        }
+        init_storage(self.storage);
+    }
+}
+
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<CommonPropsConfig> {
+    let budget_ms = match config.get("dynamic_props_budget_ms") {
+        None => None,
+        Some(Value::Number(n)) if n.as_u64().is_some_and(|n| n > 0) => n.as_u64(),
+        Some(_) => return host_error!("\"dynamic_props_budget_ms\" should be a positive integer!"),
+    };
+    let storage = match storage::from_config(config)? {
+        None => None,
+        Some(mut storage) => {
+            let props = storage.load()?;
+            Some((storage, props))
+        }
+    };
+    Ok(CommonPropsConfig { budget_ms, storage })
+}
+
+/// Takes the persisted static common properties, the ones set before init take precedence.
+/// Failing to save the merged ones is not fatal, they are saved again on the next change.
+fn init_storage(storage: Option<(Box<dyn PropsStorage>, Props)>) {
+    let mut ps = PROPS_STORAGE.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some((storage, mut props)) = storage else {
+        *ps = None;
+        return;
     };
+    let storage = ps.insert(storage);
+    log_info!("Loaded {} static common properties from storage.", props.len());
+
+    let mut scp = STATIC_COMMON_PROPS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(crt) = scp.get_mut().take() {
+        props.extend(crt);
+        if let Err(e) = storage.save(&props) {
+            log_warning!("Failed to save the static common properties set before init, {e}");
+        }
+    }
     scp.replace(Some(props));
-    // store in local ...
+}
+
+/// The provider will be invoked by the thread that adding the event, before the internal lock is held.
+/// It should be fast and must not call the APIs of SDK. While it's running, the events added by other threads wait
+/// no longer than the budget, then they are tracked without dynamic common properties.
+pub(crate) fn set_dynamic_comm_props(provider: Arc<DynamicPropsProvider>) -> Result<()> {
+    let Ok(mut dcp) = DYNAMIC_COMMON_PROPS.write() else {
+        return internal_error!("Failed to get lock for dynamic_common_properties!");
+    };
+    dcp.replace(provider);
+    DYNAMIC_PROPS_OVERRUNS.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+pub(crate) fn clear_dynamic_comm_props() -> Result<()> {
+    let Ok(mut dcp) = DYNAMIC_COMMON_PROPS.write() else {
+        return internal_error!("Failed to get lock for dynamic_common_properties!");
+    };
+    dcp.take();
     Ok(())
 }
 
+// Marks the provider running, until dropped.
+struct DynamicPropsCall;
+
+impl DynamicPropsCall {
+    fn acquire(timeout: Duration) -> Option<Self> {
+        let calling = DYNAMIC_PROPS_CALLING.lock().ok()?;
+        let (mut calling, _) = DYNAMIC_PROPS_CALLED.wait_timeout_while(calling, timeout, |calling| *calling).ok()?;
+        if *calling {
+            return None;
+        }
+        *calling = true;
+        Some(DynamicPropsCall)
+    }
+}
+
+impl Drop for DynamicPropsCall {
+    fn drop(&mut self) {
+        if let Ok(mut calling) = DYNAMIC_PROPS_CALLING.lock() {
+            *calling = false;
+        }
+        DYNAMIC_PROPS_CALLED.notify_one();
+    }
+}
+
+/// Failure of the provider will not fail the event, it will be tracked without dynamic common properties.
+/// To be invoked before the internal lock is held, see [set_dynamic_comm_props].
+pub(crate) fn get_dynamic_comm_props() -> Option<Props> {
+    let provider = {
+        let Ok(dcp) = DYNAMIC_COMMON_PROPS.read() else {
+            log_error!("Failed to get lock for dynamic_common_properties!");
+            return None;
+        };
+        dcp.clone()?
+    };
+
+    let budget = DYNAMIC_PROPS_BUDGET_MS.load(Ordering::Relaxed);
+    let Some(call) = DynamicPropsCall::acquire(Duration::from_millis(budget)) else {
+        log_warning!("Dynamic common properties are still running after the budget ({budget}ms), skipped!");
+        count_overrun();
+        return None;
+    };
+    let st = Instant::now();
+    let result = provider();
+    let elapsed = st.elapsed().as_millis() as u64;
+    drop(call);
+
+    if elapsed > budget {
+        log_warning!("Dynamic common properties took {elapsed}ms, exceeds the budget ({budget}ms)!");
+        count_overrun();
+    } else {
+        DYNAMIC_PROPS_OVERRUNS.store(0, Ordering::Relaxed);
+    }
+
+    match result.and_then(|props| verify_common_properties(&props).map(|_| props)) {
+        Ok(props) => Some(props),
+        Err(e) => {
+            log_error!("Failed to get dynamic common properties, reason: {e}");
+            None
+        }
+    }
+}
+
+fn count_overrun() {
+    let overruns = DYNAMIC_PROPS_OVERRUNS.fetch_add(1, Ordering::Relaxed) + 1;
+    if overruns >= MAX_DYNAMIC_PROPS_OVERRUNS {
+        log_error!("Dynamic common properties exceeded the budget {overruns} times in a row, removed!");
+        let _ = clear_dynamic_comm_props();
+    }
+}
+
+pub(crate) fn set_static_comm_props(props: Props) -> Result<()> {
+    verify_common_properties(&props)?;
+    update_static_comm_props(|scp| *scp = Some(props))
+}
+
 pub(crate) fn clear_static_comm_props() -> Result<()> {
-    let Ok(scp) = STATIC_COMMON_PROPS.lock() else {
+    update_static_comm_props(|scp| *scp = None)
+}
+
+/// Merges into the existing ones, the given properties override the ones with the same key.
+pub(crate) fn merge_static_comm_props(props: Props) -> Result<()> {
+    verify_common_properties(&props)?;
+    update_static_comm_props(|scp| {
+        scp.get_or_insert_with(Map::new).extend(props);
+    })
+}
+
+pub(crate) fn set_static_comm_prop(key: String, value: Value) -> Result<()> {
+    let mut props = Map::with_capacity(1);
+    props.insert(key, value);
+    merge_static_comm_props(props)
+}
+
+pub(crate) fn unset_static_comm_prop(key: &str) -> Result<()> {
+    update_static_comm_props(|scp| {
+        if let Some(scp) = scp.as_mut() {
+            scp.remove(key);
+        }
+    })
+}
+
+/// Returns a snapshot of the static common properties, empty if none.
+pub(crate) fn get_static_comm_props() -> Result<Props> {
+    let Ok(mut scp) = STATIC_COMMON_PROPS.lock() else {
+        return internal_error!("Failed to get lock for static_common_properties!");
+    };
+    Ok(scp.get_mut().clone().unwrap_or_default())
+}
+
+/// Updates in memory and then persists to storage (if any).
+fn update_static_comm_props<F>(f: F) -> Result<()> where F: FnOnce(&mut Option<Props>) {
+    let Ok(mut scp) = STATIC_COMMON_PROPS.lock() else {
         return internal_error!("Failed to get lock for static_common_properties!");
     };
-    scp.replace(None);
-    // clear local stored.
+    f(scp.get_mut());
+
+    let Ok(mut ps) = PROPS_STORAGE.lock() else {
+        return internal_error!("Failed to get lock for storage of static_common_properties!");
+    };
+    if let Some(storage) = ps.as_mut() {
+        let empty = Map::new();
+        if let Err(e) = storage.save(scp.get_mut().as_ref().unwrap_or(&empty)) {
+            return error_with!(e, "Static common properties are updated, but failed to persist!");
+        }
+    }
     Ok(())
 }
 
-pub(crate) fn fulfill_by_comm_props(event: &mut Event) -> Result<()> {
+/// Common properties are applied to each event, with precedence:
+///     1. "#sdk_*" injected by SDK, which are not allowed in common properties.
+///     2. properties of the event.
+///     3. user-scoped common properties, see [crate::event::user_context::UserContext].
+///     4. dynamic common properties.
+///     5. static common properties.
+/// The dynamic common properties are given by [get_dynamic_comm_props].
+pub(crate) fn fulfill_by_comm_props(event: &mut Event, user_props: Option<&Props>, dynamic_props: Option<&Props>) -> Result<()> {
+    if let Some(user_props) = user_props {
+        fulfill_absent(event, user_props);
+    }
+    if let Some(dcp) = dynamic_props {
+        fulfill_absent(event, dcp);
+    }
+
     let Ok(mut scp) = STATIC_COMMON_PROPS.lock() else {
         return internal_error!("Failed to get lock for static_common_properties!");
     };
-    let key_properties = String::from("properties");
     if let Some(scp) = scp.get_mut() {
-        if let Some(Value::Object(properties)) = event.get_mut(&key_properties) {
-            // insert to existed "properties"
-            for (k, v) in scp {
-                if !properties.contains_key(k) {
-                    properties.insert(k.clone(), v.clone());
-                }
-            }
-        } else {
-            // create new if "properties" is not found
-            let mut properties = Map::with_capacity(scp.len());
-            for (k, v) in scp {
-                if !properties.contains_key(k) {
-                    properties.insert(k.clone(), v.clone());
-                }
+        fulfill_absent(event, scp);
+    }
+    Ok(())
+}
+
+fn fulfill_absent(event: &mut Event, props: &Props) {
+    let key_properties = String::from("properties");
+    if let Some(Value::Object(properties)) = event.get_mut(&key_properties) {
+        // insert to existed "properties"
+        for (k, v) in props {
+            if !properties.contains_key(k) {
+                properties.insert(k.clone(), v.clone());
             }
-            event.insert(key_properties, Value::Object(properties));
         }
+    } else {
+        // create new if "properties" is not found
+        event.insert(key_properties, Value::Object(props.clone()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, mpsc, Mutex};
+    use serde_json::json;
+    use super::{DYNAMIC_COMMON_PROPS, clear_dynamic_comm_props, clear_static_comm_props, fulfill_absent, fulfill_by_comm_props, get_dynamic_comm_props, get_static_comm_props, merge_static_comm_props, set_dynamic_comm_props, set_static_comm_prop, set_static_comm_props, unset_static_comm_prop};
+    use crate::event::data_verification::verify_common_properties;
+    use crate::util::error::DTError;
+
+    #[test]
+    fn precedence() {
+        let mut event = json!({"properties": {"a": 1}}).as_object().unwrap().to_owned();
+        fulfill_absent(&mut event, json!({"a": 2, "b": 2}).as_object().unwrap());
+        fulfill_absent(&mut event, json!({"a": 3, "b": 3, "c": 3}).as_object().unwrap());
+        assert_eq!(event["properties"], json!({"a": 1, "b": 2, "c": 3}));
+
+        let mut event = json!({}).as_object().unwrap().to_owned();
+        fulfill_absent(&mut event, json!({"a": 1}).as_object().unwrap());
+        assert_eq!(event["properties"], json!({"a": 1}));
+
+        // Applied to user events as well.
+        let mut event = json!({"#event_type": "user", "#event_name": "#user_set", "properties": {"a": 1}}).as_object().unwrap().to_owned();
+        let user_props = json!({"a": 2, "b": 2}).as_object().unwrap().to_owned();
+        let dynamic_props = json!({"b": 3, "c": 3}).as_object().unwrap().to_owned();
+        fulfill_by_comm_props(&mut event, Some(&user_props), Some(&dynamic_props)).expect("Failed to fulfill");
+        assert_eq!(event["properties"], json!({"a": 1, "b": 2, "c": 3}));
+    }
+
+    #[test]
+    fn verified_at_set_time() {
+        assert!(verify_common_properties(json!({
+            "channel": "store", "#zone_offset": 8, "#os": "linux", "#is_foreground": true
+        }).as_object().unwrap()).is_ok());
+
+        for invalid in [
+            json!({"1channel": "store"}),           // invalid name
+            json!({"#zone_offset": "8"}),           // invalid type of preset property
+            json!({"#ad_seq": "1"}),                // preset property out of scope
+            json!({"#sdk_type": "my_sdk"}),         // reserved by SDK
+            json!({"#sdk_version_name": "1.0"}),
+        ] {
+            let props = invalid.as_object().unwrap().to_owned();
+            assert!(matches!(set_static_comm_props(props.clone()), Err(DTError::VerifyError(_))), "{invalid}");
+            assert!(matches!(merge_static_comm_props(props), Err(DTError::VerifyError(_))), "{invalid}");
+        }
+        assert!(matches!(set_static_comm_prop(String::from("#sdk_type"), json!("x")), Err(DTError::VerifyError(_))));
+    }
+
+    #[test]
+    fn incremental_static_props() {
+        merge_static_comm_props(json!({"scp_a": 1, "scp_b": 1}).as_object().unwrap().to_owned())
+            .expect("Failed to merge");
+        merge_static_comm_props(json!({"scp_b": 2, "scp_c": 2}).as_object().unwrap().to_owned())
+            .expect("Failed to merge");
+        unset_static_comm_prop("scp_a").expect("Failed to unset");
+        assert_eq!(get_static_comm_props().unwrap(), json!({"scp_b": 2, "scp_c": 2}).as_object().unwrap().to_owned());
+
+        let mut event = json!({"#event_type": "user", "#event_name": "#user_add", "properties": {"scp_b": 1}}).as_object().unwrap().to_owned();
+        fulfill_by_comm_props(&mut event, None, None).expect("Failed to fulfill");
+        assert_eq!(event["properties"], json!({"scp_b": 1, "scp_c": 2}));
+
+        clear_static_comm_props().expect("Failed to clear");
+        assert!(get_static_comm_props().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dynamic_props() {
+        set_dynamic_comm_props(Arc::new(|| Ok(json!({"region": "eu"}).as_object().unwrap().to_owned())))
+            .expect("Failed to set");
+        assert_eq!(get_dynamic_comm_props(), Some(json!({"region": "eu"}).as_object().unwrap().to_owned()));
+
+        // Failed provider is isolated.
+        set_dynamic_comm_props(Arc::new(|| Err(DTError::HostError(String::from("boom")))))
+            .expect("Failed to set");
+        assert_eq!(get_dynamic_comm_props(), None);
+
+        // So as the invalid properties.
+        set_dynamic_comm_props(Arc::new(|| Ok(json!({"#sdk_type": "x"}).as_object().unwrap().to_owned())))
+            .expect("Failed to set");
+        assert_eq!(get_dynamic_comm_props(), None);
+
+        // Slow provider is removed after exceeding the budget several times.
+        set_dynamic_comm_props(Arc::new(|| {
+            std::thread::sleep(std::time::Duration::from_millis(30));
+            Ok(serde_json::Map::new())
+        })).expect("Failed to set");
+        for _ in 0..3 {
+            assert!(get_dynamic_comm_props().is_some());
+        }
+        assert_eq!(get_dynamic_comm_props(), None);
+
+        // Hung provider doesn't block the others, which are skipped after the budget.
+        let (entered_tx, entered_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+        set_dynamic_comm_props(Arc::new(move || {
+            entered_tx.send(()).unwrap();
+            let _ = release_rx.lock().unwrap().recv();
+            Ok(serde_json::Map::new())
+        })).expect("Failed to set");
+        let hung = std::thread::spawn(get_dynamic_comm_props);
+        entered_rx.recv().unwrap();
+        for _ in 0..3 {
+            assert_eq!(get_dynamic_comm_props(), None);
+        }
+        assert!(DYNAMIC_COMMON_PROPS.read().unwrap().is_none(), "Should be removed");
+        release_tx.send(()).unwrap();
+        assert!(hung.join().unwrap().is_some());
+
+        clear_dynamic_comm_props().expect("Failed to clear");
     }
-    Ok(())
 }
@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::event::common_properties::Props;
+use crate::util::error::macros::{host_error, runtime_error};
+use crate::util::error::Result;
+
+/// Persistent storage of static common properties.
+pub(crate) trait PropsStorage: Send {
+    fn load(&mut self) -> Result<Props>;
+
+    fn save(&mut self, props: &Props) -> Result<()>;
+}
+
+/// Creates the storage by the keys presented in the init config:
+///     - "common_props_persist_path": file path to persist, absent for no persistence.
+///     - "common_props_persist_type": "file" (default, JSON) or "sqlite" (requires "database" feature).
+pub(crate) fn from_config(config: &Map<String, Value>) -> Result<Option<Box<dyn PropsStorage>>> {
+    let path = match config.get("common_props_persist_path") {
+        None => return Ok(None),
+        Some(Value::String(path)) if !path.is_empty() => path.clone(),
+        Some(_) => return host_error!("\"common_props_persist_path\" should be a non-empty string!"),
+    };
+    match config.get("common_props_persist_type") {
+        None => Ok(Some(Box::new(FileStorage { path }))),
+        Some(Value::String(t)) => match t.to_lowercase().as_str() {
+            "file" => Ok(Some(Box::new(FileStorage { path }))),
+            #[cfg(feature = "database")]
+            "sqlite" => Ok(Some(Box::new(sqlite::SqliteStorage::new(path)?))),
+            _ => host_error!("\"common_props_persist_type\" (\"{t}\") is not supported!"),
+        },
+        Some(_) => host_error!("\"common_props_persist_type\" should be a string!"),
+    }
+}
+
+/// Stores as a JSON object in the file.
+#[derive(Debug)]
+pub(crate) struct FileStorage {
+    path: String,
+}
+
+impl PropsStorage for FileStorage {
+    fn load(&mut self) -> Result<Props> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return runtime_error!("Failed to read common properties from \"{}\", reason: {e}", self.path),
+        };
+        match serde_json::from_str::<Value>(&content) {
+            Ok(Value::Object(props)) => Ok(props),
+            Ok(_) => runtime_error!("Stored common properties in \"{}\" is not a map!", self.path),
+            Err(e) => runtime_error!("Failed to parse common properties from \"{}\", reason: {e}", self.path),
+        }
+    }
+
+    fn save(&mut self, props: &Props) -> Result<()> {
+        let path = Path::new(&self.path);
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let content = match serde_json::to_string(props) {
+            Ok(content) => content,
+            Err(e) => return runtime_error!("Failed to jsonify common properties, reason: {e}"),
+        };
+        // Writes to a temporary file then renames, to not leave a broken file behind.
+        let tmp_path = path.with_extension("tmp");
+        if let Err(e) = fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, path)) {
+            return runtime_error!("Failed to write common properties to \"{}\", reason: {e}", self.path);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+mod sqlite {
+    use rusqlite::Connection;
+    use serde_json::{Map, Value};
+
+    use crate::event::common_properties::Props;
+    use crate::event::common_properties::storage::PropsStorage;
+    use crate::util::error::macros::runtime_error;
+    use crate::util::error::Result;
+
+    pub(crate) struct SqliteStorage {
+        conn: Connection,
+    }
+
+    impl SqliteStorage {
+        pub(crate) fn new(path: String) -> Result<Self> {
+            let conn = match Connection::open(&path) {
+                Ok(conn) => conn,
+                Err(e) => return runtime_error!("Failed to open database \"{path}\", reason: {e}"),
+            };
+            if let Err(e) = conn.execute(
+                "CREATE TABLE IF NOT EXISTS static_common_props (key TEXT PRIMARY KEY, value TEXT NOT NULL)", ()
+            ) {
+                return runtime_error!("Failed to create table for common properties, reason: {e}");
+            }
+            Ok(SqliteStorage { conn })
+        }
+    }
+
+    impl PropsStorage for SqliteStorage {
+        fn load(&mut self) -> Result<Props> {
+            let rows: rusqlite::Result<Vec<(String, Value)>> = self.conn
+                .prepare("SELECT key, value FROM static_common_props")
+                .and_then(|mut stmt| {
+                    stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?.collect()
+                });
+            match rows {
+                Ok(rows) => Ok(rows.into_iter().collect::<Map<String, Value>>()),
+                Err(e) => runtime_error!("Failed to load common properties from database, reason: {e}"),
+            }
+        }
+
+        fn save(&mut self, props: &Props) -> Result<()> {
+            let result = self.conn.transaction().and_then(|tx| {
+                tx.execute("DELETE FROM static_common_props", ())?;
+                {
+                    let mut stmt = tx.prepare("INSERT INTO static_common_props (key, value) VALUES (?1, ?2)")?;
+                    for (k, v) in props {
+                        stmt.execute((k, v))?;
+                    }
+                }
+                tx.commit()
+            });
+            if let Err(e) = result {
+                return runtime_error!("Failed to save common properties to database, reason: {e}");
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{FileStorage, PropsStorage};
+
+    #[test]
+    fn file_storage() {
+        let path = std::env::temp_dir().join(format!("dt_comm_props_{}.json", uuid::Uuid::new_v4()));
+        let mut storage = FileStorage { path: path.to_str().unwrap().to_string() };
+        assert!(storage.load().expect("Missing file should be empty").is_empty());
+
+        let props = json!({"a": 1, "b": [1, "2"]}).as_object().unwrap().to_owned();
+        storage.save(&props).expect("Failed to save");
+        assert_eq!(storage.load().expect("Failed to load"), props);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "database")]
+    #[test]
+    fn sqlite_storage() {
+        let path = std::env::temp_dir().join(format!("dt_comm_props_{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        let mut storage = super::sqlite::SqliteStorage::new(path.clone()).expect("Failed to open");
+        assert!(storage.load().expect("Failed to load").is_empty());
+
+        let props = json!({"a": 1, "b": {"c": "d"}}).as_object().unwrap().to_owned();
+        storage.save(&props).expect("Failed to save");
+        let props = json!({"a": 2}).as_object().unwrap().to_owned();
+        storage.save(&props).expect("Failed to save");
+        assert_eq!(storage.load().expect("Failed to load"), props);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock};
 
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde_json::{Map, Value};
 
 use crate::event::Event;
-use crate::util::error::macros::verify_error;
+use crate::log_warning;
+use crate::util::datetime::{epoch_to_millis, get_time_since_epoch};
+use crate::util::error::macros::{host_error, internal_error, verify_error};
 use crate::util::error::Result;
 
 const NAME_REGEX_STR: &'static str = r"^[a-zA-Z#][a-zA-Z\d_]{0,63}$";
@@ -40,7 +43,12 @@ pub(super) static COMPULSORY_META_PROPS: Lazy<Vec<String>> = Lazy::new(|| vec!(
 ));
 
 static COMMON_PROPS: PropsConstraintMap = Lazy::new(|| HashMap::from([
-    ("#sdk_type", TypeConstraint::String), ("#sdk_version_name", TypeConstraint::String)
+    ("#sdk_type", TypeConstraint::String), ("#sdk_version_name", TypeConstraint::String),
+    ("#sample_rate", TypeConstraint::Number),
+]));
+// Ranges of the numeric preset properties, exclusive of the min and inclusive of the max.
+static PRESET_PROPS_RANGES: Lazy<HashMap<&str, (f64, f64)>> = Lazy::new(|| HashMap::from([
+    ("#sample_rate", (0.0, 1.0)),
 ]));
 static PRESET_EVENT_PROPS_COMMON: PropsConstraintMap = Lazy::new(|| HashMap::from([
     ("#bundle_id", TypeConstraint::String), ("#zone_offset", TypeConstraint::Number),
@@ -139,6 +147,177 @@ static PRESET_EVENTS: Lazy<HashMap<&str, Vec<&PropsConstraintMap>>> = Lazy::new(
     ("#ias_subscribe_notify", vec![&PRESET_PROPS_IAS]),
 ]));
 
+/// What to do once a property value exceeds the [PropsLimits].
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) enum LimitMode {
+    Reject,             // fails the verification of the whole event.
+    Truncate,           // cuts the value down to the limit and keeps the event.
+}
+
+/// Limits of property values, aligned with what the ingestion backend accepts.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PropsLimits {
+    pub(crate) max_string_bytes: usize,     // Length of a string value in bytes.
+    pub(crate) max_array_len: usize,        // Number of items in an array value.
+    pub(crate) max_object_depth: usize,     // Nesting level of objects, top-level object value counts as 1.
+    pub(crate) max_props_len: usize,        // Number of properties of an event.
+    pub(crate) mode: LimitMode,
+}
+
+impl PropsLimits {
+    pub(crate) const DEFAULT: PropsLimits = PropsLimits {
+        max_string_bytes: 2048,
+        max_array_len: 500,
+        max_object_depth: 3,
+        max_props_len: 1000,
+        mode: LimitMode::Reject,
+    };
+}
+
+impl Default for PropsLimits {
+    fn default() -> Self {
+        PropsLimits::DEFAULT
+    }
+}
+
+static PROPS_LIMITS: RwLock<PropsLimits> = RwLock::new(PropsLimits::DEFAULT);
+
+/// The [PropsLimits] updated by the keys presented in the init config:
+///     - "max_string_bytes", "max_array_len", "max_object_depth", "max_props_len": positive integer.
+///     - "props_limit_mode": "reject" (default) or "truncate".
+pub(crate) fn parse_props_limits(config: &Map<String, Value>) -> Result<PropsLimits> {
+    let mut limits = get_props_limits()?;
+    limits.max_string_bytes = get_limit_from_config(config, "max_string_bytes", limits.max_string_bytes)?;
+    limits.max_array_len = get_limit_from_config(config, "max_array_len", limits.max_array_len)?;
+    limits.max_object_depth = get_limit_from_config(config, "max_object_depth", limits.max_object_depth)?;
+    limits.max_props_len = get_limit_from_config(config, "max_props_len", limits.max_props_len)?;
+    match config.get("props_limit_mode") {
+        None => {},
+        Some(Value::String(mode)) => match mode.to_lowercase().as_str() {
+            "reject" => limits.mode = LimitMode::Reject,
+            "truncate" => limits.mode = LimitMode::Truncate,
+            _ => return host_error!("\"props_limit_mode\" should be either \"reject\" or \"truncate\"!"),
+        },
+        Some(_) => return host_error!("\"props_limit_mode\" should be a string!"),
+    }
+    Ok(limits)
+}
+
+pub(crate) fn set_props_limits(limits: PropsLimits) {
+    *PROPS_LIMITS.write().unwrap_or_else(PoisonError::into_inner) = limits;
+}
+
+fn get_limit_from_config(config: &Map<String, Value>, key: &str, default: usize) -> Result<usize> {
+    match config.get(key) {
+        None => Ok(default),
+        Some(Value::Number(n)) => match n.as_u64() {
+            Some(n) if n > 0 => Ok(n as usize),
+            _ => host_error!("\"{}\" should be a positive integer!", key),
+        },
+        Some(_) => host_error!("\"{}\" should be a positive integer!", key),
+    }
+}
+
+fn get_props_limits() -> Result<PropsLimits> {
+    let Ok(pl) = PROPS_LIMITS.read() else {
+        return internal_error!("Failed to get lock for props_limits!");
+    };
+    Ok(*pl)
+}
+
+/// Accepted window of "#event_time" relative to the local clock, None for unlimited.
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct EventTimeRules {
+    pub(crate) max_past_ms: Option<u64>,
+    pub(crate) max_future_ms: Option<u64>,
+}
+
+static EVENT_TIME_RULES: RwLock<EventTimeRules> = RwLock::new(EventTimeRules {
+    max_past_ms: None,
+    max_future_ms: None,
+});
+
+/// The [EventTimeRules] updated by the keys presented in the init config:
+///     - "event_time_max_past_days": non-negative integer, 0 for unlimited (default).
+///     - "event_time_max_future_mins": non-negative integer, 0 for unlimited (default).
+pub(crate) fn parse_event_time_rules(config: &Map<String, Value>) -> Result<EventTimeRules> {
+    let mut rules = get_event_time_rules()?;
+    if let Some(window) = get_window_from_config(config, "event_time_max_past_days", 24 * 3600 * 1000)? {
+        rules.max_past_ms = window;
+    }
+    if let Some(window) = get_window_from_config(config, "event_time_max_future_mins", 60 * 1000)? {
+        rules.max_future_ms = window;
+    }
+    Ok(rules)
+}
+
+pub(crate) fn set_event_time_rules(rules: EventTimeRules) {
+    *EVENT_TIME_RULES.write().unwrap_or_else(PoisonError::into_inner) = rules;
+}
+
+/// The window in milliseconds if the key presents, None inside for unlimited.
+fn get_window_from_config(config: &Map<String, Value>, key: &str, unit_ms: u64) -> Result<Option<Option<u64>>> {
+    let n = match config.get(key) {
+        None => return Ok(None),
+        Some(Value::Number(n)) => n.as_u64(),
+        Some(_) => None,
+    };
+    let Some(n) = n else {
+        return host_error!("\"{}\" should be a non-negative integer!", key);
+    };
+    if n == 0 {
+        return Ok(Some(None));
+    }
+    match n.checked_mul(unit_ms) {
+        Some(window) => Ok(Some(Some(window))),
+        None => host_error!("\"{}\" is too large! (Given: {})", key, n),
+    }
+}
+
+fn get_event_time_rules() -> Result<EventTimeRules> {
+    let Ok(etr) = EVENT_TIME_RULES.read() else {
+        return internal_error!("Failed to get lock for event_time_rules!");
+    };
+    Ok(*etr)
+}
+
+/// Normalises "#event_time" to milliseconds and checks it against the [EventTimeRules].
+pub(crate) fn verify_event_time(event_map: &mut Event) -> Result<()> {
+    let rules = get_event_time_rules()?;
+    let now = get_time_since_epoch().as_millis() as u64;
+    verify_event_time_aux(event_map, &rules, now)
+}
+
+fn verify_event_time_aux(event_map: &mut Event, rules: &EventTimeRules, now: u64) -> Result<()> {
+    let Some(Value::Number(time)) = event_map.get("#event_time") else {
+        // Type will be reported by verify_event().
+        return Ok(());
+    };
+    let Some(mut time) = time.as_u64() else {
+        return verify_error!("#event_time should be a non-negative integer in milliseconds! (Given: {})", time);
+    };
+
+    let millis = epoch_to_millis(time);
+    if millis != time {
+        log_warning!("#event_time ({}) seems not in milliseconds, converted to {}!", time, millis);
+        time = millis;
+    }
+
+    if let Some(max_past_ms) = rules.max_past_ms {
+        if time.saturating_add(max_past_ms) < now {
+            return verify_error!("#event_time ({}) is too far in the past! (Limit: {}ms)", time, max_past_ms);
+        }
+    }
+    if let Some(max_future_ms) = rules.max_future_ms {
+        if time > now.saturating_add(max_future_ms) {
+            return verify_error!("#event_time ({}) is too far in the future! (Limit: {}ms)", time, max_future_ms);
+        }
+    }
+
+    event_map.insert(String::from("#event_time"), Value::from(time));
+    Ok(())
+}
+
 pub(super) fn init() -> Result<()> {
     // Init the regex beforehand.
     let _ = NAME_RE.is_match("a");
@@ -196,6 +375,145 @@ pub(crate) fn verify_event(event_map: &Event) -> Result<()> {
     }
 }
 
+/// Verifies the common properties by the same rules of properties of custom events.
+/// "#sdk_*" are reserved as they are always injected by SDK.
+pub(crate) fn verify_common_properties(properties: &Map<String, Value>) -> Result<()> {
+    let scope = String::from("common properties");
+    for (k, v) in properties {
+        if k.starts_with("#sdk_") {
+            return verify_error!("Property \"{}\" is reserved by SDK, cannot be a common property!", k);
+        }
+        verify_properties(&scope, k, v, PRESET_EVENT_PROPS_COMMON.get(k.as_str()))?
+    }
+    Ok(())
+}
+
+/// Applies the [PropsLimits] to the "properties" of given event.
+pub(crate) fn limit_event(event_map: &mut Event) -> Result<()> {
+    let limits = get_props_limits()?;
+    if let Some(Value::Object(properties)) = event_map.get_mut("properties") {
+        limit_properties(properties, &limits)
+    } else {
+        Ok(())
+    }
+}
+
+/// Applies the limit of number of properties only, e.g. after the ones stamped by SDK are added.
+pub(crate) fn limit_event_props_len(event_map: &mut Event) -> Result<()> {
+    let limits = get_props_limits()?;
+    if let Some(Value::Object(properties)) = event_map.get_mut("properties") {
+        limit_props_len(properties, &limits)
+    } else {
+        Ok(())
+    }
+}
+
+fn limit_properties(properties: &mut Map<String, Value>, limits: &PropsLimits) -> Result<()> {
+    limit_props_len(properties, limits)?;
+
+    let mut dropped: Vec<String> = Vec::new();
+    for (k, v) in properties.iter_mut() {
+        if !limit_value(k, v, limits, 0)? {
+            dropped.push(k.clone());
+        }
+    }
+    for k in dropped {
+        properties.remove(&k);
+    }
+    Ok(())
+}
+
+fn limit_props_len(properties: &mut Map<String, Value>, limits: &PropsLimits) -> Result<()> {
+    if properties.len() > limits.max_props_len {
+        if limits.mode == LimitMode::Reject {
+            return verify_error!(
+                "Number of properties exceeds the limit! (Given: {}, Limit: {})", properties.len(), limits.max_props_len
+            );
+        }
+        // Preset properties are kept, custom properties are dropped from the tail.
+        let mut to_remove = properties.len() - limits.max_props_len;
+        let custom_keys: Vec<String> = properties.keys()
+            .filter(|k| !is_preset(k))
+            .rev()
+            .take(to_remove)
+            .cloned()
+            .collect();
+        for k in custom_keys {
+            properties.remove(&k);
+            to_remove -= 1;
+        }
+        if to_remove > 0 {
+            return verify_error!("Number of preset properties exceeds the limit! (Limit: {})", limits.max_props_len);
+        }
+        log_warning!("Number of properties exceeds the limit ({}), the exceeded ones are dropped!", limits.max_props_len);
+    }
+    Ok(())
+}
+
+/// Returns false if the value should be dropped (truncate mode only).
+fn limit_value(key: &str, value: &mut Value, limits: &PropsLimits, depth: usize) -> Result<bool> {
+    let truncate = limits.mode == LimitMode::Truncate;
+    match value {
+        Value::String(s) if s.len() > limits.max_string_bytes => {
+            if !truncate {
+                return verify_error!(
+                    "Length of string value for property \"{}\" exceeds the limit! (Given: {} bytes, Limit: {} bytes)",
+                    key, s.len(), limits.max_string_bytes
+                );
+            }
+            let mut end = limits.max_string_bytes;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            s.truncate(end);
+            log_warning!("String value for property \"{}\" is truncated to {} bytes!", key, end);
+        },
+        Value::Array(arr) => {
+            if arr.len() > limits.max_array_len {
+                if !truncate {
+                    return verify_error!(
+                        "Length of array value for property \"{}\" exceeds the limit! (Given: {}, Limit: {})",
+                        key, arr.len(), limits.max_array_len
+                    );
+                }
+                arr.truncate(limits.max_array_len);
+                log_warning!("Array value for property \"{}\" is truncated to {} items!", key, limits.max_array_len);
+            }
+            let mut i = 0;
+            while i < arr.len() {
+                if limit_value(key, &mut arr[i], limits, depth)? {
+                    i += 1;
+                } else {
+                    arr.remove(i);
+                }
+            }
+        },
+        Value::Object(map) => {
+            if depth + 1 > limits.max_object_depth {
+                if !truncate {
+                    return verify_error!(
+                        "Nesting depth of object value for property \"{}\" exceeds the limit! (Limit: {})",
+                        key, limits.max_object_depth
+                    );
+                }
+                log_warning!("Object value for property \"{}\" exceeds the nesting depth limit, dropped!", key);
+                return Ok(false);
+            }
+            let mut dropped: Vec<String> = Vec::new();
+            for (k, v) in map.iter_mut() {
+                if !limit_value(k, v, limits, depth + 1)? {
+                    dropped.push(k.clone());
+                }
+            }
+            for k in dropped {
+                map.remove(&k);
+            }
+        },
+        _ => {}
+    }
+    Ok(true)
+}
+
 fn is_preset(name: &String) -> bool {
     name.starts_with("#")
 }
@@ -219,6 +537,17 @@ fn check_type_constraint(value: &Value, target: &TypeConstraint) -> bool {
     }
 }
 
+fn check_range(key: &str, value: &Value) -> Result<()> {
+    let (Some((min, max)), Some(n)) = (PRESET_PROPS_RANGES.get(key), value.as_f64()) else {
+        return Ok(());
+    };
+    if n > *min && n <= *max {
+        Ok(())
+    } else {
+        verify_error!("The value for property \"{}\" is out of range (Given: {}, Expected: ({}, {}])!", key, n, min, max)
+    }
+}
+
 fn check_meta_is_string_and_nonempty(event_map: &Event, key: String) -> Result<()> {
     if let Some(value) = event_map.get(&key) {
         if let Value::String(value) = value {
@@ -263,7 +592,7 @@ fn verify_properties(
                     key, value, constraint
                 )
             } else {
-                Ok(())
+                check_range(key, value)
             }
         } else {
             // Property (starts with #) is out of scope.
@@ -351,7 +680,7 @@ fn verify_all_custom_props_are_num(properties: &Map<String, Value>) -> Result<()
 mod test {
     use serde_json::{json, Value};
 
-    use super::verify_event;
+    use super::{EventTimeRules, limit_properties, limit_props_len, LimitMode, PropsLimits, parse_event_time_rules, parse_props_limits, verify_event, verify_event_time_aux};
 
     fn verify(obj: Value, target: bool) {
         let obj = obj.as_object().unwrap();
@@ -865,4 +1194,148 @@ mod test {
         let elapsed = st.elapsed().as_micros();
         println!("Total: {}, Avg: {}", elapsed, elapsed / n)
     }
-}
\ No newline at end of file
+
+    fn small_limits(mode: LimitMode) -> PropsLimits {
+        PropsLimits {
+            max_string_bytes: 4,
+            max_array_len: 2,
+            max_object_depth: 1,
+            max_props_len: 3,
+            mode,
+        }
+    }
+
+    #[test]
+    fn props_limits_reject() {
+        let limits = small_limits(LimitMode::Reject);
+        let check = |props: Value| {
+            let mut props = props.as_object().unwrap().to_owned();
+            limit_properties(&mut props, &limits).is_ok()
+        };
+
+        assert!(check(json!({"a": "1234", "b": [1, 2], "c": {"d": 1}})));
+        assert!(!check(json!({"a": "12345"})));
+        assert!(!check(json!({"a": ["1", "12345"]})));
+        assert!(!check(json!({"a": [1, 2, 3]})));
+        assert!(!check(json!({"a": {"b": {"c": 1}}})));
+        assert!(!check(json!({"a": [{"b": {"c": 1}}]})));
+        assert!(!check(json!({"a": 1, "b": 2, "c": 3, "d": 4})));
+    }
+
+    #[test]
+    fn props_limits_truncate() {
+        let limits = small_limits(LimitMode::Truncate);
+        let mut props = json!({
+            "#sdk_type": "rust",
+            "a": "一二",
+            "b": [1, 2, 3],
+            "c": {"d": {"e": 1}, "f": "123456"},
+            "z": 0
+        }).as_object().unwrap().to_owned();
+        limit_properties(&mut props, &limits).expect("Should be truncated rather than rejected");
+        assert_eq!(Value::Object(props), json!({
+            "#sdk_type": "rust",
+            "a": "一",
+            "b": [1, 2],
+        }));
+
+        // Preset properties cannot be dropped.
+        let mut props = json!({
+            "#a": 1, "#b": 2, "#c": 3, "#d": 4
+        }).as_object().unwrap().to_owned();
+        assert!(limit_properties(&mut props, &limits).is_err());
+    }
+
+    #[test]
+    fn sample_rate() {
+        super::init().expect("Failed to init");
+        let event = |rate: Value| json!({
+            "#app_id": "123",
+            "#event_time": 123,
+            "#dt_id": "ddd",
+            "#bundle_id": "com.xx",
+            "#event_name": "test_event",
+            "#event_type": "track",
+            "#event_syn": "eeeee",
+            "properties": {
+                "#sample_rate": rate
+            }
+        });
+        verify(event(json!(0.3)), true);
+        verify(event(json!(1)), true);
+        verify(event(json!(0)), false);
+        verify(event(json!(1.5)), false);
+        verify(event(json!("0.3")), false);
+    }
+
+    #[test]
+    fn props_len_limit_after_stamping() {
+        let limits = small_limits(LimitMode::Truncate);
+        let mut props = json!({
+            "a": 1, "b": 2, "c": 3
+        }).as_object().unwrap().to_owned();
+        limit_properties(&mut props, &limits).expect("Within the limits");
+        props.insert("#sample_rate".to_string(), json!(0.5));
+        limit_props_len(&mut props, &limits).expect("Should be truncated rather than rejected");
+        assert_eq!(Value::Object(props), json!({"a": 1, "b": 2, "#sample_rate": 0.5}));
+    }
+
+    #[test]
+    fn props_limits_config() {
+        let config = json!({"max_string_bytes": 0}).as_object().unwrap().to_owned();
+        assert!(parse_props_limits(&config).is_err());
+        let config = json!({"max_array_len": "1"}).as_object().unwrap().to_owned();
+        assert!(parse_props_limits(&config).is_err());
+        let config = json!({"props_limit_mode": "drop"}).as_object().unwrap().to_owned();
+        assert!(parse_props_limits(&config).is_err());
+        let config = json!({"props_limit_mode": "reject"}).as_object().unwrap().to_owned();
+        assert!(parse_props_limits(&config).is_ok());
+    }
+
+    #[test]
+    fn event_time() {
+        let now: u64 = 1_700_000_000_000;
+        let check = |time: Value, rules: &EventTimeRules| -> Option<Value> {
+            let mut j = json!({"#event_time": time}).as_object().unwrap().to_owned();
+            verify_event_time_aux(&mut j, rules, now).ok().map(|_| j["#event_time"].clone())
+        };
+
+        let rules = EventTimeRules::default();
+        assert_eq!(check(json!(now), &rules), Some(json!(now)));
+        assert_eq!(check(json!(now / 1000), &rules), Some(json!(now)));
+        assert_eq!(check(json!(now * 1000), &rules), Some(json!(now)));
+        assert_eq!(check(json!(-1), &rules), None);
+        assert_eq!(check(json!(1.5), &rules), None);
+
+        let rules = EventTimeRules {
+            max_past_ms: Some(24 * 3600 * 1000),
+            max_future_ms: Some(60 * 1000),
+        };
+        assert_eq!(check(json!(now - 3600 * 1000), &rules), Some(json!(now - 3600 * 1000)));
+        assert_eq!(check(json!(now - 2 * 24 * 3600 * 1000), &rules), None);
+        assert_eq!(check(json!(now + 30 * 1000), &rules), Some(json!(now + 30 * 1000)));
+        assert_eq!(check(json!(now + 120 * 1000), &rules), None);
+        assert_eq!(check(json!((now + 120 * 1000) / 1000), &rules), None);
+
+        let rules = EventTimeRules {
+            max_past_ms: Some(u64::MAX),
+            max_future_ms: Some(u64::MAX),
+        };
+        assert_eq!(check(json!(u64::MAX), &rules), Some(json!(u64::MAX / 1000)));
+        assert_eq!(check(json!(now), &rules), Some(json!(now)));
+    }
+
+    #[test]
+    fn event_time_config() {
+        let config = json!({"event_time_max_past_days": -1}).as_object().unwrap().to_owned();
+        assert!(parse_event_time_rules(&config).is_err());
+        let config = json!({"event_time_max_future_mins": "10"}).as_object().unwrap().to_owned();
+        assert!(parse_event_time_rules(&config).is_err());
+        let config = json!({"event_time_max_past_days": u64::MAX}).as_object().unwrap().to_owned();
+        assert!(parse_event_time_rules(&config).is_err());
+        let config = json!({"event_time_max_future_mins": u64::MAX / 1000}).as_object().unwrap().to_owned();
+        assert!(parse_event_time_rules(&config).is_err());
+        let config = json!({"event_time_max_past_days": 0}).as_object().unwrap().to_owned();
+        assert!(parse_event_time_rules(&config).is_ok());
+    }
+}
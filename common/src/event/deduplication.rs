@@ -0,0 +1,305 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use serde_json::{Map, Value};
+
+use crate::event::Event;
+use crate::{log_info, log_warning};
+use crate::util::error::macros::{host_error, internal_error, runtime_error};
+use crate::util::error::Result;
+use crate::util::metrics;
+use crate::util::metrics::Counter;
+
+static DEDUPLICATOR: Mutex<Option<Deduplicator>> = Mutex::new(None);
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) enum DedupMode {
+    Drop,               // drops the repeated event silently.
+    Report,             // drops the repeated event and reports it as an error.
+}
+
+/// Keeps the recently seen "#event_syn" within a bounded window, the oldest one is evicted first.
+/// The persisted file is appended with the new ones, and rewritten once it's too long.
+#[derive(Debug)]
+pub(crate) struct Deduplicator {
+    capacity: usize,
+    mode: DedupMode,
+    persist_path: Option<String>,
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+    // Inserted but not appended to the file yet, and the lines in the file.
+    unpersisted: Vec<String>,
+    persisted_lines: usize,
+    // Too many are unpersisted, the file is rewritten by the window instead.
+    rewrite: bool,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(capacity: usize, mode: DedupMode, persist_path: Option<String>) -> Self {
+        let mut dedup = Deduplicator {
+            capacity, mode, persist_path,
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            unpersisted: Vec::new(),
+            persisted_lines: 0,
+            rewrite: false,
+        };
+        dedup.load();
+        dedup
+    }
+
+    pub(crate) fn contains(&self, syn: &str) -> bool {
+        self.seen.contains(syn)
+    }
+
+    pub(crate) fn insert(&mut self, syn: String) {
+        if self.seen.contains(&syn) {
+            return;
+        }
+        while self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(syn.clone());
+        if self.persist_path.is_some() && !self.rewrite {
+            if self.unpersisted.len() < self.capacity {
+                self.unpersisted.push(syn.clone());
+            } else {
+                self.unpersisted.clear();
+                self.rewrite = true;
+            }
+        }
+        self.order.push_back(syn);
+    }
+
+    fn load(&mut self) {
+        let Some(path) = &self.persist_path else {
+            return;
+        };
+        let Ok(file) = File::open(path) else {
+            return;
+        };
+        let lines: Vec<String> = BufReader::new(file).lines()
+            .map_while(|it| it.ok())
+            .filter(|it| !it.is_empty())
+            .collect();
+        self.persisted_lines = lines.len();
+        for syn in lines {
+            self.insert(syn);
+        }
+        self.unpersisted.clear();
+        self.rewrite = false;
+        log_info!("Loaded {} #event_syn for deduplication.", self.order.len());
+    }
+
+    pub(crate) fn persist(&mut self) -> Result<()> {
+        let Some(path) = &self.persist_path else {
+            return Ok(());
+        };
+        if !self.rewrite && self.unpersisted.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = Path::new(path).parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let rewrite = self.rewrite || self.persisted_lines + self.unpersisted.len() > self.capacity * 2;
+        let lines: Vec<&String> = if rewrite {
+            self.order.iter().collect()
+        } else {
+            self.unpersisted.iter().collect()
+        };
+        let mut content = String::with_capacity(lines.len() * 37);
+        for syn in lines.iter() {
+            content.push_str(syn);
+            content.push('\n');
+        }
+        let result = if rewrite {
+            // Writes to a temporary file then renames, to not leave a broken file behind.
+            let tmp_path = Path::new(path).with_extension("tmp");
+            fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, path))
+        } else {
+            OpenOptions::new().create(true).append(true).open(path)
+                .and_then(|mut file| file.write_all(content.as_bytes()))
+        };
+        if let Err(e) = result {
+            return runtime_error!("Failed to persist #event_syn for deduplication to \"{path}\", reason: {e}");
+        }
+        self.persisted_lines = if rewrite { lines.len() } else { self.persisted_lines + lines.len() };
+        self.unpersisted.clear();
+        self.rewrite = false;
+        Ok(())
+    }
+}
+
+/// Deduplicator by the init config, None for disabled, applied only if the init succeeds.
+pub(crate) struct DedupConfig(Option<Deduplicator>);
+
+impl DedupConfig {
+    pub(crate) fn apply(self) {
+        *DEDUPLICATOR.lock().unwrap_or_else(PoisonError::into_inner) = self.0;
+    }
+}
+
+/// Parses the deduplication by the keys presented in the init config:
+///     - "dedup_capacity": non-negative integer, number of #event_syn to remember, 0 for disabled (default).
+///     - "dedup_mode": "drop" (default) or "report".
+///     - "dedup_persist_path": optional, file to keep the remembered #event_syn across restarts.
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<DedupConfig> {
+    let capacity = match config.get("dedup_capacity") {
+        None => 0,
+        Some(Value::Number(n)) => match n.as_u64() {
+            Some(n) => n as usize,
+            None => return host_error!("\"dedup_capacity\" should be a non-negative integer!"),
+        },
+        Some(_) => return host_error!("\"dedup_capacity\" should be a non-negative integer!"),
+    };
+    let mode = match config.get("dedup_mode") {
+        None => DedupMode::Drop,
+        Some(Value::String(mode)) => match mode.to_lowercase().as_str() {
+            "drop" => DedupMode::Drop,
+            "report" => DedupMode::Report,
+            _ => return host_error!("\"dedup_mode\" should be either \"drop\" or \"report\"!"),
+        },
+        Some(_) => return host_error!("\"dedup_mode\" should be a string!"),
+    };
+    let persist_path = match config.get("dedup_persist_path") {
+        None => None,
+        Some(Value::String(path)) => Some(path.clone()),
+        Some(_) => return host_error!("\"dedup_persist_path\" should be a string!"),
+    };
+
+    Ok(DedupConfig(if capacity == 0 {
+        None
+    } else {
+        Some(Deduplicator::new(capacity, mode, persist_path))
+    }))
+}
+
+/// Returns the "#event_syn" given by host, which is the one could be repeated.
+pub(crate) fn get_host_syn(event: &Event) -> Option<String> {
+    if let Some(Value::String(syn)) = event.get("#event_syn") {
+        Some(syn.clone())
+    } else {
+        None
+    }
+}
+
+/// Returns Ok(false) if the event with given "#event_syn" is a repeated one and should be dropped.
+pub(crate) fn check(syn: &str) -> Result<bool> {
+    let Ok(dedup) = DEDUPLICATOR.lock() else {
+        return internal_error!("Failed to get lock for deduplicator!");
+    };
+    let Some(dedup) = dedup.as_ref() else {
+        return Ok(true);
+    };
+    if !dedup.contains(syn) {
+        return Ok(true);
+    }
+    match dedup.mode {
+        DedupMode::Drop => {
+            log_warning!("Event with #event_syn \"{syn}\" is repeated, dropped!");
+            metrics::incr(Counter::EventsDeduplicated, 1);
+            Ok(false)
+        },
+        DedupMode::Report => runtime_error!("Event with #event_syn \"{syn}\" is repeated, dropped!"),
+    }
+}
+
+/// Remembers the "#event_syn" of an event that has been accepted by consumer.
+pub(crate) fn record(syn: String) -> Result<()> {
+    let Ok(mut dedup) = DEDUPLICATOR.lock() else {
+        return internal_error!("Failed to get lock for deduplicator!");
+    };
+    if let Some(dedup) = dedup.as_mut() {
+        dedup.insert(syn);
+    }
+    Ok(())
+}
+
+pub(crate) fn persist() -> Result<()> {
+    let Ok(mut dedup) = DEDUPLICATOR.lock() else {
+        return internal_error!("Failed to get lock for deduplicator!");
+    };
+    if let Some(dedup) = dedup.as_mut() {
+        dedup.persist()
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DedupMode, Deduplicator};
+
+    #[test]
+    fn bounded_window() {
+        let mut dedup = Deduplicator::new(2, DedupMode::Drop, None);
+        dedup.insert(String::from("a"));
+        dedup.insert(String::from("b"));
+        dedup.insert(String::from("a"));
+        assert!(dedup.contains("a"));
+        assert!(dedup.contains("b"));
+
+        dedup.insert(String::from("c"));
+        assert!(!dedup.contains("a"));
+        assert!(dedup.contains("b"));
+        assert!(dedup.contains("c"));
+    }
+
+    #[test]
+    fn persisted() {
+        let path = std::env::temp_dir().join(format!("dt_dedup_{}.txt", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap().to_string();
+
+        let mut dedup = Deduplicator::new(3, DedupMode::Drop, Some(path_str.clone()));
+        for syn in ["a", "b", "c", "d"] {
+            dedup.insert(String::from(syn));
+        }
+        dedup.persist().expect("Failed to persist");
+
+        let dedup = Deduplicator::new(2, DedupMode::Drop, Some(path_str));
+        assert!(!dedup.contains("a"));
+        assert!(!dedup.contains("b"));
+        assert!(dedup.contains("c"));
+        assert!(dedup.contains("d"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn appended_then_rewritten() {
+        let path = std::env::temp_dir().join(format!("dt_dedup_{}.txt", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap().to_string();
+        let read = || std::fs::read_to_string(&path).unwrap();
+
+        let mut dedup = Deduplicator::new(2, DedupMode::Drop, Some(path_str.clone()));
+        dedup.insert(String::from("a"));
+        dedup.insert(String::from("b"));
+        dedup.persist().unwrap();
+        dedup.insert(String::from("c"));
+        dedup.persist().unwrap();
+        assert_eq!(read(), "a\nb\nc\n");
+        // Nothing new to persist
+        dedup.persist().unwrap();
+        assert_eq!(read(), "a\nb\nc\n");
+
+        dedup.insert(String::from("d"));
+        dedup.insert(String::from("e"));
+        dedup.persist().unwrap();
+        assert_eq!(read(), "d\ne\n");
+        assert!(!path.with_extension("tmp").exists());
+
+        let mut dedup = Deduplicator::new(2, DedupMode::Drop, Some(path_str));
+        assert!(dedup.contains("d") && dedup.contains("e"));
+        for syn in ["f", "g", "h"] {
+            dedup.insert(String::from(syn));
+        }
+        dedup.persist().unwrap();
+        assert_eq!(read(), "g\nh\n");
+        let _ = std::fs::remove_file(path);
+    }
+}
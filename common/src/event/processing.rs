@@ -1,23 +1,84 @@
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::OnceLock;
+use std::sync::{OnceLock, PoisonError, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde_json::{Map, Number, Value};
-use crate::event::common_properties::fulfill_by_comm_props;
-use crate::event::data_verification::{META_PROPS, verify_event};
-use crate::event::Event;
+use crate::event::common_properties::{fulfill_by_comm_props, Props};
+use crate::event::data_verification::{limit_event, limit_event_props_len, META_PROPS, verify_event, verify_event_time};
+use crate::event::{Event, sampling};
 use crate::log_error;
 use crate::util::error::{DTError, Result};
 use crate::util::error::DTError::InternalError;
-use crate::util::error::macros::error_with;
+use crate::util::datetime::{epoch_to_millis, fmt_millis_for_date_prop, parse_iso8601_millis};
+use crate::util::error::macros::{error_with, host_error, internal_error, verify_error};
 
 pub static DEBUG: AtomicBool = AtomicBool::new(false);
+/// Fills "#zone_offset" (in hours) of track events by the timezone of host if it's absent.
+pub static FILL_ZONE_OFFSET: AtomicBool = AtomicBool::new(false);
 
-pub fn process_event(event_map: Event) -> Result<Event> {
+/// Custom properties whose value is datetime, accepting ISO-8601 string or epoch.
+static DATE_PROPS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Processing settings in the init config, applied only if the init succeeds.
+pub(crate) struct ProcessingConfig {
+    fill_zone_offset: Option<bool>,
+    date_props: Option<Vec<String>>,
+}
+
+impl ProcessingConfig {
+    pub(crate) fn apply(self) {
+        if let Some(fill) = self.fill_zone_offset {
+            FILL_ZONE_OFFSET.store(fill, Ordering::Relaxed);
+        }
+        if let Some(date_props) = self.date_props {
+            *DATE_PROPS.write().unwrap_or_else(PoisonError::into_inner) = date_props;
+        }
+    }
+}
+
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<ProcessingConfig> {
+    let fill_zone_offset = match config.get("fill_zone_offset") {
+        None => None,
+        Some(Value::Bool(fill)) => Some(*fill),
+        Some(_) => return host_error!("\"fill_zone_offset\" should be a boolean!"),
+    };
+    let date_props = match config.get("date_props") {
+        None => None,
+        Some(Value::Array(arr)) => {
+            let mut date_props = Vec::with_capacity(arr.len());
+            for v in arr {
+                let Value::String(name) = v else {
+                    return host_error!("\"date_props\" should be a list of string!");
+                };
+                date_props.push(name.clone());
+            }
+            Some(date_props)
+        },
+        Some(_) => return host_error!("\"date_props\" should be a list of string!"),
+    };
+    Ok(ProcessingConfig { fill_zone_offset, date_props })
+}
+
+/// Returns None if the event is valid but dropped by sampling.
+/// The user-scoped common properties (if any) are given by [crate::event::user_context::UserContext].
+/// The dynamic common properties (if any) are given by [crate::event::common_properties::get_dynamic_comm_props].
+pub fn process_event(event_map: Event, user_props: Option<&Props>, dynamic_props: Option<&Props>) -> Result<Option<Event>> {
     let mut event = eventify(event_map)?;
     fulfill_metas(&mut event);
+    {
+        let Ok(date_props) = DATE_PROPS.read() else {
+            return internal_error!("Failed to get lock for date_props!");
+        };
+        normalise_date_values(&mut event, &date_props)?;
+    }
     inject_sdk_base_info(&mut event);
-    fulfill_by_comm_props(&mut event)?;
-    let verify_result = verify_event(&mut event);
+    fulfill_by_comm_props(&mut event, user_props, dynamic_props)?;
+    #[cfg(feature = "benchmark")]
+    let st = std::time::Instant::now();
+    let verify_result = verify_event_time(&mut event)
+        .and_then(|_| limit_event(&mut event))
+        .and_then(|_| verify_event(&event));
+    #[cfg(feature = "benchmark")]
+    (&crate::util::benchmark_tracer::BM_TRACER).add("verification", st.elapsed().as_micros());
 
     match verify_result {
         Err(e) => if let DTError::VerifyError(_) = e {
@@ -25,7 +86,16 @@ pub fn process_event(event_map: Event) -> Result<Event> {
         } else {
             Err(e)
         },
-        Ok(_) => Ok(event)
+        Ok(_) => {
+            if !sampling::sample(&mut event)? {
+                return Ok(None);
+            }
+            // The "#sample_rate" stamped counts as well.
+            if let Err(e) = limit_event_props_len(&mut event) {
+                return error_with!(e, "Verification failed for {event:?}");
+            }
+            Ok(Some(event))
+        }
     }
 }
 
@@ -46,7 +116,7 @@ fn is_need_eventify(event: &Event) -> bool {
 }
 
 /// flatted map / event -> event
-fn eventify(mut event: Event) -> Result<Event> {
+pub(crate) fn eventify(mut event: Event) -> Result<Event> {
     if !is_need_eventify(&event) {
         return Ok(event);
     }
@@ -89,6 +159,55 @@ fn fulfill_metas(event: &mut Event) {
     if !event.contains_key(&String::from("properties")) {
         event.insert(String::from("properties"), Value::Object(serde_json::Map::with_capacity(2)));
     }
+
+    if FILL_ZONE_OFFSET.load(Ordering::Relaxed) {
+        if let Some(Value::String(event_type)) = event.get("#event_type") {
+            if event_type == "track" {
+                fulfill_zone_offset(event);
+            }
+        }
+    }
+}
+
+fn fulfill_zone_offset(event: &mut Event) {
+    if let Some(Value::Object(properties)) = event.get_mut("properties") {
+        if !properties.contains_key("#zone_offset") {
+            let offset_sec = chrono::Local::now().offset().local_minus_utc();
+            properties.insert(String::from("#zone_offset"), Value::from(offset_sec as f64 / 3600.0));
+        }
+    }
+}
+
+/// Converts "#event_time" in ISO-8601 to milliseconds, and values of the date properties to the backend format.
+fn normalise_date_values(event: &mut Event, date_props: &[String]) -> Result<()> {
+    if let Some(Value::String(time)) = event.get("#event_time") {
+        let Some(millis) = parse_iso8601_millis(time) else {
+            return verify_error!("#event_time (\"{}\") is not a valid ISO-8601 datetime!", time);
+        };
+        event.insert(String::from("#event_time"), Value::from(millis));
+    }
+
+    if date_props.is_empty() {
+        return Ok(());
+    }
+    let Some(Value::Object(properties)) = event.get_mut("properties") else {
+        return Ok(());
+    };
+    for name in date_props.iter() {
+        let Some(value) = properties.get_mut(name) else {
+            continue;
+        };
+        let millis = match value {
+            Value::String(s) => parse_iso8601_millis(s),
+            Value::Number(n) => n.as_u64().map(|n| epoch_to_millis(n) as i64),
+            _ => None,
+        };
+        let Some(formatted) = millis.and_then(fmt_millis_for_date_prop) else {
+            return verify_error!("Value of date property \"{}\" should be an ISO-8601 string or epoch! (Given: {})", name, value);
+        };
+        *value = Value::String(formatted);
+    }
+    Ok(())
 }
 
 fn inject_sdk_base_info(event_map: &mut Event) {
@@ -108,7 +227,7 @@ fn inject_sdk_base_info(event_map: &mut Event) {
 #[cfg(test)]
 mod test {
     use serde_json::json;
-    use super::{inject_sdk_base_info, eventify, process_event};
+    use super::{DATE_PROPS, inject_sdk_base_info, eventify, fulfill_zone_offset, normalise_date_values, process_event};
 
     #[test]
     fn test_inject_sdk_base_info() {
@@ -182,10 +301,66 @@ mod test {
         let mut tm = 0;
         for _ in 0..n {
             let st = std::time::Instant::now();
-            process_event(j.clone()).expect("This event is not valid");
+            process_event(j.clone(), None, None).expect("This event is not valid");
             tm += st.elapsed().as_micros();
         }
         println!("Total: {}, Avg: {}", tm, tm / n);
         println!("QPS: {}", 1000000/(tm/n));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fulfill_zone_offset() {
+        let mut j = json!({
+            "#event_type": "track",
+            "properties": {}
+        }).as_object().unwrap().to_owned();
+        fulfill_zone_offset(&mut j);
+        assert!(j["properties"]["#zone_offset"].is_number());
+
+        let mut j = json!({
+            "#event_type": "track",
+            "properties": {"#zone_offset": 5.5}
+        }).as_object().unwrap().to_owned();
+        fulfill_zone_offset(&mut j);
+        assert_eq!(j["properties"]["#zone_offset"], json!(5.5));
+    }
+
+    #[test]
+    fn test_normalise_date_values() {
+        let date_props = [String::from("birthday"), String::from("paid_at")];
+
+        let mut j = json!({
+            "#event_time": "2024-01-02T03:04:05.678Z",
+            "properties": {
+                "birthday": "2024-01-02T03:04:05.678Z",
+                "paid_at": 1704164645,
+                "other": "2024-01-02T03:04:05Z"
+            }
+        }).as_object().unwrap().to_owned();
+        normalise_date_values(&mut j, &date_props).expect("Should be valid");
+        assert_eq!(j["#event_time"], json!(1704164645678_u64));
+        let expected = crate::util::datetime::fmt_millis_for_date_prop(1704164645678).unwrap();
+        assert_eq!(j["properties"]["birthday"], json!(expected));
+        let expected = crate::util::datetime::fmt_millis_for_date_prop(1704164645000).unwrap();
+        assert_eq!(j["properties"]["paid_at"], json!(expected));
+        assert_eq!(j["properties"]["other"], json!("2024-01-02T03:04:05Z"));
+
+        let mut j = json!({"#event_time": "yesterday", "properties": {}}).as_object().unwrap().to_owned();
+        assert!(normalise_date_values(&mut j, &date_props).is_err());
+        let mut j = json!({"properties": {"birthday": true}}).as_object().unwrap().to_owned();
+        assert!(normalise_date_values(&mut j, &date_props).is_err());
+        assert!(normalise_date_values(&mut j, &[]).is_ok());
+    }
+
+    #[test]
+    fn failed_init_applies_nothing() {
+        let config = json!({
+            "consumer": "log",
+            "path": "unused",
+            "max_batch_len": 0,
+            "date_props": ["birthday"],
+        }).as_object().unwrap().to_owned();
+        assert!(crate::init_by_config(config).is_err());
+        assert!(DATE_PROPS.read().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use serde_json::{Map, Value};
+
+use crate::event::Event;
+use crate::log_debug;
+use crate::util::error::macros::{host_error, internal_error};
+use crate::util::error::Result;
+use crate::util::metrics;
+use crate::util::metrics::Counter;
+
+const SAMPLE_RATE_KEY: &str = "#sample_rate";
+// Cap of the token buckets, for the rules matching any "#event_name".
+const MAX_BUCKETS: usize = 1024;
+
+static SAMPLER: Mutex<Option<Sampler>> = Mutex::new(None);
+
+/// Sampling rule for events matched by "#event_name" and "#event_type" (None for any).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SamplingRule {
+    event_name: Option<String>,
+    event_type: Option<String>,
+    ratio: Option<f64>,             // Ratio of events to keep, in (0, 1].
+    by_dt_id: bool,                 // Keeps all or none of events of a user if true, otherwise per event.
+    rate_per_sec: Option<f64>,      // Cap of events kept per second per "#event_name", by token bucket.
+    burst: Option<f64>,             // Capacity of the token bucket, defaults to rate_per_sec.
+}
+
+impl SamplingRule {
+    fn from_config(value: &Value) -> Result<Self> {
+        let Value::Object(rule) = value else {
+            return host_error!("Each of \"sampling_rules\" should be a map!");
+        };
+        let get_str = |key: &str| -> Result<Option<String>> {
+            match rule.get(key) {
+                None => Ok(None),
+                Some(Value::String(s)) => Ok(Some(s.clone())),
+                Some(_) => host_error!("\"{}\" of sampling rule should be a string!", key),
+            }
+        };
+        let get_positive = |key: &str| -> Result<Option<f64>> {
+            match rule.get(key) {
+                None => Ok(None),
+                Some(Value::Number(n)) => match n.as_f64() {
+                    Some(n) if n > 0.0 => Ok(Some(n)),
+                    _ => host_error!("\"{}\" of sampling rule should be a positive number!", key),
+                },
+                Some(_) => host_error!("\"{}\" of sampling rule should be a positive number!", key),
+            }
+        };
+
+        let ratio = get_positive("ratio")?;
+        if let Some(ratio) = ratio {
+            if ratio > 1.0 {
+                return host_error!("\"ratio\" of sampling rule should be in (0, 1]!");
+            }
+        }
+        let by_dt_id = match rule.get("by_dt_id") {
+            None => true,
+            Some(Value::Bool(b)) => *b,
+            Some(_) => return host_error!("\"by_dt_id\" of sampling rule should be a boolean!"),
+        };
+
+        Ok(SamplingRule {
+            event_name: get_str("event_name")?,
+            event_type: get_str("event_type")?,
+            ratio,
+            by_dt_id,
+            rate_per_sec: get_positive("rate_per_sec")?,
+            burst: get_positive("burst")?,
+        })
+    }
+
+    fn is_matched(&self, event_name: &str, event_type: &str) -> bool {
+        self.event_name.iter().all(|it| it == event_name)
+            && self.event_type.iter().all(|it| it == event_type)
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    rate_per_sec: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate_per_sec: f64, capacity: f64, now: Instant) -> Self {
+        TokenBucket { capacity, tokens: capacity, rate_per_sec, last: now }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.capacity);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Refilled to the full, same as a new one.
+    fn is_idle(&self, now: Instant) -> bool {
+        self.tokens + now.saturating_duration_since(self.last).as_secs_f64() * self.rate_per_sec >= self.capacity
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Sampler {
+    rules: Vec<SamplingRule>,
+    buckets: HashMap<(usize, String), TokenBucket>,     // by (index of rule, event name)
+}
+
+impl Sampler {
+    pub(crate) fn new(rules: Vec<SamplingRule>) -> Self {
+        Sampler { rules, buckets: HashMap::new() }
+    }
+
+    /// Returns false if the event should be dropped, the first matched rule is applied.
+    pub(crate) fn sample(&mut self, event: &mut Event, now: Instant) -> bool {
+        let event_name = event.get("#event_name").and_then(Value::as_str).unwrap_or_default();
+        let event_type = event.get("#event_type").and_then(Value::as_str).unwrap_or_default();
+        let Some((idx, rule)) = self.rules.iter().enumerate()
+            .find(|(_, rule)| rule.is_matched(event_name, event_type)) else {
+            return true;
+        };
+
+        if let Some(ratio) = rule.ratio {
+            let get_seed = |key: &str| event.get(key).and_then(Value::as_str).filter(|it| !it.is_empty());
+            // Per event if the "#dt_id" is absent, or all of these events would be kept or dropped together.
+            let seed = if rule.by_dt_id { get_seed("#dt_id") } else { None }
+                .or_else(|| get_seed("#event_syn"))
+                .unwrap_or_default();
+            if hash_to_unit(seed) >= ratio {
+                log_debug!(event_name = event_name; "Event \"{event_name}\" is sampled out.");
+                metrics::incr(Counter::EventsSampledOut, 1);
+                return false;
+            }
+        }
+
+        if let Some(rate_per_sec) = rule.rate_per_sec {
+            let capacity = rule.burst.unwrap_or(rate_per_sec).max(1.0);
+            let key = (idx, event_name.to_string());
+            if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(&key) {
+                evict_buckets(&mut self.buckets, now);
+            }
+            let bucket = self.buckets.entry(key)
+                .or_insert_with(|| TokenBucket::new(rate_per_sec, capacity, now));
+            if !bucket.try_acquire(now) {
+                log_debug!(event_name = event_name; "Event \"{event_name}\" exceeds the rate cap, dropped.");
+                metrics::incr(Counter::EventsSampledOut, 1);
+                return false;
+            }
+        }
+
+        if let Some(ratio) = rule.ratio {
+            if let Some(Value::Object(properties)) = event.get_mut("properties") {
+                properties.insert(String::from(SAMPLE_RATE_KEY), Value::from(ratio));
+            }
+        }
+        true
+    }
+}
+
+// Drops the idle buckets, or the least recently used one if none is idle.
+fn evict_buckets(buckets: &mut HashMap<(usize, String), TokenBucket>, now: Instant) {
+    buckets.retain(|_, bucket| !bucket.is_idle(now));
+    if buckets.len() < MAX_BUCKETS {
+        return;
+    }
+    let lru = buckets.iter()
+        .min_by_key(|(_, bucket)| bucket.last)
+        .map(|(key, _)| key.clone());
+    if let Some(lru) = lru {
+        buckets.remove(&lru);
+    }
+}
+
+/// Maps the string to [0, 1) by FNV-1a with the finalizer of MurmurHash3,
+/// which is stable across runs and platforms.
+fn hash_to_unit(s: &str) -> f64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in s.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^= hash >> 33;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Sampling rules by the init config, None for unchanged, applied only if the init succeeds.
+pub(crate) struct SamplingConfig(Option<Vec<SamplingRule>>);
+
+impl SamplingConfig {
+    pub(crate) fn apply(self) {
+        if let Some(rules) = self.0 {
+            *SAMPLER.lock().unwrap_or_else(PoisonError::into_inner) =
+                if rules.is_empty() { None } else { Some(Sampler::new(rules)) };
+        }
+    }
+}
+
+/// Parses the sampling rules by "sampling_rules" in the init config, a list of map with keys:
+///     - "event_name", "event_type": optional, to match the event, absent for any.
+///     - "ratio": optional, ratio of events to keep in (0, 1], stamped as "#sample_rate" on kept events.
+///     - "by_dt_id": optional, keeps all or none events of a "#dt_id" (default) or sampling per event,
+///       events without "#dt_id" are sampled per event anyway.
+///     - "rate_per_sec", "burst": optional, cap of events kept per second for each "#event_name".
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<SamplingConfig> {
+    match config.get("sampling_rules") {
+        None => Ok(SamplingConfig(None)),
+        Some(Value::Array(rules)) => Ok(SamplingConfig(Some(rules.iter()
+            .map(SamplingRule::from_config)
+            .collect::<Result<Vec<SamplingRule>>>()?))),
+        Some(_) => host_error!("\"sampling_rules\" should be a list of map!"),
+    }
+}
+
+
+/// Returns false if the event is sampled out or exceeds the rate cap.
+pub(crate) fn sample(event: &mut Event) -> Result<bool> {
+    let Ok(mut sampler) = SAMPLER.lock() else {
+        return internal_error!("Failed to get lock for sampler!");
+    };
+    if let Some(sampler) = sampler.as_mut() {
+        Ok(sampler.sample(event, Instant::now()))
+    } else {
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use serde_json::{json, Value};
+
+    use super::{MAX_BUCKETS, Sampler, SamplingRule};
+
+    fn rule(value: Value) -> SamplingRule {
+        SamplingRule::from_config(&value).expect("Rule is not valid")
+    }
+
+    fn event(name: &str, dt_id: &str, syn: &str) -> serde_json::Map<String, Value> {
+        json!({
+            "#event_name": name,
+            "#event_type": "track",
+            "#dt_id": dt_id,
+            "#event_syn": syn,
+            "properties": {}
+        }).as_object().unwrap().to_owned()
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(SamplingRule::from_config(&json!({"ratio": 0})).is_err());
+        assert!(SamplingRule::from_config(&json!({"ratio": 1.5})).is_err());
+        assert!(SamplingRule::from_config(&json!({"rate_per_sec": -1})).is_err());
+        assert!(SamplingRule::from_config(&json!({"event_name": 1})).is_err());
+        assert!(SamplingRule::from_config(&json!({"by_dt_id": "true"})).is_err());
+        assert!(SamplingRule::from_config(&json!([])).is_err());
+    }
+
+    #[test]
+    fn ratio_by_dt_id() {
+        let mut sampler = Sampler::new(vec![rule(json!({"event_name": "heartbeat", "ratio": 0.3}))]);
+        let now = Instant::now();
+
+        let mut kept_users = 0;
+        for user in 0..1000 {
+            let dt_id = format!("user_{user}");
+            let kept: Vec<bool> = (0..5)
+                .map(|i| sampler.sample(&mut event("heartbeat", &dt_id, &format!("{user}_{i}")), now))
+                .collect();
+            assert!(kept.iter().all(|it| *it == kept[0]), "User should be either fully in or out");
+            if kept[0] {
+                kept_users += 1;
+            }
+        }
+        assert!((200..400).contains(&kept_users), "Kept: {kept_users}");
+
+        // Other events are not affected, kept events are stamped.
+        let mut e = event("other", "user_0", "x");
+        assert!(sampler.sample(&mut e, now));
+        assert!(e["properties"].get("#sample_rate").is_none());
+        let mut kept = (0..100)
+            .map(|i| event("heartbeat", &format!("u{i}"), "x"))
+            .find_map(|mut e| if sampler.sample(&mut e, now) { Some(e) } else { None })
+            .expect("At least one should be kept");
+        assert_eq!(kept["properties"]["#sample_rate"].take(), json!(0.3));
+
+        // Per event without "#dt_id".
+        let kept = (0..1000)
+            .filter(|i| sampler.sample(&mut event("heartbeat", "", &format!("syn_{i}")), now))
+            .count();
+        assert!((200..400).contains(&kept), "Kept: {kept}");
+    }
+
+    #[test]
+    fn rate_cap() {
+        let mut sampler = Sampler::new(vec![rule(json!({"event_type": "track", "rate_per_sec": 2, "burst": 3}))]);
+        let now = Instant::now();
+
+        let kept = (0..10).filter(|_| sampler.sample(&mut event("a", "u", "x"), now)).count();
+        assert_eq!(kept, 3);
+        // Bucket is per event name.
+        assert!(sampler.sample(&mut event("b", "u", "x"), now));
+
+        let later = now + Duration::from_secs(1);
+        let kept = (0..10).filter(|_| sampler.sample(&mut event("a", "u", "x"), later)).count();
+        assert_eq!(kept, 2);
+
+        // Bounded, the idle ones are evicted first.
+        let later = later + Duration::from_secs(10);
+        for i in 0..2 * MAX_BUCKETS {
+            assert!(sampler.sample(&mut event(&format!("name_{i}"), "u", "x"), later));
+        }
+        assert!(sampler.buckets.len() <= MAX_BUCKETS);
+    }
+}
@@ -0,0 +1,51 @@
+use serde_json::Value;
+
+use crate::event::common_properties::Props;
+use crate::event::Event;
+
+pub const TYPE_TRACK: &str = "track";
+pub const TYPE_USER: &str = "user";
+
+/// Assembles the event from the flat properties, which may contain meta properties like "#event_time".
+/// Only the keys of custom properties are meaningful for "#user_unset", hence their values are replaced by 0.
+pub fn assemble(event_name: &str, event_type: &str, properties: Props, sdk_type: &str) -> Event {
+    let mut event = Event::with_capacity(properties.len() + 5);
+    let is_unset = event_name == "#user_unset";
+    for (k, v) in properties {
+        if is_unset && !k.starts_with('#') {
+            event.insert(k, Value::from(0));
+        } else {
+            event.insert(k, v);
+        }
+    }
+    event.insert(String::from("#event_name"), Value::from(event_name));
+    event.insert(String::from("#event_type"), Value::from(event_type));
+    event.insert(String::from("#sdk_type"), Value::from(sdk_type));
+    event
+}
+
+/// Fills "#dt_id" and "#acid" (if given) of the assembled event.
+pub(crate) fn fill_ids(event: &mut Event, dt_id: String, acid: Option<String>) {
+    event.insert(String::from("#dt_id"), Value::from(dt_id));
+    if let Some(acid) = acid {
+        event.insert(String::from("#acid"), Value::from(acid));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{assemble, fill_ids, TYPE_USER};
+
+    #[test]
+    fn assemble_event() {
+        let props = json!({"#event_time": 1700000000000u64, "coins": 10, "#os": "x"}).as_object().unwrap().to_owned();
+        let mut event = assemble("#user_unset", TYPE_USER, props, "my_sdk");
+        fill_ids(&mut event, String::from("dt1"), Some(String::new()));
+        assert_eq!(serde_json::Value::Object(event), json!({
+            "#event_time": 1700000000000u64, "coins": 0, "#os": "x",
+            "#event_name": "#user_unset", "#event_type": "user", "#sdk_type": "my_sdk", "#dt_id": "dt1", "#acid": ""
+        }));
+    }
+}
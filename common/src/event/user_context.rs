@@ -0,0 +1,81 @@
+use serde_json::Value;
+
+use crate::event::common_properties::Props;
+use crate::event::data_verification::verify_common_properties;
+use crate::event::Event;
+use crate::util::error::macros::verify_error;
+use crate::util::error::Result;
+
+/// Scope of a user, events added through it are filled with "#dt_id", "#acid" (if absent)
+/// and the user-scoped common properties, which take precedence over the dynamic and static ones.
+#[derive(Debug, Clone)]
+pub struct UserContext {
+    dt_id: String,
+    acid: Option<String>,
+    props: Props,
+}
+
+impl UserContext {
+    /// The properties are verified as common properties.
+    pub fn new(dt_id: String, acid: Option<String>, props: Props) -> Result<Self> {
+        if dt_id.is_empty() {
+            return verify_error!("#dt_id of user context cannot be empty!");
+        }
+        verify_common_properties(&props)?;
+        Ok(UserContext { dt_id, acid: acid.filter(|it| !it.is_empty()), props })
+    }
+
+    pub fn dt_id(&self) -> &str {
+        &self.dt_id
+    }
+
+    pub fn acid(&self) -> Option<&str> {
+        self.acid.as_deref()
+    }
+
+    pub fn props(&self) -> &Props {
+        &self.props
+    }
+
+    pub fn add(&self, mut event: Event) -> Result<()> {
+        self.fill_ids(&mut event);
+        crate::add_with_user_props(event, Some(&self.props))
+    }
+
+    fn fill_ids(&self, event: &mut Event) {
+        event.entry("#dt_id").or_insert_with(|| Value::from(self.dt_id.as_str()));
+        if let Some(acid) = &self.acid {
+            event.entry("#acid").or_insert_with(|| Value::from(acid.as_str()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::UserContext;
+    use crate::util::error::DTError;
+
+    #[test]
+    fn user_context() {
+        assert!(matches!(UserContext::new(String::new(), None, Default::default()), Err(DTError::VerifyError(_))));
+        let invalid = json!({"#sdk_type": "x"}).as_object().unwrap().to_owned();
+        assert!(matches!(UserContext::new(String::from("u1"), None, invalid), Err(DTError::VerifyError(_))));
+
+        let props = json!({"vip_level": 3}).as_object().unwrap().to_owned();
+        let ctx = UserContext::new(String::from("u1"), Some(String::new()), props).expect("Should be valid");
+        assert_eq!(ctx.acid(), None);
+
+        let mut event = json!({"#event_name": "a"}).as_object().unwrap().to_owned();
+        ctx.fill_ids(&mut event);
+        assert_eq!(event["#dt_id"], json!("u1"));
+        assert!(!event.contains_key("#acid"));
+
+        let ctx = UserContext::new(String::from("u1"), Some(String::from("ac1")), Default::default()).unwrap();
+        let mut event = json!({"#dt_id": "u2"}).as_object().unwrap().to_owned();
+        ctx.fill_ids(&mut event);
+        assert_eq!(event["#dt_id"], json!("u2"));
+        assert_eq!(event["#acid"], json!("ac1"));
+    }
+}
@@ -1,24 +1,34 @@
 use std::backtrace::Backtrace;
 use std::sync::atomic::Ordering;
 use std::sync::Once;
+use std::time::Duration;
 
 use serde_json::{Map, Value};
 
 use crate::base::mem;
 use crate::base::MemValue::Consumer as MemConsumer;
-use crate::consumer::Consumer;
+use crate::consumer::{CloseOutcome, Consumer};
 use crate::consumer::log::LogConsumer;
-use crate::event::common_properties::{clear_static_comm_props, Props, set_static_comm_props};
+use crate::event::common_properties::{clear_dynamic_comm_props, clear_static_comm_props, get_dynamic_comm_props, get_static_comm_props, merge_static_comm_props, Props, set_dynamic_comm_props, set_static_comm_prop, set_static_comm_props, unset_static_comm_prop};
+use crate::event::deduplication;
 use crate::event::Event;
 use crate::event::processing::{DEBUG, process_event};
+use crate::event::tracking::{assemble, fill_ids, TYPE_TRACK, TYPE_USER};
+use crate::event::user_context::UserContext;
+use crate::util::error::DTError;
 use crate::util::error::macros::{host_error, internal_error, runtime_error};
 use crate::util::error::Result;
+use crate::util::error_hook::ErrorCategory;
+use crate::util::metrics::Counter;
 
 pub mod util;
 mod base;
 pub mod consumer;
 pub mod event;
 pub(crate) mod upload;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
 
 static INITIALIZER: Once = Once::new();
 
@@ -33,30 +43,44 @@ pub fn init_by_config(mut config: Map<String, Value>) -> Result<()> {
         log_warning!("Running in benchmark mode!")
     });
 
+    let Ok(mut mem) = mem().lock() else {
+        return internal_error!("lock is reentered!");
+    };
+    if mem.contains_key(&consumer::MEM_KEY.to_string()) {
+        return runtime_error!("Consumer can only be initialized once.");
+    }
+
+    // Validate all, nothing global is changed if the init fails
+    let log_config = util::logger::parse_config(&config)?;
+    let metrics_config = util::metrics::parse_config(&config)?;
+    let event_config = event::parse_config(&config)?;
+
     // Init consumer
     let Some(Value::String(cn)) = config.get("consumer") else {
         return host_error!("Initialization config is missing 'consumer' or its type is not valid!")
     };
-    let consumer: Result<Box<dyn Consumer>> = match cn.to_lowercase().as_str() {
-        "log" => LogConsumer::from_config(&mut config),
+    let consumer: Box<dyn Consumer> = match cn.to_lowercase().as_str() {
+        "log" => LogConsumer::from_config(&mut config)?,
         _ => return host_error!("Initialization config has 'consumer' but it's out of domain!")
     };
 
+    // Nothing fails from here, the metrics server (opt-in) is started last
+    log_config.apply();
+    event_config.apply();
+    metrics_config.apply();
+
     // Insert to memory
-    let Ok(mut mem) = mem().lock() else {
-        return internal_error!("lock is reentered!");
-    };
-    if mem.contains_key(&consumer::MEM_KEY.to_string()) {
-        return runtime_error!("Consumer can only be initialized once.");
-    } else {
-        mem.insert(consumer::MEM_KEY.to_string(), MemConsumer(consumer?));
-        log_info!("Initialized!");
-    }
+    mem.insert(consumer::MEM_KEY.to_string(), MemConsumer(consumer));
+    log_info!("Initialized!");
 
     // After init success
     if let Some(Value::Bool(debug)) = config.get("_debug") {
         DEBUG.store(*debug, Ordering::Relaxed);
     }
+    #[cfg(feature = "benchmark")]
+    if let Some(Value::String(path)) = config.get("_benchmark_output") {
+        util::benchmark_tracer::BM_TRACER.set_output(path);
+    }
 
     Ok(())
 }
@@ -95,42 +119,149 @@ pub fn add(mut event: Event) -> Result<()> {
         let crt = crt.as_micros();
         if let Some(Value::String(time)) = event.remove("$_event_call_time") {
             if let Ok(st) = time.parse::<u128>() {
-                (&util::benchmark_tracer::BM_TRACER).add("Time used before add", crt-st);
+                (&util::benchmark_tracer::BM_TRACER).add("before_add", crt-st);
             }
         }
     }
 
     let st = std::time::Instant::now();
     let ret = add_aux(event);
-    (&util::benchmark_tracer::BM_TRACER).add("Time for add", st.elapsed().as_micros());
+    (&util::benchmark_tracer::BM_TRACER).add("add", st.elapsed().as_micros());
     ret
 }
 
 pub fn add_aux(event: Event) -> Result<()> {
+    add_with_user_props(event, None)
+}
+
+pub(crate) fn add_with_user_props(event: Event, user_props: Option<&Props>) -> Result<()> {
+    let kept = util::error_hook::is_hooked().then(|| util::error_hook::keep_event(&event));
+    // Before the lock, a slow provider doesn't block the other threads.
+    let dynamic_props = get_dynamic_comm_props();
+    let result = add_locked(event, user_props, dynamic_props.as_ref());
+    if let Err(e) = &result {
+        util::metrics::reject(e);
+        util::error_hook::report(e, kept.as_ref(), ErrorCategory::Add);
+    }
+    result
+}
+
+fn add_locked(event: Event, user_props: Option<&Props>, dynamic_props: Option<&Props>) -> Result<()> {
     let Ok(mut mem) = mem().lock() else {
         return internal_error!("lock is reentered!");
     };
 
     if let Some(MemConsumer(consumer)) = mem.get_mut(&consumer::MEM_KEY.to_string()) {
-        let event = process_event(event)?;
-        consumer.add(Box::new(event))
+        let syn = deduplication::get_host_syn(&event);
+        #[cfg(feature = "benchmark")]
+        let st = std::time::Instant::now();
+        let event = process_event(event, user_props, dynamic_props);
+        #[cfg(feature = "benchmark")]
+        (&util::benchmark_tracer::BM_TRACER).add("process_event", st.elapsed().as_micros());
+        let Some(event) = event? else {
+            return Ok(());
+        };
+        if let Some(syn) = &syn {
+            if !deduplication::check(syn)? {
+                return Ok(());
+            }
+        }
+        consumer.add(Box::new(event))?;
+        util::metrics::incr(Counter::EventsAccepted, 1);
+        if let Some(syn) = syn {
+            deduplication::record(syn)?;
+        }
+        Ok(())
     } else {
         runtime_error!("Consumer should be initialized before API calls!")
     }
 }
 
+/// Tracks an event, the properties could contain meta properties like "#event_time".
+/// "#sdk_type" identifies the binding or SDK calling this.
+pub fn track(dt_id: String, acid: Option<String>, event_name: &str, properties: Props, sdk_type: &str) -> Result<()> {
+    let mut event = assemble(event_name, TYPE_TRACK, properties, sdk_type);
+    fill_ids(&mut event, dt_id, acid);
+    add(event)
+}
+
+fn add_user_event(dt_id: String, acid: Option<String>, event_name: &str, properties: Props, sdk_type: &str) -> Result<()> {
+    let mut event = assemble(event_name, TYPE_USER, properties, sdk_type);
+    fill_ids(&mut event, dt_id, acid);
+    add(event)
+}
+
+/// Sets the user properties, overriding the existing ones.
+pub fn user_set(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_set", properties, sdk_type)
+}
+
+/// Sets the user properties, which are ignored if existing.
+pub fn user_set_once(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_set_once", properties, sdk_type)
+}
+
+/// Adds the user properties arithmetically, hence values of custom properties should be numbers.
+pub fn user_add(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_add", properties, sdk_type)
+}
+
+/// Unsets the user properties, only keys of custom properties are meaningful.
+pub fn user_unset(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_unset", properties, sdk_type)
+}
+
+/// Deletes the user.
+pub fn user_delete(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_delete", properties, sdk_type)
+}
+
+/// Appends to the user properties, hence values of custom properties should be lists.
+pub fn user_append(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_append", properties, sdk_type)
+}
+
+/// Appends to the user properties without duplications, hence values of custom properties should be lists.
+pub fn user_uniq_append(dt_id: String, acid: Option<String>, properties: Props, sdk_type: &str) -> Result<()> {
+    add_user_event(dt_id, acid, "#user_uniq_append", properties, sdk_type)
+}
+
 pub fn flush() -> Result<()> {
+    report_flush(flush_locked())
+}
+
+fn flush_locked() -> Result<()> {
     let Ok(mut mem) = mem().lock() else {
         return internal_error!("Something wrong, lock is reentered!");
     };
     if let Some(MemConsumer(consumer)) = mem.get_mut(&consumer::MEM_KEY.to_string()) {
-        consumer.flush()
+        consumer.flush()?;
+        deduplication::persist()
     } else {
         runtime_error!("Consumer should be initialized before API calls!")
     }
 }
 
 pub fn close() -> Result<()> {
+    close_with_timeout(Duration::MAX).map(|_| ())
+}
+
+/// Closes within the timeout: sends what can be sent in time, the remaining events are reported to the error hook
+/// as dropped. Returns the numbers of events sent and remaining while closing.
+/// The timeout only applies to the consumers uploading by workers, the "log" consumer writes all the events to the
+/// file before returning.
+pub fn close_with_timeout(timeout: Duration) -> Result<CloseOutcome> {
+    report_flush(close_locked(timeout))
+}
+
+fn report_flush<T>(result: Result<T>) -> Result<T> {
+    if let Err(e) = &result {
+        util::error_hook::report(e, None, ErrorCategory::Flush);
+    }
+    result
+}
+
+fn close_locked(timeout: Duration) -> Result<CloseOutcome> {
     let Ok(mut mem) = mem().lock() else {
         return internal_error!("Something wrong, lock is reentered!");
     };
@@ -139,18 +270,84 @@ pub fn close() -> Result<()> {
     util::benchmark_tracer::BM_TRACER.summary();
 
     if let Some(MemConsumer(mut consumer)) = mem.remove(&consumer::MEM_KEY.to_string()) {
-        let ret = consumer.close();
-        log_info!("Closed!");
+        let ret = consumer.close_timeout(timeout);
+        let persisted = deduplication::persist();
+        let ret = ret.and_then(|outcome| persisted.map(|_| outcome));
+        if let Ok(outcome) = &ret {
+            log_info!(sent = outcome.sent, remaining = outcome.remaining; "Closed!");
+        }
         ret
     } else {
         runtime_error!("Consumer should be initialized before API calls!")
     }
 }
 
+/// Returns a context to add events of the user, see [UserContext].
+pub fn with_user(dt_id: String, acid: Option<String>, props: Props) -> Result<UserContext> {
+    UserContext::new(dt_id, acid, props)
+}
+
 pub fn set_static_common_props(props: Props) -> Result<()> {
     set_static_comm_props(props)
 }
 
 pub fn clear_static_common_props() -> Result<()> {
     clear_static_comm_props()
-}
\ No newline at end of file
+}
+
+/// Merges into the existing static common properties rather than replacing them.
+pub fn merge_static_common_props(props: Props) -> Result<()> {
+    merge_static_comm_props(props)
+}
+
+pub fn get_static_common_props() -> Result<Props> {
+    get_static_comm_props()
+}
+
+pub fn set_static_common_prop(key: String, value: Value) -> Result<()> {
+    set_static_comm_prop(key, value)
+}
+
+pub fn unset_static_common_prop(key: &str) -> Result<()> {
+    unset_static_comm_prop(key)
+}
+
+/// Sets the provider of dynamic common properties, which is invoked for each event.
+/// The provider should be fast and must not call the APIs of SDK.
+pub fn set_dynamic_common_props<F>(provider: F) -> Result<()>
+    where F: Fn() -> Result<Map<String, Value>> + Send + Sync + 'static
+{
+    set_dynamic_comm_props(std::sync::Arc::new(provider))
+}
+
+pub fn clear_dynamic_common_props() -> Result<()> {
+    clear_dynamic_comm_props()
+}
+
+/// Sets the hook receiving each error with the event and where it occurred, e.g. to count the dropped events.
+/// The event is redacted to the meta properties identifying it if `redact` is true.
+/// The hook should be fast and must not call the APIs of SDK.
+pub fn set_error_hook<F>(hook: F, redact: bool) -> Result<()>
+    where F: Fn(&DTError, Option<&Event>, ErrorCategory) + Send + Sync + 'static
+{
+    util::error_hook::set_error_hook(std::sync::Arc::new(hook), redact)
+}
+
+pub fn clear_error_hook() -> Result<()> {
+    util::error_hook::clear_error_hook()
+}
+
+/// Snapshot of the runtime metrics, e.g. events accepted and rejected by reason, see [util::metrics::stats].
+pub fn stats() -> Map<String, Value> {
+    util::metrics::stats()
+}
+
+/// The runtime metrics in Prometheus text exposition format, see [util::metrics::render_prometheus].
+pub fn prometheus_metrics() -> String {
+    util::metrics::render_prometheus()
+}
+
+/// Serves the runtime metrics at "/metrics" on the address, see [util::metrics::serve_prometheus].
+pub fn serve_metrics(addr: &str) -> Result<std::net::SocketAddr> {
+    util::metrics::serve_prometheus(addr)
+}
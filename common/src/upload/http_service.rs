@@ -4,6 +4,8 @@ use reqwest::blocking::Client;
 use serde_json::{Map, Value};
 use crate::util::error::macros::{network_error, remote_error};
 use crate::util::error::{Result, DTError};
+use crate::util::metrics;
+use crate::util::metrics::Counter;
 
 #[cfg(all(feature = "network"))]
 #[derive(Debug)]
@@ -34,6 +36,18 @@ impl HttpService {
         url: &String, data: String,
         app_id: &String, data_count: usize, token: &String,
         sdk_type: &String, sdk_version: &String
+    ) -> Result<Map<String, Value>> {
+        metrics::incr(Counter::UploadAttempts, 1);
+        let result = self.send(url, data, app_id, data_count, token, sdk_type, sdk_version);
+        metrics::incr(if result.is_ok() { Counter::UploadSuccesses } else { Counter::UploadFailures }, 1);
+        result
+    }
+
+    fn send(
+        self: &'static Box<Self>,
+        url: &String, data: String,
+        app_id: &String, data_count: usize, token: &String,
+        sdk_type: &String, sdk_version: &String
     ) -> Result<Map<String, Value>> {
         let response = self.client
             .post(url)
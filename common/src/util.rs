@@ -5,5 +5,7 @@ pub(crate) mod data_struct;
 pub mod logger;
 pub mod error;
 pub mod result;
+pub mod error_hook;
+pub mod metrics;
 #[cfg(feature = "benchmark")]
 pub mod benchmark_tracer;
\ No newline at end of file
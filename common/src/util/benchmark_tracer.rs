@@ -1,40 +1,247 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::Mutex;
 use once_cell::sync::Lazy;
-use crate::log_warning;
+use serde_json::{json, Map, Value};
+use crate::{log_error, log_warning};
 
 #[cfg(feature = "benchmark")]
 pub static BM_TRACER: Lazy<BenchmarkTracer> = Lazy::new(|| BenchmarkTracer::new());
 
+/// Percentiles in the summary and the dumps.
+const PERCENTILES: [(&str, f64); 4] = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999)];
+
+// Values below 2 * SUB_BUCKETS are counted exactly, above that every power of two is split into SUB_BUCKETS,
+// which bounds the relative error to 1 / SUB_BUCKETS (~1.6%) like HDR histograms with 2 significant digits.
+const SUB_BUCKET_BITS: u32 = 6;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Histogram of samples in bounded memory (at most ~30KB for the full range of u64).
+#[derive(Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        let index = Self::index_of(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min = if self.count == 0 { value } else { self.min.min(value) };
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum += value as u128;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    /// Same as [Histogram::sum], but saturated to u64 for the dumps.
+    pub fn sum_u64(&self) -> u64 {
+        u64::try_from(self.sum).unwrap_or(u64::MAX)
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.sum as f64 / self.count as f64 }
+    }
+
+    /// The value at the quantile (0.0 to 1.0), as the highest value of its bucket, but no more than the max.
+    pub fn percentile(&self, quantile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::highest_of(index).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn index_of(value: u64) -> usize {
+        if value < 2 * SUB_BUCKETS {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+        ((shift as u64 + 1) * SUB_BUCKETS + (value >> shift) - SUB_BUCKETS) as usize
+    }
+
+    fn highest_of(index: usize) -> u64 {
+        let index = index as u64;
+        if index < 2 * SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / SUB_BUCKETS - 1;
+        let lowest = (index % SUB_BUCKETS + SUB_BUCKETS) << shift;
+        lowest.saturating_add((1 << shift) - 1)
+    }
+}
+
+/// Timings (µs) of the stages, e.g. "process_event", "verification", "serialization" and "file_write".
 #[cfg(feature = "benchmark")]
 pub struct BenchmarkTracer {
-    holder: Mutex<HashMap<String, Vec<u128>>>
+    holder: Mutex<BTreeMap<String, Histogram>>,
+    output: Mutex<Option<PathBuf>>,
 }
 
 impl BenchmarkTracer {
     pub fn new() -> Self {
         BenchmarkTracer {
-            holder: Mutex::new(HashMap::new())
+            holder: Mutex::new(BTreeMap::new()),
+            output: Mutex::new(None),
         }
     }
 
     pub fn add(self: &Self, key: &str, value: u128) {
         let mut guard = self.holder.lock().unwrap();
-        if let Some(vec) = guard.get_mut(key) {
-            vec.push(value);
+        let value = u64::try_from(value).unwrap_or(u64::MAX);
+        if let Some(histogram) = guard.get_mut(key) {
+            histogram.record(value);
         } else {
-            guard.insert(key.to_string(), vec![value]);
+            let mut histogram = Histogram::default();
+            histogram.record(value);
+            guard.insert(key.to_string(), histogram);
         }
     }
 
+    /// Where the summary is dumped, CSV if the path ends with ".csv", otherwise JSON.
+    pub fn set_output(&self, path: &str) {
+        self.output.lock().unwrap().replace(PathBuf::from(path));
+    }
+
+    /// Logs the summary of each stage, and dumps it to the output if set.
     pub fn summary(self: &Self) {
+        {
+            let guard = self.holder.lock().unwrap();
+            for (key, histogram) in guard.iter() {
+                let percentiles = PERCENTILES.iter()
+                    .map(|(name, quantile)| format!("{name}: {}µs", histogram.percentile(*quantile)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                log_warning!("{}: sum: {}µs ({}), avg: {:.1}µs, max: {}µs, min: {}µs, {}",
+                    key, histogram.sum(), histogram.count(), histogram.mean(), histogram.max(), histogram.min(), percentiles)
+            }
+        }
+
+        let Some(path) = self.output.lock().unwrap().clone() else {
+            return;
+        };
+        let content = if path.extension().is_some_and(|ext| ext == "csv") {
+            self.to_csv()
+        } else {
+            Value::Object(self.to_json()).to_string()
+        };
+        if let Err(e) = fs::write(&path, content) {
+            log_error!("Failed to dump benchmark to {path:?}, {e}");
+        }
+    }
+
+    /// Summary by stage, e.g. {"file_write": {"count": 10, "sum": 1200, "mean": 120.0, "min": 80, "max": 300, "p50": 100, ...}}.
+    pub fn to_json(&self) -> Map<String, Value> {
+        let guard = self.holder.lock().unwrap();
+        guard.iter().map(|(key, histogram)| {
+            let mut stage = json!({
+                "count": histogram.count(),
+                "sum": histogram.sum_u64(),
+                "mean": histogram.mean(),
+                "min": histogram.min(),
+                "max": histogram.max(),
+            });
+            for (name, quantile) in PERCENTILES {
+                stage[name] = Value::from(histogram.percentile(quantile));
+            }
+            (key.clone(), stage)
+        }).collect()
+    }
+
+    /// Summary by stage with the header "stage,count,sum,mean,min,max,p50,p90,p99,p999".
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("stage,count,sum,mean,min,max");
+        for (name, _) in PERCENTILES {
+            csv.push(',');
+            csv.push_str(name);
+        }
+        csv.push('\n');
         let guard = self.holder.lock().unwrap();
-        for (key, vec) in guard.iter() {
-            let sum: u128 = vec.iter().sum();
-            let avg = sum / vec.len() as u128;
-            let max = vec.iter().max().unwrap_or(&0);
-            let min = vec.iter().min().unwrap_or(&0);
-            log_warning!("{}: sum: {}µs ({}), avg: {}µs, max: {:?}µs, min: {:?}µs", key, sum, vec.len(), avg, max, min)
+        for (key, histogram) in guard.iter() {
+            let _ = write!(csv, "{},{},{},{:.1},{},{}", key, histogram.count(), histogram.sum_u64(), histogram.mean(), histogram.min(), histogram.max());
+            for (_, quantile) in PERCENTILES {
+                let _ = write!(csv, ",{}", histogram.percentile(quantile));
+            }
+            csv.push('\n');
         }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BenchmarkTracer, Histogram};
+
+    #[test]
+    fn histogram() {
+        let mut histogram = Histogram::default();
+        for value in 1..=10000 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.count(), 10000);
+        assert_eq!(histogram.min(), 1);
+        assert_eq!(histogram.max(), 10000);
+        assert_eq!(histogram.mean(), 5000.5);
+        for (quantile, expected) in [(0.5, 5000.0), (0.9, 9000.0), (0.99, 9900.0), (0.999, 9990.0)] {
+            let actual = histogram.percentile(quantile) as f64;
+            assert!(actual >= expected && actual <= expected * (1.0 + 1.0 / 64.0), "{quantile}: {actual}");
+        }
+        assert_eq!(histogram.percentile(1.0), 10000);
+        assert!(histogram.counts.len() < 1000);
+
+        let mut histogram = Histogram::default();
+        histogram.record(u64::MAX);
+        histogram.record(0);
+        assert_eq!(histogram.percentile(0.5), 0);
+        assert_eq!(histogram.percentile(1.0), u64::MAX);
+    }
+
+    #[test]
+    fn dump() {
+        let tracer = BenchmarkTracer::new();
+        tracer.add("file_write", 100);
+        tracer.add("file_write", 300);
+        let json = tracer.to_json();
+        assert_eq!(json["file_write"]["count"], 2);
+        assert_eq!(json["file_write"]["p50"], 100);
+        assert_eq!(json["file_write"]["p999"], 300);
+        assert_eq!(tracer.to_csv(), "stage,count,sum,mean,min,max,p50,p90,p99,p999\nfile_write,2,400,200.0,100,300,100,300,300,300\n");
+
+        // Saturated rather than truncated.
+        let tracer = BenchmarkTracer::new();
+        tracer.add("stuck", u64::MAX as u128);
+        tracer.add("stuck", u64::MAX as u128);
+        assert_eq!(tracer.to_json()["stuck"]["sum"], u64::MAX);
+        assert!(tracer.to_csv().contains(&format!("stuck,2,{},", u64::MAX)));
     }
 }
@@ -1 +1 @@
-pub(crate) mod ordered_linked_list;
\ No newline at end of file
+pub(crate) mod timer_heap;
\ No newline at end of file
@@ -0,0 +1,256 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+pub enum PoppedResult<T, O> {
+    Empty,
+    Unavailable(O),
+    Success(T),
+}
+
+#[derive(Clone)]
+struct Entry<T, O: Ord> {
+    elem: T,        // the handler of this message
+    order: O,       // when to handle this message
+    seq: u64,       // keeps FIFO among the same order
+}
+
+impl<T, O: Ord> PartialEq for Entry<T, O> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T, O: Ord> Eq for Entry<T, O> {}
+
+impl<T, O: Ord> PartialOrd for Entry<T, O> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, O: Ord> Ord for Entry<T, O> {
+    // Reversed, BinaryHeap is a max-heap.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.order.cmp(&self.order).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Elements ordered by `O` (e.g. the time to handle), FIFO among the same order. O(log n) to push and pop.
+#[derive(Clone)]
+pub struct TimerHeap<T, O: Ord> {
+    heap: BinaryHeap<Entry<T, O>>,
+    seq: u64,
+    // Upper bound of the orders in the heap, which is exact unless the greatest one has been popped,
+    // then it's equal to all remaining ones since the popped one is the least.
+    max_order: Option<O>,
+}
+
+impl<T, O: Ord + Default + Copy> TimerHeap<T, O> {
+    pub fn new() -> Self {
+        TimerHeap {
+            heap: BinaryHeap::new(),
+            seq: 0,
+            max_order: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn push(&mut self, elem: T) {
+        self.push_by(elem, O::default());
+    }
+
+    /// Pushes after all the elements in the heap.
+    #[allow(dead_code)]
+    pub fn push_end(&mut self, elem: T) {
+        let order = self.max_order.unwrap_or_default();
+        self.push_by(elem, order);
+    }
+
+    pub fn push_by(&mut self, elem: T, order: O) {
+        self.max_order = Some(self.max_order.map_or(order, |max| max.max(order)));
+        self.heap.push(Entry { elem, order, seq: self.seq });
+        self.seq = self.seq.wrapping_add(1);
+    }
+
+    #[allow(dead_code)]
+    pub fn pop(&mut self) -> PoppedResult<T, O> {
+        self.pop_aux(None)
+    }
+
+    #[allow(dead_code)]
+    pub fn pop_by(&mut self, target_order: O) -> PoppedResult<T, O> {
+        self.pop_aux(Some(target_order))
+    }
+
+    fn pop_aux(&mut self, target_order: Option<O>) -> PoppedResult<T, O> {
+        let Some(head) = self.heap.peek() else {
+            return PoppedResult::Empty;
+        };
+        if target_order.is_some_and(|target_order| head.order > target_order) {
+            return PoppedResult::Unavailable(head.order);
+        }
+        let Some(entry) = self.heap.pop() else {
+            return PoppedResult::Empty;
+        };
+        if self.heap.is_empty() {
+            self.max_order = None;
+        }
+        PoppedResult::Success(entry.elem)
+    }
+
+    /// Keeps only the elements matching the predicate, O(n).
+    #[allow(dead_code)]
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.heap.retain(|entry| f(&entry.elem));
+        self.max_order = self.heap.iter().map(|entry| entry.order).max();
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+impl<T, O: Ord + Default + Copy> Default for TimerHeap<T, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use super::{PoppedResult, TimerHeap};
+
+    fn drain(heap: &mut TimerHeap<usize, u64>) -> Vec<usize> {
+        let mut popped = Vec::new();
+        while let PoppedResult::Success(elem) = heap.pop() {
+            popped.push(elem);
+        }
+        popped
+    }
+
+    #[test]
+    fn it_works() {
+        let mut heap: TimerHeap<usize, u64> = TimerHeap::new();
+        assert!(matches!(heap.pop(), PoppedResult::Empty));
+
+        heap.push_by(1, 30);
+        heap.push_by(2, 10);
+        heap.push_by(3, 20);
+        heap.push(4);
+        heap.push_end(5);
+        heap.push_by(6, 10);
+        assert_eq!(heap.len(), 6);
+
+        assert!(matches!(heap.pop_by(0), PoppedResult::Success(4)));
+        assert!(matches!(heap.pop_by(5), PoppedResult::Unavailable(10)));
+        assert!(matches!(heap.pop_by(10), PoppedResult::Success(2)));
+        assert!(matches!(heap.pop_by(10), PoppedResult::Success(6)));
+        assert!(matches!(heap.pop_by(10), PoppedResult::Unavailable(20)));
+
+        let mut cloned = heap.clone();
+        assert_eq!(drain(&mut heap), vec![3, 1, 5]);
+        assert_eq!(drain(&mut cloned), vec![3, 1, 5]);
+        assert_eq!(heap.len(), 0);
+    }
+
+    #[test]
+    fn push_end() {
+        let mut heap: TimerHeap<usize, u64> = TimerHeap::new();
+        heap.push_by(1, 10);
+        heap.push_by(2, 20);
+        assert!(matches!(heap.pop(), PoppedResult::Success(1)));
+        heap.push_end(3);
+        heap.push_by(4, 20);
+        assert!(matches!(heap.pop(), PoppedResult::Success(2)));
+        assert!(matches!(heap.pop(), PoppedResult::Success(3)));
+        assert!(matches!(heap.pop(), PoppedResult::Success(4)));
+
+        // The greatest one popped, the rest are the same order.
+        heap.push_by(5, 30);
+        heap.push_by(6, 30);
+        assert!(matches!(heap.pop(), PoppedResult::Success(5)));
+        heap.push_end(7);
+        heap.push_by(8, 30);
+        assert_eq!(drain(&mut heap), vec![6, 7, 8]);
+
+        // Empty, from the default order.
+        heap.push_end(9);
+        heap.push_by(10, 1);
+        assert_eq!(drain(&mut heap), vec![9, 10]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut heap: TimerHeap<usize, u64> = TimerHeap::new();
+        heap.push_by(1, 10);
+        heap.push_by(2, 50);
+        heap.push_by(3, 20);
+        heap.retain(|elem| *elem != 2);
+        assert_eq!(heap.len(), 2);
+        heap.push_end(4);
+        heap.push_by(5, 30);
+        assert!(matches!(heap.pop_by(20), PoppedResult::Success(1)));
+        assert!(matches!(heap.pop_by(20), PoppedResult::Success(3)));
+        assert!(matches!(heap.pop_by(20), PoppedResult::Success(4)));
+        assert!(matches!(heap.pop_by(20), PoppedResult::Unavailable(30)));
+    }
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u64),
+        PushEnd,
+        PopBy(u64),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..20u64).prop_map(Op::Push),
+            Just(Op::PushEnd),
+            (0..20u64).prop_map(Op::PopBy),
+        ]
+    }
+
+    proptest! {
+        // Same as a stable sort by the order, with push_end after all the elements.
+        #[test]
+        #[cfg_attr(miri, ignore)]
+        fn same_as_sorted(ops in prop::collection::vec(op(), 0..200)) {
+            let mut heap: TimerHeap<usize, u64> = TimerHeap::new();
+            let mut model: Vec<(u64, usize)> = Vec::new();
+            for (id, op) in ops.into_iter().enumerate() {
+                match op {
+                    Op::Push(order) => {
+                        heap.push_by(id, order);
+                        let at = model.partition_point(|(o, _)| *o <= order);
+                        model.insert(at, (order, id));
+                    }
+                    Op::PushEnd => {
+                        heap.push_end(id);
+                        let order = model.last().map_or(0, |(o, _)| *o);
+                        model.push((order, id));
+                    }
+                    Op::PopBy(target) => {
+                        match (heap.pop_by(target), model.first().copied()) {
+                            (PoppedResult::Empty, None) => {}
+                            (PoppedResult::Unavailable(order), Some((o, _))) => {
+                                prop_assert!(o > target);
+                                prop_assert_eq!(order, o);
+                            }
+                            (PoppedResult::Success(elem), Some((o, expected))) => {
+                                prop_assert!(o <= target);
+                                prop_assert_eq!(elem, expected);
+                                model.remove(0);
+                            }
+                            _ => prop_assert!(false, "Unexpected result"),
+                        }
+                    }
+                }
+                prop_assert_eq!(heap.len(), model.len());
+            }
+            let expected: Vec<usize> = model.into_iter().map(|(_, id)| id).collect();
+            prop_assert_eq!(drain(&mut heap), expected);
+        }
+    }
+}
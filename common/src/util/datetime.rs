@@ -1,5 +1,5 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
 
 #[allow(dead_code)]
 pub(crate) fn get_time_since_epoch() -> Duration {
@@ -18,4 +18,67 @@ pub fn get_fmt_datetime() -> String {
 
 pub(crate) fn get_fmt_datetime_detail() -> String {
     Local::now().format("%+ (%s)").to_string()
-}
\ No newline at end of file
+}
+
+// Epoch below this is considered as in seconds, and above the upper one is considered as in microseconds.
+const EPOCH_SEC_UPPER_BOUND: u64 = 100_000_000_000;
+const EPOCH_MICROS_LOWER_BOUND: u64 = 100_000_000_000_000;
+
+/// Guesses the unit of given epoch (seconds, milliseconds or microseconds) and converts it to milliseconds.
+pub(crate) fn epoch_to_millis(epoch: u64) -> u64 {
+    if epoch < EPOCH_SEC_UPPER_BOUND {
+        epoch * 1000
+    } else if epoch >= EPOCH_MICROS_LOWER_BOUND {
+        epoch / 1000
+    } else {
+        epoch
+    }
+}
+
+/// Parses ISO-8601 datetime to milliseconds since epoch.
+/// Datetime without offset (or date only) is considered as in the local timezone.
+pub fn parse_iso8601_millis(s: &str) -> Option<i64> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.timestamp_millis());
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+        .ok()?;
+    Local.from_local_datetime(&naive).earliest().map(|dt| dt.timestamp_millis())
+}
+
+/// Formats milliseconds since epoch as ISO-8601 (RFC 3339) in UTC, e.g. "2024-01-01T00:00:00.000Z".
+pub fn fmt_millis_iso8601(millis: i64) -> Option<String> {
+    Utc.timestamp_millis_opt(millis).single().map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// Formats milliseconds since epoch in the format of date property expected by backend (local timezone).
+pub(crate) fn fmt_millis_for_date_prop(millis: i64) -> Option<String> {
+    Local.timestamp_millis_opt(millis).single().map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{epoch_to_millis, fmt_millis_iso8601, parse_iso8601_millis};
+
+    #[test]
+    fn iso8601() {
+        assert_eq!(parse_iso8601_millis("2024-01-02T03:04:05.678Z"), Some(1704164645678));
+        assert_eq!(parse_iso8601_millis("2024-01-02T11:04:05.678+08:00"), Some(1704164645678));
+        assert_eq!(parse_iso8601_millis("2024-01-02 03:04:05+00:00"), Some(1704164645000));
+        assert!(parse_iso8601_millis("2024-01-02T03:04:05.123456").is_some());
+        assert!(parse_iso8601_millis("2024-01-02").is_some());
+        assert_eq!(parse_iso8601_millis("2024-13-02"), None);
+        assert_eq!(parse_iso8601_millis("1704164645678"), None);
+
+        assert_eq!(fmt_millis_iso8601(1704164645678), Some(String::from("2024-01-02T03:04:05.678Z")));
+    }
+
+    #[test]
+    fn epoch_unit() {
+        assert_eq!(epoch_to_millis(1704164645), 1704164645000);
+        assert_eq!(epoch_to_millis(1704164645678), 1704164645678);
+        assert_eq!(epoch_to_millis(1704164645678123), 1704164645678);
+    }
+}
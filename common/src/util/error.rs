@@ -18,6 +18,42 @@ pub enum DTError {
     HostError(String),          // Errors causing by host/port.
 }
 
+/// Stable numeric codes of the errors, exposed to hosts.
+impl DTError {
+    pub const CODE_VERIFY: i32 = 1;
+    pub const CODE_INTERNAL: i32 = 2;
+    pub const CODE_RUNTIME: i32 = 3;
+    pub const CODE_NETWORK: i32 = 4;
+    pub const CODE_REMOTE: i32 = 5;
+    pub const CODE_HOST: i32 = 6;
+
+    /// Code of the error, or of its root cause if it's wrapped with context.
+    pub fn code(&self) -> i32 {
+        match self {
+            DTError::VerifyError(_) => Self::CODE_VERIFY,
+            DTError::InternalError(_) => Self::CODE_INTERNAL,
+            DTError::RuntimeError(_) => Self::CODE_RUNTIME,
+            DTError::NetworkError(_) => Self::CODE_NETWORK,
+            DTError::WithContext { cause, .. } => cause.code(),
+            DTError::RemoteError(_) => Self::CODE_REMOTE,
+            DTError::HostError(_) => Self::CODE_HOST,
+        }
+    }
+
+    /// Name of the kind, e.g. "verify", or of its root cause if it's wrapped with context.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DTError::VerifyError(_) => "verify",
+            DTError::InternalError(_) => "internal",
+            DTError::RuntimeError(_) => "runtime",
+            DTError::NetworkError(_) => "network",
+            DTError::WithContext { cause, .. } => cause.kind(),
+            DTError::RemoteError(_) => "remote",
+            DTError::HostError(_) => "host",
+        }
+    }
+}
+
 impl fmt::Display for DTError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -103,4 +139,21 @@ pub(crate) mod macros {
     pub(crate) use error_with;
     pub(crate) use remote_error;
     pub(crate) use host_error;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::DTError;
+
+    #[test]
+    fn error_code() {
+        assert_eq!(DTError::VerifyError(String::new()).code(), 1);
+        assert_eq!(DTError::HostError(String::new()).code(), 6);
+        let wrapped = DTError::WithContext {
+            context: String::from("ctx"),
+            cause: Box::new(DTError::WithContext { context: String::from("inner"), cause: Box::new(DTError::NetworkError(String::new())) }),
+        };
+        assert_eq!(wrapped.code(), DTError::CODE_NETWORK);
+        assert_eq!(wrapped.kind(), "network");
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::event::Event;
+use crate::log_error;
+use crate::util::error::DTError;
+use crate::util::error::macros::internal_error;
+use crate::util::error::Result;
+
+/// Where the error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The event is dropped while adding, e.g. it failed the verification.
+    Add,
+    /// Failed to flush or close, the event is absent.
+    Flush,
+    /// The consumer failed to write the event, which is dropped.
+    Write,
+}
+
+impl ErrorCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Add => "add",
+            ErrorCategory::Flush => "flush",
+            ErrorCategory::Write => "write",
+        }
+    }
+}
+
+/// Receives each error with the event if any, see [ErrorCategory].
+pub type ErrorHook = dyn Fn(&DTError, Option<&Event>, ErrorCategory) + Send + Sync;
+
+static ERROR_HOOK: RwLock<Option<Arc<ErrorHook>>> = RwLock::new(None);
+static REDACT_EVENT: AtomicBool = AtomicBool::new(false);
+
+// Meta properties kept in the redacted event.
+const REDACTED_KEEPS: [&str; 5] = ["#event_name", "#event_type", "#event_time", "#event_syn", "#sdk_type"];
+
+/// The hook is invoked by the thread hitting the error, maybe with the internal lock held.
+/// Hence, it should be fast and must not call the APIs of SDK.
+/// If `redact` is true, the event passed to the hook only keeps the meta properties identifying it,
+/// i.e. "#event_name", "#event_type", "#event_time", "#event_syn" and "#sdk_type".
+pub(crate) fn set_error_hook(hook: Arc<ErrorHook>, redact: bool) -> Result<()> {
+    let Ok(mut eh) = ERROR_HOOK.write() else {
+        return internal_error!("Failed to get lock for error hook!");
+    };
+    REDACT_EVENT.store(redact, Ordering::Relaxed);
+    eh.replace(hook);
+    Ok(())
+}
+
+pub(crate) fn clear_error_hook() -> Result<()> {
+    let Ok(mut eh) = ERROR_HOOK.write() else {
+        return internal_error!("Failed to get lock for error hook!");
+    };
+    eh.take();
+    Ok(())
+}
+
+/// Whether the hook is set, to avoid keeping a copy of the event needlessly.
+pub(crate) fn is_hooked() -> bool {
+    ERROR_HOOK.read().is_ok_and(|eh| eh.is_some())
+}
+
+/// Returns the event to keep for the hook, which is redacted if required.
+pub(crate) fn keep_event(event: &Event) -> Event {
+    if REDACT_EVENT.load(Ordering::Relaxed) {
+        redact(event)
+    } else {
+        event.clone()
+    }
+}
+
+fn redact(event: &Event) -> Event {
+    event.iter()
+        .filter(|(k, _)| REDACTED_KEEPS.contains(&k.as_str()))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+/// Passes the error to the hook if it's set, the event should be kept by [keep_event].
+pub(crate) fn report(e: &DTError, event: Option<&Event>, category: ErrorCategory) {
+    let hook = {
+        let Ok(eh) = ERROR_HOOK.read() else {
+            log_error!("Failed to get lock for error hook!");
+            return;
+        };
+        let Some(hook) = eh.clone() else {
+            return;
+        };
+        hook
+    };
+    hook(e, event, category);
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+
+    use super::{clear_error_hook, ErrorCategory, is_hooked, keep_event, report, set_error_hook};
+    use crate::util::error::DTError;
+
+    #[test]
+    fn error_hook() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        set_error_hook(Arc::new(move |e, event, category| {
+            sink.lock().unwrap().push((e.code(), event.cloned(), category));
+        }), true).unwrap();
+        assert!(is_hooked());
+
+        let event = json!({"#event_name": "a", "#dt_id": "u1", "#event_time": 1, "coins": 1}).as_object().unwrap().to_owned();
+        let kept = keep_event(&event);
+        assert_eq!(serde_json::Value::Object(kept.clone()), json!({"#event_name": "a", "#event_time": 1}));
+        report(&DTError::VerifyError(String::from("invalid")), Some(&kept), ErrorCategory::Add);
+        report(&DTError::InternalError(String::from("disk")), None, ErrorCategory::Flush);
+
+        clear_error_hook().unwrap();
+        assert!(!is_hooked());
+        report(&DTError::InternalError(String::from("ignored")), None, ErrorCategory::Flush);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0], (DTError::CODE_VERIFY, Some(kept), ErrorCategory::Add));
+        assert_eq!(received[1], (DTError::CODE_INTERNAL, None, ErrorCategory::Flush));
+    }
+}
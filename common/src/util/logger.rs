@@ -1,4 +1,16 @@
-use std::sync::atomic::{AtomicBool, AtomicI8};
+use std::fmt::Arguments;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Once, PoisonError, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicI8, Ordering};
+use log::{LevelFilter, Log, Metadata, Record};
+use log::kv::{Key, VisitSource, VisitValue};
+use serde_json::{Map, Value};
+use crate::util::error::macros::{host_error, internal_error};
+use crate::util::error::Result;
+
+pub use log::Level;
 
 #[allow(unused_imports)]
 pub static LOG_ENABLED: AtomicBool = AtomicBool::new(false);
@@ -7,46 +19,410 @@ pub static LOG_ENABLED: AtomicBool = AtomicBool::new(false);
 /// 0: Debug
 /// 1: Info
 /// 2: Warning
-/// Error
+/// 3: Error
+/// 4: Silent, even errors are not logged
 pub static LOG_LEVEL: AtomicI8 = AtomicI8::new(0);
 
+pub const LEVEL_DEBUG: i8 = 0;
+pub const LEVEL_INFO: i8 = 1;
+pub const LEVEL_WARNING: i8 = 2;
+pub const LEVEL_ERROR: i8 = 3;
+pub const LEVEL_SILENT: i8 = 4;
+
+/// Whether to format the logs as JSON lines, see [LogFormat].
+static LOG_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Target of the records sent to the `log` facade, for Rust hosts to filter by.
+pub const TARGET: &str = "dt_core";
+
 pub fn can_log(level: i8, ignore_enable: bool) -> bool {
-    (ignore_enable || LOG_ENABLED.load(std::sync::atomic::Ordering::Relaxed))
-        && level >=  LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed)
+    (ignore_enable || LOG_ENABLED.load(Ordering::Relaxed))
+        && level >=  LOG_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Format of the logs written to stderr and file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// e.g. "[DT Core | INFO | 2024-01-01 00:00:00.000000] Flushed 2 events!"
+    Text,
+    /// e.g. {"level":"info","timestamp":"2024-01-01T00:00:00.000Z","module":"common::consumer::log","message":"Flushed 2 events!","batch_size":2}
+    /// with the structured fields of the log flattened.
+    Json,
+}
+
+impl LogFormat {
+    /// Parses "text" or "json", case-insensitively.
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => host_error!("Unknown log format \"{s}\", expected \"text\" or \"json\""),
+        }
+    }
+}
+
+pub fn set_log_format(format: LogFormat) {
+    LOG_JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Sets the minimum level of logs, see [LOG_LEVEL].
+pub fn set_log_level(level: i8) {
+    LOG_LEVEL.store(level, Ordering::Relaxed);
+}
+
+/// Parses the named level, i.e. "debug", "info", "warning", "error" or "silent", case-insensitively.
+pub fn parse_log_level(name: &str) -> Result<i8> {
+    match name.to_lowercase().as_str() {
+        "debug" => Ok(LEVEL_DEBUG),
+        "info" => Ok(LEVEL_INFO),
+        "warning" | "warn" => Ok(LEVEL_WARNING),
+        "error" => Ok(LEVEL_ERROR),
+        "silent" => Ok(LEVEL_SILENT),
+        _ => host_error!("Unknown log level \"{name}\", expected \"debug\", \"info\", \"warning\", \"error\" or \"silent\""),
+    }
+}
+
+/// Logging settings in the init config, applied only if the init succeeds.
+pub(crate) struct LogConfig {
+    level: Option<i8>,
+    enabled: Option<bool>,
+}
+
+impl LogConfig {
+    pub(crate) fn apply(self) {
+        if let Some(level) = self.level {
+            set_log_level(level);
+        }
+        if let Some(enabled) = self.enabled {
+            LOG_ENABLED.store(enabled, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Parses the logging settings in the config:
+///     - "log_enabled": optional, whether to log below warning, same as toggling the logger.
+///     - "log_level": optional, named level, see [parse_log_level].
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<LogConfig> {
+    let level = match config.get("log_level") {
+        None => None,
+        Some(Value::String(name)) => Some(parse_log_level(name)?),
+        Some(_) => return host_error!("\"log_level\" should be a string!"),
+    };
+    let enabled = match config.get("log_enabled") {
+        None => None,
+        Some(Value::Bool(enabled)) => Some(*enabled),
+        Some(_) => return host_error!("\"log_enabled\" should be a boolean!"),
+    };
+    Ok(LogConfig { level, enabled })
+}
+
+/// Receives the level and the message of each log.
+pub type LogCallback = dyn Fn(Level, &str) + Send + Sync;
+
+/// Where the logs go, if the logger of SDK is in use, see [set_log_sink].
+pub enum LogSink {
+    Stderr,
+    File(RotatingFile),
+    /// Forwards to the host, e.g. Python `logging`. It may be invoked by any thread.
+    Callback(Box<LogCallback>),
+}
+
+impl LogSink {
+    /// Rotates the file by `max_bytes`, with at most `max_files` rotated ones kept.
+    pub fn file(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> Result<Self> {
+        let path = path.into();
+        match RotatingFile::new(&path, max_bytes, max_files) {
+            Ok(file) => Ok(LogSink::File(file)),
+            Err(e) => internal_error!("Failed to open log file {path:?}, {e}"),
+        }
+    }
+}
+
+static SINK: RwLock<Option<Arc<LogSink>>> = RwLock::new(None);
+static LOGGER: DTLogger = DTLogger;
+static INSTALLER: Once = Once::new();
+
+/// Sets where the logs go, stderr by default, and installs the logger of SDK like [install_default_logger].
+///
+/// Logs are sent to the `log` facade, the sink only takes effect if no logger was set by the host before.
+/// Otherwise, it's up to the logger of the host, with records in target [TARGET].
+pub fn set_log_sink(sink: LogSink) {
+    install_default_logger();
+    let mut guard = SINK.write().unwrap_or_else(PoisonError::into_inner);
+    guard.replace(Arc::new(sink));
+}
+
+/// Installs the logger of SDK as the one of `log` facade, unless one has been set. It's for the bindings, Rust hosts
+/// set up their own loggers instead, the logs are only sent to the facade until then.
+pub fn install_default_logger() {
+    INSTALLER.call_once(|| {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(LevelFilter::Trace);
+        }
+    });
+}
+
+/// Structured fields of the log, e.g. `("batch_size", &n)`.
+pub type Fields<'a> = [(&'a str, &'a dyn log::kv::ToValue)];
+
+#[doc(hidden)]
+pub fn __log(level: Level, args: Arguments, fields: &Fields, &(module_path, file, line): &(&'static str, &'static str, u32)) {
+    if level > log::max_level() {
+        return;
+    }
+    log::logger().log(&Record::builder()
+        .args(args)
+        .level(level)
+        .target(TARGET)
+        .key_values(&fields)
+        .module_path_static(Some(module_path))
+        .file_static(Some(file))
+        .line(Some(line))
+        .build());
+}
+
+/// Name of the level used by bindings, i.e. "debug", "info", "warning" or "error".
+pub fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warning",
+        Level::Info => "info",
+        Level::Debug | Level::Trace => "debug",
+    }
+}
+
+/// Formats the log as a line of text, e.g. "[DT Core | INFO | 2024-01-01 00:00:00.000000] Initialized!".
+pub fn fmt_text(level: Level, msg: &str) -> String {
+    format!("[DT Core | {} | {}] {}", level_name(level).to_uppercase(), crate::util::datetime::get_fmt_datetime(), msg)
+}
+
+/// Formats the record by [LogFormat], without the structured fields in text.
+fn fmt_record(record: &Record, msg: &str) -> String {
+    if !LOG_JSON.load(Ordering::Relaxed) {
+        return fmt_text(record.level(), msg);
+    }
+    let mut line = Map::new();
+    line.insert(String::from("level"), Value::from(level_name(record.level())));
+    let now = crate::util::datetime::get_time_since_epoch().as_millis() as i64;
+    line.insert(String::from("timestamp"), Value::from(crate::util::datetime::fmt_millis_iso8601(now)));
+    line.insert(String::from("module"), Value::from(record.module_path()));
+    line.insert(String::from("message"), Value::from(msg));
+    let _ = record.key_values().visit(&mut JsonFields(&mut line));
+    Value::Object(line).to_string()
+}
+
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: log::kv::Value<'kvs>) -> std::result::Result<(), log::kv::Error> {
+        let mut json = JsonValue(Value::Null);
+        value.visit(&mut json)?;
+        self.0.insert(key.to_string(), json.0);
+        Ok(())
+    }
+}
+
+struct JsonValue(Value);
+
+impl<'v> VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: log::kv::Value) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value);
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value);
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value);
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> std::result::Result<(), log::kv::Error> {
+        self.0 = Value::from(value);
+        Ok(())
+    }
+}
+
+struct DTLogger;
+
+impl Log for DTLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == TARGET
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let sink = SINK.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let msg = record.args().to_string();
+        match sink.as_deref() {
+            None | Some(LogSink::Stderr) => eprintln!("{}", fmt_record(record, &msg)),
+            Some(LogSink::File(file)) => {
+                let line = fmt_record(record, &msg);
+                if let Err(e) = file.write_line(&line) {
+                    eprintln!("{line}");
+                    eprintln!("{}", fmt_text(Level::Error, &format!("Failed to write log to {:?}, {e}", file.path)));
+                }
+            },
+            Some(LogSink::Callback(callback)) => callback(record.level(), &msg),
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(LogSink::File(file)) = SINK.read().unwrap_or_else(PoisonError::into_inner).as_deref() {
+            file.flush();
+        }
+    }
+}
+
+/// Log file rotated by size, the rotated ones are renamed to "{path}.1" (the latest) to "{path}.{max_files}".
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    // Opened file and its size.
+    state: Mutex<Option<(File, u64)>>,
+}
+
+impl RotatingFile {
+    /// Opens the file for appending, `max_files` is the number of rotated files to keep.
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> std::io::Result<Self> {
+        let path = path.into();
+        let opened = Self::open(&path)?;
+        Ok(RotatingFile { path, max_bytes, max_files, state: Mutex::new(Some(opened)) })
+    }
+
+    fn open(path: &PathBuf) -> std::io::Result<(File, u64)> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    fn write_line(&self, line: &str) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let len = line.len() as u64 + 1;
+        if let Some((_, size)) = state.as_ref() {
+            if *size > 0 && size + len > self.max_bytes {
+                state.take();
+                self.rotate()?;
+            }
+        }
+        if state.is_none() {
+            state.replace(Self::open(&self.path)?);
+        }
+        let Some((file, size)) = state.as_mut() else {
+            unreachable!()
+        };
+        writeln!(file, "{line}")?;
+        *size += len;
+        Ok(())
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.max_files == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        let _ = std::fs::remove_file(self.rotated(self.max_files));
+        for i in (1..self.max_files).rev() {
+            let from = self.rotated(i);
+            if from.exists() {
+                std::fs::rename(from, self.rotated(i + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated(1))
+    }
+
+    fn rotated(&self, i: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{i}"));
+        path.into()
+    }
+
+    fn flush(&self) {
+        if let Some((file, _)) = self.state.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            let _ = file.flush();
+        }
+    }
 }
 
+/// The macros accept structured fields before the message, e.g. `log_info!(batch_size = n; "Flushed {n} events!")`,
+/// which are carried by the records sent to the `log` facade and flattened in JSON format.
 pub mod logger {
     #[macro_export]
     macro_rules! log_debug {
+        ($($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+            if ($crate::util::logger::can_log(0, false)) {
+                $crate::util::logger::__log($crate::util::logger::Level::Debug, format_args!($($arg)+), &[$((stringify!($key), &$value)),+], &(module_path!(), file!(), line!()));
+            }
+        };
         ($($arg:tt)*) => {
             if ($crate::util::logger::can_log(0, false)) {
-                println!("[DT Core | DEBUG | {}] {}", $crate::util::datetime::get_fmt_datetime(), format!($($arg)*));
+                $crate::util::logger::__log($crate::util::logger::Level::Debug, format_args!($($arg)*), &[], &(module_path!(), file!(), line!()));
             }
         };
     }
 
     #[macro_export]
     macro_rules! log_info {
+        ($($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+            if ($crate::util::logger::can_log(1, false)) {
+                $crate::util::logger::__log($crate::util::logger::Level::Info, format_args!($($arg)+), &[$((stringify!($key), &$value)),+], &(module_path!(), file!(), line!()));
+            }
+        };
         ($($arg:tt)*) => {
             if ($crate::util::logger::can_log(1, false)) {
-                println!("[DT Core | INFO | {}] {}", $crate::util::datetime::get_fmt_datetime(), format!($($arg)*));
+                $crate::util::logger::__log($crate::util::logger::Level::Info, format_args!($($arg)*), &[], &(module_path!(), file!(), line!()));
             }
         };
     }
 
     #[macro_export]
     macro_rules! log_warning {
+        ($($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+            if ($crate::util::logger::can_log(2, true)) {
+                $crate::util::logger::__log($crate::util::logger::Level::Warn, format_args!($($arg)+), &[$((stringify!($key), &$value)),+], &(module_path!(), file!(), line!()));
+            }
+        };
         ($($arg:tt)*) => {
             if ($crate::util::logger::can_log(2, true)) {
-                println!("[DT Core | WARNING | {}] {}", $crate::util::datetime::get_fmt_datetime(), format!($($arg)*));
+                $crate::util::logger::__log($crate::util::logger::Level::Warn, format_args!($($arg)*), &[], &(module_path!(), file!(), line!()));
             }
         };
     }
 
     #[macro_export]
     macro_rules! log_error {
+        ($($key:ident = $value:expr),+ ; $($arg:tt)+) => {
+            if ($crate::util::logger::can_log(3, true)) {
+                $crate::util::logger::__log($crate::util::logger::Level::Error, format_args!($($arg)+), &[$((stringify!($key), &$value)),+], &(module_path!(), file!(), line!()));
+            }
+        };
         ($($arg:tt)*) => {
-            eprintln!("[DT Core | ERROR | {}] {}", $crate::util::datetime::get_fmt_datetime(), format!($($arg)*))
+            if ($crate::util::logger::can_log(3, true)) {
+                $crate::util::logger::__log($crate::util::logger::Level::Error, format_args!($($arg)*), &[], &(module_path!(), file!(), line!()));
+            }
         };
     }
 
@@ -54,4 +430,72 @@ pub mod logger {
     pub use log_info;
     pub use log_warning;
     pub use log_error;
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use log::Record;
+    use serde_json::Value;
+    use std::sync::atomic::Ordering;
+    use serde_json::json;
+    use super::{can_log, Fields, fmt_record, Level, LEVEL_SILENT, LOG_ENABLED, LOG_LEVEL, LogFormat, parse_config, parse_log_level, RotatingFile, set_log_format, TARGET};
+
+    #[test]
+    fn log_level() {
+        assert_eq!(parse_log_level("Warning").unwrap(), 2);
+        assert_eq!(parse_log_level("silent").unwrap(), LEVEL_SILENT);
+        assert!(parse_log_level("verbose").is_err());
+
+        let config = json!({"log_level": "silent", "log_enabled": true}).as_object().unwrap().to_owned();
+        parse_config(&config).unwrap().apply();
+        assert!(LOG_ENABLED.load(Ordering::Relaxed));
+        assert!(!can_log(3, true));
+        let config = json!({"log_level": "error", "log_enabled": false}).as_object().unwrap().to_owned();
+        parse_config(&config).unwrap().apply();
+        assert!(can_log(3, true));
+        assert!(!can_log(2, true));
+        assert!(parse_config(&json!({"log_level": 1}).as_object().unwrap().to_owned()).is_err());
+        LOG_LEVEL.store(0, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn json_format() {
+        assert_eq!(LogFormat::parse("JSON").unwrap(), LogFormat::Json);
+        assert!(LogFormat::parse("xml").is_err());
+
+        let fields: &Fields = &[("batch_size", &2usize), ("file_path", &"dt.log"), ("error_kind", &"internal")];
+        set_log_format(LogFormat::Json);
+        let line = fmt_record(&Record::builder()
+            .level(Level::Info)
+            .target(TARGET)
+            .module_path_static(Some("common::consumer::log"))
+            .key_values(&fields)
+            .build(), "Flushed 2 events!");
+        set_log_format(LogFormat::Text);
+
+        let line: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(line["level"], "info");
+        assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(line["module"], "common::consumer::log");
+        assert_eq!(line["message"], "Flushed 2 events!");
+        assert_eq!(line["batch_size"], 2);
+        assert_eq!(line["file_path"], "dt.log");
+        assert_eq!(line["error_kind"], "internal");
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = std::env::temp_dir().join(format!("dt_log_{}", uuid::Uuid::new_v4()));
+        let path = dir.join("sdk.log");
+        let file = RotatingFile::new(&path, 20, 2).unwrap();
+        for i in 0..4 {
+            file.write_line(&format!("line {i} of log")).unwrap();
+        }
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("sdk.log"), "line 3 of log\n");
+        assert_eq!(read("sdk.log.1"), "line 2 of log\n");
+        assert_eq!(read("sdk.log.2"), "line 1 of log\n");
+        assert!(!dir.join("sdk.log.3").exists());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
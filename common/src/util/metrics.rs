@@ -0,0 +1,295 @@
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+
+use crate::util::datetime::get_time_since_epoch;
+use crate::{log_error, log_info};
+use crate::util::error::DTError;
+use crate::util::error::macros::{host_error, internal_error, runtime_error};
+use crate::util::error::Result;
+
+/// Monotonic counters maintained by the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Counter {
+    /// Passed the processing and handed to the consumer.
+    EventsAccepted,
+    EventsSampledOut,
+    /// Dropped as repeated #event_syn.
+    EventsDeduplicated,
+    EventsWritten,
+    /// Dropped by the consumer, since it failed to write them.
+    EventsWriteFailed,
+    BytesWritten,
+    FilesRotated,
+    // Only counted with the feature "network".
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
+    UploadAttempts,
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
+    UploadSuccesses,
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
+    UploadFailures,
+}
+
+// Names and help texts of the counters, by the order of Counter.
+const COUNTER_NAMES: [(&str, &str); 10] = [
+    ("events_accepted", "Events passed the processing and handed to the consumer."),
+    ("events_sampled_out", "Events dropped by sampling or the rate cap."),
+    ("events_deduplicated", "Events dropped as repeated #event_syn."),
+    ("events_written", "Events written by the consumer."),
+    ("events_write_failed", "Events dropped since the consumer failed to write them."),
+    ("bytes_written", "Bytes written by the consumer."),
+    ("files_rotated", "Files rotated by the consumer, by time or size."),
+    ("upload_attempts", "Upload attempts."),
+    ("upload_successes", "Successful uploads."),
+    ("upload_failures", "Failed uploads."),
+];
+
+// Reasons of the rejected events, by the code of DTError (1-based).
+const REJECT_REASONS: [&str; 6] = ["verify", "internal", "runtime", "network", "remote", "host"];
+
+static COUNTERS: [AtomicU64; COUNTER_NAMES.len()] = [const { AtomicU64::new(0) }; COUNTER_NAMES.len()];
+static REJECTED: [AtomicU64; REJECT_REASONS.len()] = [const { AtomicU64::new(0) }; REJECT_REASONS.len()];
+// Events waiting in the consumer to be written or uploaded.
+static QUEUE_DEPTH: AtomicU64 = AtomicU64::new(0);
+// Milliseconds since epoch, 0 if never flushed.
+static LAST_FLUSH_TIME: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn incr(counter: Counter, n: u64) {
+    COUNTERS[counter as usize].fetch_add(n, Ordering::Relaxed);
+}
+
+/// Counts the event rejected while adding, by the kind of the error.
+pub(crate) fn reject(e: &DTError) {
+    let idx = (e.code() as usize).clamp(1, REJECT_REASONS.len()) - 1;
+    REJECTED[idx].fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn set_queue_depth(depth: usize) {
+    QUEUE_DEPTH.store(depth as u64, Ordering::Relaxed);
+}
+
+pub(crate) fn mark_flushed() {
+    LAST_FLUSH_TIME.store(get_time_since_epoch().as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Snapshot of the metrics, e.g.
+/// ```json
+/// {
+///     "events_accepted": 10, "events_rejected": {"verify": 1, "internal": 0, ...}, "events_sampled_out": 2,
+///     "events_deduplicated": 0, "events_written": 10, "events_write_failed": 0, "bytes_written": 2048,
+///     "files_rotated": 1, "upload_attempts": 0, "upload_successes": 0, "upload_failures": 0,
+///     "queue_depth": 0, "last_flush_time": 1704067200000
+/// }
+/// ```
+/// Counters are monotonic since the process started, "last_flush_time" is in milliseconds since epoch (0 if never).
+pub fn stats() -> Map<String, Value> {
+    let mut stats = Map::with_capacity(COUNTER_NAMES.len() + 3);
+    for ((name, _), counter) in COUNTER_NAMES.iter().zip(COUNTERS.iter()) {
+        stats.insert(name.to_string(), Value::from(counter.load(Ordering::Relaxed)));
+    }
+    let rejected = REJECT_REASONS.iter().zip(REJECTED.iter())
+        .map(|(reason, counter)| (reason.to_string(), Value::from(counter.load(Ordering::Relaxed))))
+        .collect::<Map<String, Value>>();
+    stats.insert(String::from("events_rejected"), Value::Object(rejected));
+    stats.insert(String::from("queue_depth"), Value::from(QUEUE_DEPTH.load(Ordering::Relaxed)));
+    stats.insert(String::from("last_flush_time"), Value::from(LAST_FLUSH_TIME.load(Ordering::Relaxed)));
+    stats
+}
+
+/// Renders the metrics in Prometheus text exposition format (0.0.4), with the names prefixed by "dt_", e.g.
+/// ```text
+/// # HELP dt_events_accepted_total Events passed the processing and handed to the consumer.
+/// # TYPE dt_events_accepted_total counter
+/// dt_events_accepted_total 10
+/// ```
+/// The rejected events are labeled by reason, e.g. `dt_events_rejected_total{reason="verify"} 1`.
+pub fn render_prometheus() -> String {
+    let mut text = String::with_capacity(2048);
+    for ((name, help), counter) in COUNTER_NAMES.iter().zip(COUNTERS.iter()) {
+        let _ = writeln!(text, "# HELP dt_{name}_total {help}");
+        let _ = writeln!(text, "# TYPE dt_{name}_total counter");
+        let _ = writeln!(text, "dt_{name}_total {}", counter.load(Ordering::Relaxed));
+    }
+    text.push_str("# HELP dt_events_rejected_total Events rejected while adding, by reason.\n");
+    text.push_str("# TYPE dt_events_rejected_total counter\n");
+    for (reason, counter) in REJECT_REASONS.iter().zip(REJECTED.iter()) {
+        let _ = writeln!(text, "dt_events_rejected_total{{reason=\"{reason}\"}} {}", counter.load(Ordering::Relaxed));
+    }
+    text.push_str("# HELP dt_queue_depth Events waiting in the consumer to be written or uploaded.\n");
+    text.push_str("# TYPE dt_queue_depth gauge\n");
+    let _ = writeln!(text, "dt_queue_depth {}", QUEUE_DEPTH.load(Ordering::Relaxed));
+    text.push_str("# HELP dt_last_flush_timestamp_seconds Time of the last flush in seconds since epoch, 0 if never.\n");
+    text.push_str("# TYPE dt_last_flush_timestamp_seconds gauge\n");
+    let _ = writeln!(text, "dt_last_flush_timestamp_seconds {:.3}", LAST_FLUSH_TIME.load(Ordering::Relaxed) as f64 / 1000.0);
+    text
+}
+
+// Requested and bound addresses of the running metrics server, which differ with port 0.
+static SERVER_ADDR: Mutex<Option<(SocketAddr, SocketAddr)>> = Mutex::new(None);
+
+/// Serves the metrics at "/metrics" by a tiny HTTP server in a background thread, e.g. on "127.0.0.1:9464".
+/// Returns the bound address, which is useful with port 0. Only one server is allowed, calling again with the same
+/// address (the requested or the bound one) is a no-op.
+pub fn serve_prometheus(addr: &str) -> Result<SocketAddr> {
+    match bind(addr)? {
+        MetricsServer::Running(bound) => Ok(bound),
+        MetricsServer::Bound { requested, listener } => serve(requested, listener),
+    }
+}
+
+// The server running on the same address, or the listener bound but not served yet.
+enum MetricsServer {
+    Running(SocketAddr),
+    Bound { requested: SocketAddr, listener: TcpListener },
+}
+
+fn bind(addr: &str) -> Result<MetricsServer> {
+    let Ok(server_addr) = SERVER_ADDR.lock() else {
+        return internal_error!("Failed to get lock for metrics server!");
+    };
+    let requested = addr.parse::<SocketAddr>();
+    if let Some((running_requested, running)) = server_addr.as_ref() {
+        return match requested {
+            Ok(requested) if requested == *running_requested || requested == *running => Ok(MetricsServer::Running(*running)),
+            _ => runtime_error!("Metrics server is already running on {running}!"),
+        };
+    }
+    let Ok(requested) = requested else {
+        return host_error!("Invalid address for metrics server: \"{addr}\"!");
+    };
+    match TcpListener::bind(requested) {
+        Ok(listener) => Ok(MetricsServer::Bound { requested, listener }),
+        Err(e) => internal_error!("Failed to bind metrics server on {addr}, {e}"),
+    }
+}
+
+fn serve(requested: SocketAddr, listener: TcpListener) -> Result<SocketAddr> {
+    let Ok(mut server_addr) = SERVER_ADDR.lock() else {
+        return internal_error!("Failed to get lock for metrics server!");
+    };
+    if let Some((_, running)) = server_addr.as_ref() {
+        return runtime_error!("Metrics server is already running on {running}!");
+    }
+    let bound = match listener.local_addr() {
+        Ok(bound) => bound,
+        Err(e) => return internal_error!("Failed to bind metrics server on {requested}, {e}"),
+    };
+    let spawned = std::thread::Builder::new()
+        .name(String::from("dt-metrics-server"))
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream) {
+                    log_error!("Metrics server failed to respond, {e}");
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        return internal_error!("Failed to start metrics server, {e}");
+    }
+    server_addr.replace((requested, bound));
+    Ok(bound)
+}
+
+// The metrics server if "metrics_addr" is configured, e.g. "127.0.0.1:9464". The address is bound while parsing, so
+// it's the only failure (e.g. address in use) to report, then it's served once the init succeeds.
+pub(crate) struct MetricsConfig(Option<MetricsServer>);
+
+impl MetricsConfig {
+    pub(crate) fn apply(self) {
+        if let Some(MetricsServer::Bound { requested, listener }) = self.0 {
+            match serve(requested, listener) {
+                Ok(bound) => log_info!("Serving metrics on http://{bound}/metrics"),
+                Err(e) => log_error!("Failed to serve metrics on {requested}, {e}"),
+            }
+        }
+    }
+}
+
+pub(crate) fn parse_config(config: &Map<String, Value>) -> Result<MetricsConfig> {
+    match config.get("metrics_addr") {
+        None => Ok(MetricsConfig(None)),
+        Some(Value::String(addr)) => Ok(MetricsConfig(Some(bind(addr)?))),
+        Some(_) => host_error!("\"metrics_addr\" should be a string!"),
+    }
+}
+
+// Connections are handled one by one, which is enough for scraping.
+fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    while len < buf.len() && !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", render_prometheus()),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", String::from("Not Found\n")),
+    };
+    write!(stream, "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use super::{Counter, incr, reject, render_prometheus, serve_prometheus, stats};
+    use crate::util::error::DTError;
+
+    #[test]
+    fn metrics() {
+        let get = |stats: &serde_json::Map<String, Value>, key: &str| stats[key].as_u64().unwrap();
+        let before = stats();
+        incr(Counter::BytesWritten, 100);
+        incr(Counter::UploadFailures, 1);
+        reject(&DTError::VerifyError(String::from("invalid")));
+        reject(&DTError::WithContext { context: String::from("ctx"), cause: Box::new(DTError::HostError(String::new())) });
+        let after = stats();
+
+        // Counters may be increased by other tests concurrently.
+        assert!(get(&after, "bytes_written") >= get(&before, "bytes_written") + 100);
+        assert!(get(&after, "upload_failures") > get(&before, "upload_failures"));
+        assert!(after["events_rejected"]["verify"].as_u64() > before["events_rejected"]["verify"].as_u64());
+        assert!(after["events_rejected"]["host"].as_u64() > before["events_rejected"]["host"].as_u64());
+        assert!(after.contains_key("queue_depth"));
+        assert!(after.contains_key("last_flush_time"));
+    }
+
+    #[test]
+    fn prometheus() {
+        incr(Counter::EventsAccepted, 1);
+        let text = render_prometheus();
+        assert!(text.contains("# TYPE dt_events_accepted_total counter\n"));
+        assert!(text.contains("dt_events_rejected_total{reason=\"verify\"} "));
+        assert!(text.contains("# TYPE dt_queue_depth gauge\n"));
+        assert!(text.lines().all(|line| line.starts_with('#') || line.split(' ').count() == 2));
+
+        let addr = serve_prometheus("127.0.0.1:0").unwrap();
+        assert!(serve_prometheus("127.0.0.1:1").is_err());
+        assert_eq!(serve_prometheus("127.0.0.1:0").unwrap(), addr);
+        assert_eq!(serve_prometheus(&addr.to_string()).unwrap(), addr);
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("dt_events_accepted_total "));
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -1,18 +1,37 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::log_error;
+use crate::util::error::{DTError, Result};
 
-pub fn dissolve_bool<T, E>(result: crate::util::error::Result<T>) -> Result<bool, E> {
+/// If enabled, errors are returned for the bindings to raise in host, see `DTError::code`.
+/// Otherwise, they're logged and the bindings return false (or default value), which is the default.
+pub static THROW_ON_ERROR: AtomicBool = AtomicBool::new(false);
+
+pub fn dissolve_bool<T>(result: Result<T>) -> Result<bool> {
     match result {
         Ok(_) => Ok(true),
-        Err(e) => {
-            log_error!("{e}");
-            Ok(false)
-        }
+        Err(e) => dissolve_err(e).map(|_| false),
+    }
+}
+
+pub fn dissolve<T>(result: Result<T>) -> Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => dissolve_err(e),
     }
 }
 
-pub fn dissolve<T, E>(result: crate::util::error::Result<T>) -> Result<(), E> {
-    if let Err(e) = result {
-        log_error!("{e}");
+pub fn dissolve_or_default<T: Default>(result: Result<T>) -> Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(e) => dissolve_err(e).map(|_| T::default()),
     }
-    Ok(())
-}
\ No newline at end of file
+}
+
+fn dissolve_err(e: DTError) -> Result<()> {
+    if THROW_ON_ERROR.load(Ordering::Relaxed) {
+        Err(e)
+    } else {
+        log_error!(error_kind = e.kind(); "{e}");
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::util::data_struct::ordered_linked_list::{OrderedLinkedList, PoppedResult};
+use crate::util::data_struct::timer_heap::{PoppedResult, TimerHeap};
 
 pub const FLAG_DEFAULT: usize = 0b00000000;
 pub const FLAG_TERMINATE: usize = 0b00000001;
@@ -26,52 +28,281 @@ impl<F> Task for F where F: FnOnce() {
 
 pub type RawTask = dyn Task + Send + 'static;
 pub type Message = Box<RawTask>;
+pub type RecurringTask = dyn Fn() + Send + Sync + 'static;
+
+/// Among the tasks due, the higher priority runs first, FIFO among the same priority.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+/// Handle to cancel a scheduled task, a recurring one stops recurring.
+#[derive(Clone, Default)]
+pub struct TaskHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TaskHandle {
+    /// Cancels the task if it's not running yet, which is dropped without running.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+enum Job {
+    Once(Message),
+    Recurring(Arc<RecurringTask>, u128),
+}
+
+pub struct Entry {
+    job: Job,
+    priority: Priority,
+    handle: TaskHandle,
+}
+
+impl Entry {
+    pub fn get_flag(&self) -> usize {
+        match &self.job {
+            Job::Once(task) => task.get_flag(),
+            Job::Recurring(..) => FLAG_DEFAULT,
+        }
+    }
+
+    /// Runs the task, returns itself if it's recurring, which should be passed to [MessageQueue::reschedule].
+    pub fn run(self, id: &usize) -> Option<Entry> {
+        match self.job {
+            Job::Once(task) => {
+                task.run(id);
+                None
+            }
+            Job::Recurring(ref task, _) => {
+                task();
+                Some(self)
+            }
+        }
+    }
+}
 
 pub struct MessageQueue {
-    list: OrderedLinkedList<Message, u128>
+    // Waiting for the time
+    timers: TimerHeap<Entry, u128>,
+    // Due, by priority
+    ready: TimerHeap<Entry, Priority>,
+    closed: bool,
+}
+
+fn get_crt_time() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went back!").as_millis()
 }
 
 impl MessageQueue {
     pub fn new() -> Self {
         MessageQueue {
-            list: OrderedLinkedList::new()
+            timers: TimerHeap::new(),
+            ready: TimerHeap::new(),
+            closed: false,
         }
     }
 
     #[allow(dead_code)]
-    pub fn schedule<T>(&mut self, handler: T)
+    pub fn schedule<T>(&mut self, handler: T) -> TaskHandle
         where T: Task + Send + 'static
     {
-        self.schedule_delayed(handler, 0);
+        self.schedule_delayed(handler, 0)
     }
 
-    pub fn schedule_delayed<T>(&mut self, handler: T, delay_ms: u128)
+    pub fn schedule_delayed<T>(&mut self, handler: T, delay_ms: u128) -> TaskHandle
         where T: Task + Send + 'static
     {
-        let crt_time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went back!").as_millis();
-        self.list.push_by(Box::new(handler), crt_time + delay_ms);
+        self.schedule_with_priority(handler, delay_ms, Priority::Normal)
+    }
+
+    pub fn schedule_with_priority<T>(&mut self, handler: T, delay_ms: u128, priority: Priority) -> TaskHandle
+        where T: Task + Send + 'static
+    {
+        self.push(Job::Once(Box::new(handler)), get_crt_time() + delay_ms, priority)
+    }
+
+    /// Runs the task after the delay, then again each period after the last run finished, until cancelled or closed.
+    pub fn schedule_recurring<F>(&mut self, handler: F, delay_ms: u128, period_ms: u128, priority: Priority) -> TaskHandle
+        where F: Fn() + Send + Sync + 'static
+    {
+        self.push(Job::Recurring(Arc::new(handler), period_ms), get_crt_time() + delay_ms, priority)
     }
 
-    pub fn schedule_to_end<T>(&mut self, handler: T)
+    /// Schedules after all the pending tasks, with the lowest priority.
+    pub fn schedule_to_end<T>(&mut self, handler: T) -> TaskHandle
         where T: Task + Send + 'static
     {
-        self.list.push_end(Box::new(handler));
+        // Or the cancelled ones would hold it back till their time.
+        self.timers.retain(|entry| !entry.handle.is_cancelled());
+        let handle = TaskHandle::default();
+        let entry = Entry { job: Job::Once(Box::new(handler)), priority: Priority::Low, handle: handle.clone() };
+        self.timers.push_end(entry);
+        handle
     }
 
-    pub fn pop(&mut self) -> PoppedResult<Message, u128> {
-        let crt_time = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went back!").as_millis();
-        let result = self.list.pop_by(crt_time);
-        if let PoppedResult::Unavailable(target) = result {
-            PoppedResult::Unavailable(target - crt_time)
-        } else {
-            result
+    fn push(&mut self, job: Job, time: u128, priority: Priority) -> TaskHandle {
+        let handle = TaskHandle::default();
+        self.timers.push_by(Entry { job, priority, handle: handle.clone() }, time);
+        handle
+    }
+
+    /// Puts the recurring task back for the next period, unless it's cancelled or the queue is closed.
+    pub fn reschedule(&mut self, entry: Entry) {
+        if self.closed || entry.handle.is_cancelled() {
+            return;
+        }
+        if let Job::Recurring(_, period) = entry.job {
+            self.timers.push_by(entry, get_crt_time() + period);
+        }
+    }
+
+    /// Stops the recurring tasks, the one-shot tasks are kept to run.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.timers.retain(|entry| matches!(entry.job, Job::Once(_)) && !entry.handle.is_cancelled());
+        self.ready.retain(|entry| matches!(entry.job, Job::Once(_)));
+    }
+
+    /// Drops all the pending tasks, returns the number of them except the terminations.
+    pub fn abort(&mut self) -> usize {
+        self.closed = true;
+        let mut dropped = 0;
+        let mut count = |entry: &Entry| {
+            if !has_flag(entry.get_flag(), FLAG_TERMINATE) {
+                dropped += 1;
+            }
+            false
+        };
+        self.timers.retain(&mut count);
+        self.ready.retain(&mut count);
+        dropped
+    }
+
+    /// Pops the due task with the highest priority, or the delay (ms) to the next one.
+    pub fn pop(&mut self) -> PoppedResult<Entry, u128> {
+        let crt_time = get_crt_time();
+        while let PoppedResult::Success(entry) = self.timers.pop_by(crt_time) {
+            if !entry.handle.is_cancelled() {
+                let priority = entry.priority;
+                self.ready.push_by(entry, priority);
+            }
+        }
+        while let PoppedResult::Success(entry) = self.ready.pop() {
+            if !entry.handle.is_cancelled() {
+                return PoppedResult::Success(entry);
+            }
+        }
+        match self.timers.pop_by(crt_time) {
+            PoppedResult::Unavailable(target) => PoppedResult::Unavailable(target - crt_time),
+            _ => PoppedResult::Empty,
         }
     }
 
+    /// Number of the pending tasks, including the cancelled ones not dropped yet.
     pub fn len(&self) -> usize {
-        self.list.len()
+        self.timers.len() + self.ready.len()
     }
 }
 
-unsafe impl Sync for MessageQueue {}
-unsafe impl Send for MessageQueue {}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::thread::sleep;
+    use std::time::Duration;
+    use crate::util::data_struct::timer_heap::PoppedResult;
+    use super::{MessageQueue, Priority};
+
+    fn run_due(queue: &mut MessageQueue) -> Option<u128> {
+        loop {
+            match queue.pop() {
+                PoppedResult::Success(entry) => {
+                    if let Some(entry) = entry.run(&0) {
+                        queue.reschedule(entry);
+                    }
+                }
+                PoppedResult::Unavailable(delay) => return Some(delay),
+                PoppedResult::Empty => return None,
+            }
+        }
+    }
+
+    fn record(log: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl FnOnce() + Send + 'static {
+        let log = log.clone();
+        move || log.lock().unwrap().push(name)
+    }
+
+    #[test]
+    fn ordering() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut queue = MessageQueue::new();
+        queue.schedule_with_priority(record(&log, "upload"), 0, Priority::Low);
+        queue.schedule(record(&log, "normal_1"));
+        queue.schedule_with_priority(record(&log, "flush"), 0, Priority::High);
+        queue.schedule(record(&log, "normal_2"));
+        queue.schedule_delayed(record(&log, "delayed"), 10_000);
+        queue.schedule_to_end(record(&log, "end"));
+        queue.schedule_with_priority(record(&log, "high_delayed"), 10_000, Priority::High);
+        assert!(run_due(&mut queue).is_some_and(|delay| delay > 5_000));
+        assert_eq!(*log.lock().unwrap(), vec!["flush", "normal_1", "normal_2", "upload"]);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn cancellation() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut queue = MessageQueue::new();
+        queue.schedule(record(&log, "cancelled")).cancel();
+        let kept = queue.schedule(record(&log, "kept"));
+        let retry = queue.schedule_delayed(record(&log, "retry"), 60_000);
+        retry.cancel();
+        // The cancelled retry doesn't hold back the end.
+        queue.schedule_to_end(record(&log, "end"));
+        assert_eq!(run_due(&mut queue), None);
+        assert_eq!(*log.lock().unwrap(), vec!["kept", "end"]);
+        assert!(!kept.is_cancelled() && retry.is_cancelled());
+    }
+
+    #[test]
+    fn abort() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut queue = MessageQueue::new();
+        queue.schedule(record(&log, "due"));
+        queue.schedule_delayed(record(&log, "delayed"), 60_000);
+        queue.schedule_recurring(|| {}, 0, 1, Priority::High);
+        assert_eq!(queue.abort(), 3);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(run_due(&mut queue), None);
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn recurring() {
+        let count = Arc::new(Mutex::new(0));
+        let mut queue = MessageQueue::new();
+        let counter = count.clone();
+        let handle = queue.schedule_recurring(move || *counter.lock().unwrap() += 1, 0, 1, Priority::Normal);
+        for _ in 0..3 {
+            assert!(run_due(&mut queue).is_some());
+            sleep(Duration::from_millis(2));
+        }
+        assert_eq!(*count.lock().unwrap(), 3);
+        handle.cancel();
+        assert_eq!(run_due(&mut queue), None);
+        assert_eq!(*count.lock().unwrap(), 3);
+
+        let counter = count.clone();
+        queue.schedule_recurring(move || *counter.lock().unwrap() += 1, 0, 1, Priority::Normal);
+        queue.close();
+        assert_eq!(run_due(&mut queue), None);
+        assert_eq!(*count.lock().unwrap(), 3);
+    }
+}
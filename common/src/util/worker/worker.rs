@@ -1,10 +1,10 @@
-use std::sync::{Arc, Barrier, Mutex};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Barrier, Condvar, Mutex};
 use std::thread;
 use std::thread::{JoinHandle};
-use std::time::{Duration};
-use crate::util::data_struct::ordered_linked_list::PoppedResult;
-use crate::util::worker::message_queue::{MessageQueue, Task, FLAG_TERMINATE, FLAG_BARRIER, has_flag};
+use std::time::{Duration, Instant};
+use crate::log_warning;
+use crate::util::data_struct::timer_heap::PoppedResult;
+use crate::util::worker::message_queue::{MessageQueue, Priority, Task, TaskHandle, FLAG_TERMINATE, FLAG_BARRIER, has_flag};
 
 struct Terminate {}
 impl Task for Terminate {
@@ -24,6 +24,26 @@ impl Task for BarrierTask {
 }
 
 
+// The queue, and the signal to the workers waiting for it.
+struct Shared {
+    queue: Mutex<MessageQueue>,
+    signal: Condvar,
+    // Number of the running workers, and the signal when one exits.
+    alive: Mutex<usize>,
+    exited: Condvar,
+}
+
+// Counts the worker out when its thread ends.
+struct Alive(Arc<Shared>);
+impl Drop for Alive {
+    fn drop(&mut self) {
+        if let Ok(mut alive) = self.0.alive.lock() {
+            *alive -= 1;
+        }
+        self.0.exited.notify_all();
+    }
+}
+
 #[allow(dead_code)]
 struct Worker {
     id: usize,
@@ -31,48 +51,35 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<Receiver<usize>>>, queue: Arc<Mutex<MessageQueue>>) -> Self {
+    fn new(id: usize, shared: Arc<Shared>) -> Self {
         Worker {
             id,
-            thread: Some(Self::build_thread(id.clone(), receiver, queue)),
+            thread: Some(Self::build_thread(id, shared)),
         }
     }
 
-    fn build_thread(id: usize, receiver: Arc<Mutex<Receiver<usize>>>, queue: Arc<Mutex<MessageQueue>>) -> JoinHandle<()> {
+    fn build_thread(id: usize, shared: Arc<Shared>) -> JoinHandle<()> {
         thread::spawn(move || {
+            let _alive = Alive(shared.clone());
+            let mut queue = shared.queue.lock().unwrap();
             loop {
-                let result = { queue.lock().unwrap().pop() };
-                match result {
+                match queue.pop() {
                     PoppedResult::Empty => {
-                        //println!("Worker#{}: Queue is empty, wait", id);
-                        if let Ok(sig) = receiver.lock().unwrap().recv() {
-                            //println!("Worker#{}: Empty and received signal: {}", id, sig);
-                            if has_flag(sig, FLAG_TERMINATE) {
-                                break;
-                            }
-                            continue;
-                        } else {
-                            //break;
-                        }
+                        queue = shared.signal.wait(queue).unwrap();
                     },
                     PoppedResult::Unavailable(delay) => {
-                        //println!("Worker#{}: Has task but not ready, wait for {}ms", id, delay);
-                        if let Ok(_) = receiver.lock().unwrap().recv_timeout(Duration::from_millis(delay as u64)) {
-                            //println!("Worker#{}: Unavailable and received signal: {}", id, sig);
-                            continue;
-                        } else {
-                            //break;
-                        }
+                        queue = shared.signal.wait_timeout(queue, Duration::from_millis(delay as u64)).unwrap().0;
                     },
-                    PoppedResult::Success(task) => {
-                        let flag = task.get_flag();
-                        //println!("Worker#{}: Got a task (flag: {})", id, flag);
-                        task.run(&id);
-                        match flag {
-                            n if has_flag(n, FLAG_TERMINATE) => {
-                                break;
-                            },
-                            _ => (),
+                    PoppedResult::Success(entry) => {
+                        drop(queue);
+                        let flag = entry.get_flag();
+                        let recurring = entry.run(&id);
+                        if has_flag(flag, FLAG_TERMINATE) {
+                            break;
+                        }
+                        queue = shared.queue.lock().unwrap();
+                        if let Some(entry) = recurring {
+                            queue.reschedule(entry);
                         }
                     },
                 }
@@ -84,51 +91,63 @@ impl Worker {
 #[allow(dead_code)]
 pub struct WorkerManager {
     name: String,
-    sender: Sender<usize>,      // Signal sender
     workers: Vec<Worker>,
     size: usize,
-    queue: Arc<Mutex<MessageQueue>>,
+    shared: Arc<Shared>,
 }
 
 impl WorkerManager {
     pub fn new(name: String, size: usize) -> Self {
         assert!(size > 0);
 
-        let queue = Arc::new(Mutex::new(MessageQueue::new()));
-
-        let (sender, receiver) = channel::<usize>();
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver), queue.clone()))
-        }
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(MessageQueue::new()),
+            signal: Condvar::new(),
+            alive: Mutex::new(size),
+            exited: Condvar::new(),
+        });
+        let workers = (0..size).map(|id| Worker::new(id, shared.clone())).collect();
 
         WorkerManager {
             name,
-            sender,
             workers,
             size,
-            queue
+            shared,
         }
     }
 
-    pub fn schedule<T: Task + Send + 'static>(&mut self, task: T) {
-        self.schedule_delayed(task, 0);
+    pub fn schedule<T: Task + Send + 'static>(&mut self, task: T) -> TaskHandle {
+        self.schedule_delayed(task, 0)
+    }
+
+    pub fn schedule_delayed<T: Task + Send + 'static>(&mut self, task: T, delay: u128) -> TaskHandle {
+        self.schedule_with_priority(task, delay, Priority::Normal)
     }
 
-    pub fn schedule_delayed<T: Task + Send + 'static>(&mut self, task: T, delay: u128) {
-        self.queue.lock().unwrap().schedule_delayed(task, delay);
-        let _ = self.sender.send(0);
+    /// Among the tasks due, the higher priority runs first, e.g. flushing before uploading.
+    pub fn schedule_with_priority<T: Task + Send + 'static>(&mut self, task: T, delay: u128, priority: Priority) -> TaskHandle {
+        let handle = self.shared.queue.lock().unwrap().schedule_with_priority(task, delay, priority);
+        self.shared.signal.notify_one();
+        handle
     }
 
+    /// Runs the task after the delay, then again each period after the last run finished,
+    /// until cancelled by the handle or shutdown.
     #[allow(dead_code)]
-    pub fn schedule_end<T: Task + Send + 'static>(&mut self, task: T) {
-        self.schedule_end_flag(task, 0);
+    pub fn schedule_recurring<F>(&mut self, task: F, delay: u128, period: u128, priority: Priority) -> TaskHandle
+        where F: Fn() + Send + Sync + 'static
+    {
+        let handle = self.shared.queue.lock().unwrap().schedule_recurring(task, delay, period, priority);
+        self.shared.signal.notify_one();
+        handle
     }
 
-    fn schedule_end_flag<T: Task + Send + 'static>(&mut self, task: T, flag: usize) {
-        self.queue.lock().unwrap().schedule_to_end(task);
-        let _ = self.sender.send(flag);
+    /// Runs the task after all the pending tasks.
+    #[allow(dead_code)]
+    pub fn schedule_end<T: Task + Send + 'static>(&mut self, task: T) -> TaskHandle {
+        let handle = self.shared.queue.lock().unwrap().schedule_to_end(task);
+        self.shared.signal.notify_one();
+        handle
     }
 
     pub fn place_barrier(&mut self) {
@@ -137,7 +156,7 @@ impl WorkerManager {
         for _ in 0..self.size {
             let c = Arc::clone(&barrier);
             let task = BarrierTask { barrier: c };
-            self.schedule_end_flag(task, FLAG_BARRIER);
+            self.schedule_end(task);
         }
         barrier.wait();
         //println!("WorkerManager({}): Rendezvoused", self.name)
@@ -145,28 +164,70 @@ impl WorkerManager {
 
     #[allow(dead_code)]
     pub fn queue_len(&self) -> usize {
-        self.queue.lock().unwrap().len()
+        self.shared.queue.lock().unwrap().len()
     }
 
     pub fn size(&self) -> usize {
         self.size
     }
 
+    /// Stops the recurring tasks, then waits for the pending one-shot tasks to finish,
+    /// the ones should not run on shutdown (e.g. retries) should be cancelled beforehand.
     pub fn shutdown(&mut self) {
+        self.shutdown_timeout(Duration::MAX);
+    }
+
+    /// Same as [WorkerManager::shutdown], but waits no longer than the timeout. At the deadline, the pending tasks
+    /// are dropped without running, and the workers still running a task are detached.
+    /// Returns whether all the workers stopped in time.
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> bool {
+        if self.workers.iter().all(|worker| worker.thread.is_none()) {
+            return true;
+        }
         //println!("WorkerManager({}): Sending terminate message to all workers.", self.name);
+        self.shared.queue.lock().unwrap().close();
         for _ in 0..self.size {
-            self.schedule_end_flag(Terminate {}, FLAG_TERMINATE);
+            self.schedule_end(Terminate {});
         }
 
         //println!("WorkerManager({}): Shutting down all workers.", self.name);
+        let deadline = Instant::now().checked_add(timeout);
+        let mut alive = self.shared.alive.lock().unwrap();
+        while *alive > 0 {
+            alive = match deadline {
+                None => self.shared.exited.wait(alive).unwrap(),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        break;
+                    }
+                    self.shared.exited.wait_timeout(alive, left).unwrap().0
+                }
+            };
+        }
+        let running = *alive;
+        drop(alive);
 
+        if running > 0 {
+            let dropped = self.shared.queue.lock().unwrap().abort();
+            // The idle workers exit at once, the busy ones once their tasks finish.
+            for _ in 0..self.size {
+                self.schedule_end(Terminate {});
+            }
+            self.shared.signal.notify_all();
+            log_warning!("WorkerManager({}): {} workers are still running after {:?}, detached, {} pending tasks dropped",
+                self.name, running, timeout, dropped);
+        }
         for worker in &mut self.workers {
             //println!("WorkerManager({}): Shutting down worker {}", self.name, worker.id);
             if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
+                if running == 0 {
+                    thread.join().unwrap();
+                }
                 //println!("WorkerManager({}): Terminated Worker#{}", self.name, worker.id);
             }
         }
+        running == 0
     }
 }
 
@@ -179,6 +240,11 @@ impl Drop for WorkerManager {
 
 #[cfg(test)]
 mod test {
+    use std::sync::{Arc, Barrier, mpsc, Mutex};
+    use std::sync::mpsc::TryRecvError;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use crate::util::worker::message_queue::Priority;
     use super::WorkerManager;
 
     #[test]
@@ -216,4 +282,115 @@ mod test {
         schedule_num(&mut wm, 3);
         drop(wm);
     }
+
+    fn record(log: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl FnOnce() + Send + 'static {
+        let log = log.clone();
+        move || log.lock().unwrap().push(name)
+    }
+
+    // Blocks the only worker till the returned barrier is waited, to queue up the tasks.
+    fn hold(wm: &mut WorkerManager) -> Arc<Barrier> {
+        let gate = Arc::new(Barrier::new(2));
+        let held = gate.clone();
+        wm.schedule(move || {
+            held.wait();
+        });
+        gate
+    }
+
+    #[test]
+    fn test_priority() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut wm = WorkerManager::new("priority".to_string(), 1);
+        let gate = hold(&mut wm);
+        wm.schedule_with_priority(record(&log, "upload"), 0, Priority::Low);
+        wm.schedule(record(&log, "normal"));
+        wm.schedule_with_priority(record(&log, "flush"), 0, Priority::High);
+        gate.wait();
+        wm.place_barrier();
+        assert_eq!(*log.lock().unwrap(), vec!["flush", "normal", "upload"]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut wm = WorkerManager::new("cancel".to_string(), 1);
+        let gate = hold(&mut wm);
+        let cancelled = wm.schedule(record(&log, "cancelled"));
+        wm.schedule(record(&log, "kept"));
+        cancelled.cancel();
+        gate.wait();
+        wm.place_barrier();
+        assert_eq!(*log.lock().unwrap(), vec!["kept"]);
+    }
+
+    #[test]
+    fn test_recurring() {
+        let count = Arc::new(Mutex::new(0));
+        let mut wm = WorkerManager::new("recurring".to_string(), 2);
+        let counter = count.clone();
+        let handle = wm.schedule_recurring(move || *counter.lock().unwrap() += 1, 0, 5, Priority::Normal);
+        sleep(Duration::from_millis(100));
+        handle.cancel();
+        wm.place_barrier();
+        let ran = *count.lock().unwrap();
+        assert!(ran >= 2, "ran {ran} times");
+        sleep(Duration::from_millis(20));
+        assert_eq!(*count.lock().unwrap(), ran);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let count = Arc::new(Mutex::new(0));
+        let mut wm = WorkerManager::new("shutdown".to_string(), 2);
+        let counter = count.clone();
+        wm.schedule_recurring(move || *counter.lock().unwrap() += 1, 60_000, 60_000, Priority::Normal);
+        wm.schedule_delayed(record(&log, "pending"), 20);
+        let retry = wm.schedule_delayed(record(&log, "retry"), 60_000);
+        retry.cancel();
+
+        // Neither the recurring task nor the cancelled retry holds back the shutdown.
+        let st = Instant::now();
+        wm.shutdown();
+        assert!(st.elapsed() < Duration::from_secs(10));
+        assert_eq!(*log.lock().unwrap(), vec!["pending"]);
+        assert_eq!(*count.lock().unwrap(), 0);
+        assert_eq!(wm.queue_len(), 0);
+        // Dropping after shutdown is a no-op.
+        drop(wm);
+    }
+
+    #[test]
+    fn test_shutdown_timeout() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut wm = WorkerManager::new("shutdown_timeout".to_string(), 2);
+        wm.schedule(record(&log, "done"));
+        wm.place_barrier();
+        // Met twice by the stuck task, once it's running and once it's released.
+        let barrier = Arc::new(Barrier::new(2));
+        let stuck = barrier.clone();
+        let (left_tx, left_rx) = mpsc::channel();
+        wm.schedule(move || {
+            stuck.wait();
+            stuck.wait();
+            left_tx.send(()).unwrap();
+        });
+        let (pending_tx, pending_rx) = mpsc::channel::<()>();
+        wm.schedule_delayed(move || pending_tx.send(()).unwrap(), 60_000);
+        barrier.wait();
+
+        assert!(!wm.shutdown_timeout(Duration::ZERO));
+        // The pending task is dropped without running.
+        assert_eq!(pending_rx.try_recv(), Err(TryRecvError::Disconnected));
+        barrier.wait();
+        left_rx.recv().unwrap();
+        assert_eq!(*log.lock().unwrap(), vec!["done"]);
+        assert!(wm.shutdown_timeout(Duration::ZERO));
+
+        let mut wm = WorkerManager::new("shutdown_in_time".to_string(), 2);
+        wm.schedule_delayed(record(&log, "in_time"), 10);
+        assert!(wm.shutdown_timeout(Duration::from_secs(10)));
+        assert_eq!(*log.lock().unwrap(), vec!["done", "in_time"]);
+    }
 }
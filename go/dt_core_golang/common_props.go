@@ -0,0 +1,76 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+*/
+import "C"
+
+import (
+	"runtime"
+	"unsafe"
+
+	jsoniter "github.com/json-iterator/go"
+)
+
+// SetStaticCommonProperties replaces all the static common properties, which will be added to each event.
+func (_ DTAnalytics) SetStaticCommonProperties(properties map[string]interface{}) error {
+	return callWithProps(properties, func(cProps *C.char) C.int8_t {
+		return C.dt_set_static_common_props(cProps)
+	})
+}
+
+// MergeStaticCommonProperties merges into the static common properties, the given ones override those with the same key.
+// Hence, different modules can contribute their own common properties without clobbering each other.
+func (_ DTAnalytics) MergeStaticCommonProperties(properties map[string]interface{}) error {
+	return callWithProps(properties, func(cProps *C.char) C.int8_t {
+		return C.dt_merge_static_common_props(cProps)
+	})
+}
+
+// UnsetStaticCommonProperty removes the static common property with given key.
+func (_ DTAnalytics) UnsetStaticCommonProperty(key string) error {
+	cKey := C.CString(key)
+	defer C.free(unsafe.Pointer(cKey))
+	return callC(func() C.int8_t {
+		return C.dt_unset_static_common_prop(cKey)
+	}, "failed to unset static common property")
+}
+
+// GetStaticCommonProperties returns a copy of the static common properties.
+func (_ DTAnalytics) GetStaticCommonProperties() (map[string]interface{}, error) {
+	runtime.LockOSThread()
+	cProps := C.dt_get_static_common_props()
+	if cProps == nil {
+		err := lastError("failed to get static common properties")
+		runtime.UnlockOSThread()
+		return nil, err
+	}
+	runtime.UnlockOSThread()
+	defer C.dt_free_string(cProps)
+
+	props := make(map[string]interface{})
+	if err := jsoniter.UnmarshalFromString(C.GoString(cProps), &props); err != nil {
+		return nil, err
+	}
+	return props, nil
+}
+
+// ClearStaticCommonProperties removes all the static common properties.
+func (_ DTAnalytics) ClearStaticCommonProperties() error {
+	return callC(func() C.int8_t {
+		return C.dt_clear_static_common_props()
+	}, "failed to clear static common properties")
+}
+
+func callWithProps(properties map[string]interface{}, f func(cProps *C.char) C.int8_t) error {
+	b, err := jsoniter.Marshal(properties)
+	if err != nil {
+		return err
+	}
+	cProps := C.CString(string(b))
+	defer C.free(unsafe.Pointer(cProps))
+	return callC(func() C.int8_t {
+		return f(cProps)
+	}, "given common properties are not valid")
+}
@@ -15,8 +15,8 @@ package dt_analytics
 import "C"
 
 import (
-	"errors"
 	jsoniter "github.com/json-iterator/go"
+	"time"
 	"unsafe"
 )
 
@@ -44,13 +44,11 @@ func New(consumer DTConsumer, isDebug bool) (DTAnalytics, error) {
 	configCStr := C.CString(configStr)
 	defer C.free(unsafe.Pointer(configCStr))
 
-	ret := C.dt_init(configCStr)
+	err := callC(func() C.int8_t {
+		return C.dt_init(configCStr)
+	}, "failed to init DTAnalytics")
 	clear(configMap)
-	if ret != 0 {
-		return DTAnalytics{}, nil
-	} else {
-		return DTAnalytics{}, errors.New("failed to init DTAnalytics")
-	}
+	return DTAnalytics{}, err
 }
 
 // Track an event (custom or preset).
@@ -58,7 +56,11 @@ func New(consumer DTConsumer, isDebug bool) (DTAnalytics, error) {
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) Track(dtId string, acId string, eventName string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, eventName, "track", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		cEventName := C.CString(eventName)
+		defer C.free(unsafe.Pointer(cEventName))
+		return C.dt_track(cDtId, cAcId, cEventName, cProps, cSdkType)
+	})
 }
 
 // UserSet Set user properties for the user with given dtId and acId.
@@ -66,7 +68,9 @@ func (dta DTAnalytics) Track(dtId string, acId string, eventName string, propert
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserSet(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_set", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_set(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserSetOnce Set user properties only once for user with given dtId and acId.
@@ -75,7 +79,9 @@ func (dta DTAnalytics) UserSet(dtId string, acId string, properties map[string]i
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserSetOnce(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_set_once", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_set_once(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserAdd Arithmetic add the value of property by given number for user with given dtId and acId.
@@ -84,7 +90,9 @@ func (dta DTAnalytics) UserSetOnce(dtId string, acId string, properties map[stri
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserAdd(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_add", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_add(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserUnset Unset properties for user with given dtId and acId.
@@ -93,7 +101,9 @@ func (dta DTAnalytics) UserAdd(dtId string, acId string, properties map[string]i
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserUnset(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_unset", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_unset(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserDelete Delete the user with given dtId and acId.
@@ -101,7 +111,9 @@ func (dta DTAnalytics) UserUnset(dtId string, acId string, properties map[string
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserDelete(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_delete", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_delete(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserAppend Append values to property for the user with given dtId and acId.
@@ -110,7 +122,9 @@ func (dta DTAnalytics) UserDelete(dtId string, acId string, properties map[strin
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserAppend(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_append", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_append(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
 // UserUniqAppend Append values to property without duplications for the user with given dtId and acId.
@@ -119,45 +133,90 @@ func (dta DTAnalytics) UserAppend(dtId string, acId string, properties map[strin
 //   - nil if given event and properties is valid, or
 //   - error if there are invalid and will not be processed.
 func (dta DTAnalytics) UserUniqAppend(dtId string, acId string, properties map[string]interface{}) error {
-	return dta.add(dtId, acId, "#user_uniq_append", "user", properties)
+	return callWithUserArgs(dtId, acId, properties, func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t {
+		return C.dt_user_uniq_append(cDtId, cAcId, cProps, cSdkType)
+	})
 }
 
-func (_ DTAnalytics) add(dtId string, acId string, eventName string, eventType string, properties map[string]interface{}) error {
-	event := make(map[string]interface{}, len(properties)+6)
-
-	for k, v := range properties {
-		event[k] = v
+func callWithUserArgs(dtId string, acId string, properties map[string]interface{}, f func(cDtId *C.char, cAcId *C.char, cProps *C.char, cSdkType *C.char) C.int8_t) error {
+	if properties == nil {
+		properties = map[string]interface{}{}
 	}
-	event["#dt_id"] = dtId
-	event["#acid"] = acId
-	event["#event_name"] = eventName
-	event["#event_type"] = eventType
-	event["#sdk_type"] = _sdkType
-
-	b, err := jsoniter.Marshal(event)
+	b, err := jsoniter.Marshal(properties)
 	if err != nil {
 		return err
 	}
-	eventJson := string(b)
-	cEventJson := C.CString(eventJson)
-	ret := C.dt_add_event(cEventJson)
-	clear(event)
-
-	if ret != 0 {
-		return nil
-	} else {
-		return errors.New("given event is not valid")
-	}
+	cDtId := C.CString(dtId)
+	defer C.free(unsafe.Pointer(cDtId))
+	cAcId := C.CString(acId)
+	defer C.free(unsafe.Pointer(cAcId))
+	cProps := C.CString(string(b))
+	defer C.free(unsafe.Pointer(cProps))
+	cSdkType := C.CString(_sdkType)
+	defer C.free(unsafe.Pointer(cSdkType))
+
+	return callC(func() C.int8_t {
+		return f(cDtId, cAcId, cProps, cSdkType)
+	}, "given event is not valid")
 }
 
 // Flush the data buffer manually.
-func (_ DTAnalytics) Flush() {
-	C.dt_flush()
+func (_ DTAnalytics) Flush() error {
+	return callC(func() C.int8_t {
+		return C.dt_flush()
+	}, "failed to flush")
 }
 
 // Close the DTAnalytics, remember to call this before the program finishes to preventing data loss!
-func (_ DTAnalytics) Close() {
-	C.dt_close()
+func (_ DTAnalytics) Close() error {
+	return callC(func() C.int8_t {
+		return C.dt_close()
+	}, "failed to close")
+}
+
+// CloseWithTimeout closes the DTAnalytics within the timeout, the events not sent in time are dropped and reported
+// to the error hook, e.g. by a hung upload. Returns the numbers of events sent and remaining while closing.
+// The timeout only applies to the consumers uploading by workers, the log consumer writes all the events to the
+// file before returning.
+func (_ DTAnalytics) CloseWithTimeout(timeout time.Duration) (sent uint64, remaining uint64, err error) {
+	var cSent, cRemaining C.uint64_t
+	err = callC(func() C.int8_t {
+		return C.dt_close_with_timeout(C.uint64_t(timeout.Milliseconds()), &cSent, &cRemaining)
+	}, "failed to close")
+	return uint64(cSent), uint64(cRemaining), err
+}
+
+// Stats returns the snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason),
+// "bytes_written", "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+// Counters are monotonic since the process started.
+func (_ DTAnalytics) Stats() (map[string]interface{}, error) {
+	cStats := C.dt_stats()
+	defer C.dt_free_string(cStats)
+
+	stats := make(map[string]interface{})
+	if err := jsoniter.UnmarshalFromString(C.GoString(cStats), &stats); err != nil {
+		return nil, err
+	}
+	return stats, nil
+}
+
+// PrometheusMetrics returns the runtime metrics in Prometheus text exposition format,
+// e.g. "dt_events_accepted_total 10".
+func (_ DTAnalytics) PrometheusMetrics() string {
+	cMetrics := C.dt_prometheus_metrics()
+	defer C.dt_free_string(cMetrics)
+	return C.GoString(cMetrics)
+}
+
+// ServeMetrics serves the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to
+// scrape, e.g. on "127.0.0.1:9464". Only one server is allowed, which can also be started by "metrics_addr" in
+// the init config.
+func (_ DTAnalytics) ServeMetrics(addr string) error {
+	cAddr := C.CString(addr)
+	defer C.free(unsafe.Pointer(cAddr))
+	return callC(func() C.int8_t {
+		return C.dt_serve_metrics(cAddr)
+	}, "failed to serve metrics")
 }
 
 // ToggleLogger to enable and disable the logging.
@@ -169,3 +228,8 @@ func ToggleLogger(enable bool) {
 	cEnabled := C.uint8_t(enabled)
 	C.dt_toggle_logger(cEnabled)
 }
+
+// SetLogLevel sets the minimum level of logs, LogLevel*. Logs below warning are only written if the logging is enabled.
+func SetLogLevel(level int) {
+	C.dt_set_log_level(C.int8_t(level))
+}
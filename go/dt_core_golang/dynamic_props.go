@@ -0,0 +1,79 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+
+extern char* dtDynamicPropsBridge(void);
+*/
+import "C"
+
+import (
+	"fmt"
+	"os"
+	"sync"
+	"unsafe"
+
+	jsoniter "github.com/json-iterator/go"
+)
+
+var (
+	dynamicPropsMu       sync.Mutex
+	dynamicPropsProvider func() map[string]interface{}
+	dynamicPropsLast     *C.char // kept valid until the next invocation.
+)
+
+//export dtDynamicPropsBridge
+func dtDynamicPropsBridge() *C.char {
+	dynamicPropsMu.Lock()
+	defer dynamicPropsMu.Unlock()
+
+	if dynamicPropsLast != nil {
+		C.free(unsafe.Pointer(dynamicPropsLast))
+		dynamicPropsLast = nil
+	}
+	if dynamicPropsProvider == nil {
+		return nil
+	}
+
+	props, err := callDynamicPropsProvider(dynamicPropsProvider)
+	if err != nil {
+		fmt.Fprintf(os.Stderr, "[DT Go] Dynamic common properties provider failed: %v\n", err)
+		return nil
+	}
+	b, err := jsoniter.Marshal(props)
+	if err != nil {
+		fmt.Fprintf(os.Stderr, "[DT Go] Failed to marshal dynamic common properties: %v\n", err)
+		return nil
+	}
+	dynamicPropsLast = C.CString(string(b))
+	return dynamicPropsLast
+}
+
+func callDynamicPropsProvider(provider func() map[string]interface{}) (props map[string]interface{}, err error) {
+	defer func() {
+		if r := recover(); r != nil {
+			err = fmt.Errorf("panic: %v", r)
+		}
+	}()
+	return provider(), nil
+}
+
+// SetDynamicCommonProperties sets a provider of common properties for each event, e.g. current request id.
+// Properties of the event take precedence over the dynamic ones, which take precedence over the static ones.
+// The provider should be fast and must not call the APIs of DTAnalytics.
+// Panics of the provider are recovered and the event is tracked without dynamic common properties.
+func (_ DTAnalytics) SetDynamicCommonProperties(provider func() map[string]interface{}) {
+	dynamicPropsMu.Lock()
+	dynamicPropsProvider = provider
+	dynamicPropsMu.Unlock()
+	C.dt_set_dynamic_common_props((*[0]byte)(C.dtDynamicPropsBridge))
+}
+
+// ClearDynamicCommonProperties removes the provider set by SetDynamicCommonProperties.
+func (_ DTAnalytics) ClearDynamicCommonProperties() {
+	C.dt_set_dynamic_common_props(nil)
+	dynamicPropsMu.Lock()
+	dynamicPropsProvider = nil
+	dynamicPropsMu.Unlock()
+}
@@ -0,0 +1,73 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+
+extern void dtErrorHookBridge(int32_t code, char* message, char* event, char* category);
+*/
+import "C"
+
+import (
+	"fmt"
+	"os"
+	"sync"
+
+	jsoniter "github.com/json-iterator/go"
+)
+
+var (
+	errorHookMu sync.RWMutex
+	errorHook   func(err *DTError, event map[string]interface{}, category string)
+)
+
+//export dtErrorHookBridge
+func dtErrorHookBridge(code C.int32_t, message *C.char, event *C.char, category *C.char) {
+	errorHookMu.RLock()
+	hook := errorHook
+	errorHookMu.RUnlock()
+	if hook == nil {
+		return
+	}
+
+	var props map[string]interface{}
+	if event != nil {
+		if err := jsoniter.UnmarshalFromString(C.GoString(event), &props); err != nil {
+			fmt.Fprintf(os.Stderr, "[DT Go] Failed to unmarshal event of error hook: %v\n", err)
+		}
+	}
+	callErrorHook(hook, &DTError{Code: int(code), Message: C.GoString(message)}, props, C.GoString(category))
+}
+
+func callErrorHook(hook func(*DTError, map[string]interface{}, string), err *DTError, event map[string]interface{}, category string) {
+	defer func() {
+		if r := recover(); r != nil {
+			fmt.Fprintf(os.Stderr, "[DT Go] Error hook panicked: %v\n", r)
+		}
+	}()
+	hook(err, event, category)
+}
+
+// SetErrorHook sets a hook receiving each error, e.g. to count the dropped events.
+// The hook receives the error, the event (nil if absent) and the category, i.e. "add", "flush" or "write".
+// If redact is true, the event only keeps meta properties identifying it, like "#event_name".
+// The hook may be invoked by any goroutine, it should be fast and must not call the APIs of DTAnalytics.
+// Panics of the hook are recovered.
+func (_ DTAnalytics) SetErrorHook(hook func(err *DTError, event map[string]interface{}, category string), redact bool) {
+	errorHookMu.Lock()
+	errorHook = hook
+	errorHookMu.Unlock()
+	var r C.uint8_t
+	if redact {
+		r = 1
+	}
+	C.dt_set_error_hook((*[0]byte)(C.dtErrorHookBridge), r)
+}
+
+// ClearErrorHook removes the hook set by SetErrorHook.
+func (_ DTAnalytics) ClearErrorHook() {
+	C.dt_set_error_hook(nil, 0)
+	errorHookMu.Lock()
+	errorHook = nil
+	errorHookMu.Unlock()
+}
@@ -0,0 +1,54 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+*/
+import "C"
+
+import (
+	"errors"
+	"fmt"
+	"runtime"
+)
+
+// Codes of DTError, see DT_ERR_* in dt_core_clib.h.
+const (
+	ErrCodeVerify   = int(C.DT_ERR_VERIFY)
+	ErrCodeInternal = int(C.DT_ERR_INTERNAL)
+	ErrCodeRuntime  = int(C.DT_ERR_RUNTIME)
+	ErrCodeNetwork  = int(C.DT_ERR_NETWORK)
+	ErrCodeRemote   = int(C.DT_ERR_REMOTE)
+	ErrCodeHost     = int(C.DT_ERR_HOST)
+	ErrCodeArgument = int(C.DT_ERR_ARGUMENT) // same as ErrCodeHost
+)
+
+// DTError is the error reported by the core.
+type DTError struct {
+	Code    int
+	Message string
+}
+
+func (e *DTError) Error() string {
+	return fmt.Sprintf("[%d] %s", e.Code, e.Message)
+}
+
+// callC invokes f and reads the error of the core if it fails.
+// The error is kept per thread by the core, hence the goroutine is locked to its thread during the call.
+func callC(f func() C.int8_t, fallback string) error {
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	if f() != 0 {
+		return nil
+	}
+	return lastError(fallback)
+}
+
+// lastError must be called on the thread of the failed call.
+func lastError(fallback string) error {
+	msg := C.dt_last_error()
+	if msg == nil {
+		return errors.New(fallback)
+	}
+	return &DTError{Code: int(C.dt_last_error_code()), Message: C.GoString(msg)}
+}
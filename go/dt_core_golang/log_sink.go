@@ -0,0 +1,87 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+
+extern void dtLogBridge(int8_t level, char* message);
+*/
+import "C"
+
+import (
+	"fmt"
+	"os"
+	"sync"
+	"unsafe"
+)
+
+// Levels of logs, see SetLogLevel and SetLogCallback.
+const (
+	LogLevelDebug   = int(C.DT_LOG_DEBUG)
+	LogLevelInfo    = int(C.DT_LOG_INFO)
+	LogLevelWarning = int(C.DT_LOG_WARNING)
+	LogLevelError   = int(C.DT_LOG_ERROR)
+	LogLevelSilent  = int(C.DT_LOG_SILENT) // disables even the errors.
+)
+
+var (
+	logCallbackMu sync.RWMutex
+	logCallback   func(level int, message string)
+)
+
+//export dtLogBridge
+func dtLogBridge(level C.int8_t, message *C.char) {
+	logCallbackMu.RLock()
+	callback := logCallback
+	logCallbackMu.RUnlock()
+	if callback == nil {
+		return
+	}
+
+	msg := C.GoString(message)
+	defer func() {
+		if r := recover(); r != nil {
+			fmt.Fprintf(os.Stderr, "%s\n[DT Go] Log callback panicked: %v\n", msg, r)
+		}
+	}()
+	callback(int(level), msg)
+}
+
+// LogToStderr writes the logs to stderr, which is the default.
+func LogToStderr() {
+	C.dt_log_to_stderr()
+}
+
+// LogToFile writes the logs to the file, which is rotated by maxBytes, with at most maxFiles rotated ones kept.
+// The rotated files are named "{path}.1" (the latest) to "{path}.{maxFiles}".
+func LogToFile(path string, maxBytes uint64, maxFiles uint32) error {
+	cPath := C.CString(path)
+	defer C.free(unsafe.Pointer(cPath))
+	return callC(func() C.int8_t {
+		return C.dt_log_to_file(cPath, C.uint64_t(maxBytes), C.uint32_t(maxFiles))
+	}, "failed to log to file")
+}
+
+// SetLogFormat sets the format of the logs written to stderr and file, "text" (default) or "json",
+// which writes a JSON object per line with "level", "timestamp", "module", "message" and the structured fields
+// like "batch_size".
+func SetLogFormat(format string) error {
+	cFormat := C.CString(format)
+	defer C.free(unsafe.Pointer(cFormat))
+	return callC(func() C.int8_t {
+		return C.dt_set_log_format(cFormat)
+	}, "failed to set log format")
+}
+
+// SetLogCallback forwards the logs to the callback, with the level (LogLevel*) and the message.
+// The callback may be invoked by any goroutine, panics of it are recovered. Nil to write the logs to stderr.
+func SetLogCallback(callback func(level int, message string)) {
+	logCallbackMu.Lock()
+	logCallback = callback
+	logCallbackMu.Unlock()
+	if callback == nil {
+		C.dt_set_log_callback(nil)
+	} else {
+		C.dt_set_log_callback((*[0]byte)(C.dtLogBridge))
+	}
+}
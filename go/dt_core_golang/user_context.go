@@ -0,0 +1,127 @@
+package dt_analytics
+
+/*
+#include "stdlib.h"
+#include "dt_core_clib.h"
+*/
+import "C"
+
+import (
+	"errors"
+	"runtime"
+	"sync"
+	"unsafe"
+
+	jsoniter "github.com/json-iterator/go"
+)
+
+// DTUserContext tracks events of a user, with dtId, acId and user-scoped common properties filled.
+// Use DTAnalytics.WithUser to create, and Close it once it's no longer used.
+type DTUserContext struct {
+	mu  sync.Mutex
+	ctx *C.DTUserContext
+}
+
+// WithUser creates a context to track events of the user.
+// The user-scoped common properties are added to each event of the user,
+// and take precedence over the dynamic and static ones.
+func (_ DTAnalytics) WithUser(dtId string, acId string, properties map[string]interface{}) (*DTUserContext, error) {
+	if properties == nil {
+		properties = map[string]interface{}{}
+	}
+	b, err := jsoniter.Marshal(properties)
+	if err != nil {
+		return nil, err
+	}
+	cDtId := C.CString(dtId)
+	defer C.free(unsafe.Pointer(cDtId))
+	cAcId := C.CString(acId)
+	defer C.free(unsafe.Pointer(cAcId))
+	cProps := C.CString(string(b))
+	defer C.free(unsafe.Pointer(cProps))
+
+	runtime.LockOSThread()
+	defer runtime.UnlockOSThread()
+	ctx := C.dt_with_user(cDtId, cAcId, cProps)
+	if ctx == nil {
+		return nil, lastError("given user context is not valid")
+	}
+	return &DTUserContext{ctx: ctx}, nil
+}
+
+// Track an event of this user, see DTAnalytics.Track.
+func (uc *DTUserContext) Track(eventName string, properties map[string]interface{}) error {
+	return uc.add(eventName, "track", properties)
+}
+
+// UserSet sets properties of this user, see DTAnalytics.UserSet.
+func (uc *DTUserContext) UserSet(properties map[string]interface{}) error {
+	return uc.add("#user_set", "user", properties)
+}
+
+// UserSetOnce sets properties of this user without overriding, see DTAnalytics.UserSetOnce.
+func (uc *DTUserContext) UserSetOnce(properties map[string]interface{}) error {
+	return uc.add("#user_set_once", "user", properties)
+}
+
+// UserAdd arithmetic adds properties of this user, see DTAnalytics.UserAdd.
+func (uc *DTUserContext) UserAdd(properties map[string]interface{}) error {
+	return uc.add("#user_add", "user", properties)
+}
+
+// UserUnset unsets properties of this user, see DTAnalytics.UserUnset.
+func (uc *DTUserContext) UserUnset(properties map[string]interface{}) error {
+	return uc.add("#user_unset", "user", properties)
+}
+
+// UserDelete deletes this user, see DTAnalytics.UserDelete.
+func (uc *DTUserContext) UserDelete(properties map[string]interface{}) error {
+	return uc.add("#user_delete", "user", properties)
+}
+
+// UserAppend appends values to properties of this user, see DTAnalytics.UserAppend.
+func (uc *DTUserContext) UserAppend(properties map[string]interface{}) error {
+	return uc.add("#user_append", "user", properties)
+}
+
+// UserUniqAppend appends values to properties of this user without duplications, see DTAnalytics.UserUniqAppend.
+func (uc *DTUserContext) UserUniqAppend(properties map[string]interface{}) error {
+	return uc.add("#user_uniq_append", "user", properties)
+}
+
+// Close releases the context.
+func (uc *DTUserContext) Close() {
+	uc.mu.Lock()
+	defer uc.mu.Unlock()
+	if uc.ctx != nil {
+		C.dt_release_user(uc.ctx)
+		uc.ctx = nil
+	}
+}
+
+func (uc *DTUserContext) add(eventName string, eventType string, properties map[string]interface{}) error {
+	if properties == nil {
+		properties = map[string]interface{}{}
+	}
+	b, err := jsoniter.Marshal(properties)
+	if err != nil {
+		return err
+	}
+	cEventName := C.CString(eventName)
+	defer C.free(unsafe.Pointer(cEventName))
+	cEventType := C.CString(eventType)
+	defer C.free(unsafe.Pointer(cEventType))
+	cProps := C.CString(string(b))
+	defer C.free(unsafe.Pointer(cProps))
+	cSdkType := C.CString(_sdkType)
+	defer C.free(unsafe.Pointer(cSdkType))
+
+	uc.mu.Lock()
+	defer uc.mu.Unlock()
+	if uc.ctx == nil {
+		return errors.New("user context is closed")
+	}
+	return callC(func() C.int8_t {
+		return C.dt_user_add_event(uc.ctx, cEventName, cEventType, cProps, cSdkType)
+	}, "given event is not valid")
+}
@@ -2,11 +2,11 @@ package ai.datatower.sdk;
 
 import java.io.IOException;
 import java.util.HashMap;
-import java.util.List;
 import java.util.Map;
+import java.util.function.Supplier;
 
 public class DTAnalytics {
-    private static final String SDK_TYPE = "dt_server_sdk_java";
+    static final String SDK_TYPE = "dt_server_sdk_java";
 
     private static volatile DTAnalytics instance = null;
 
@@ -32,6 +32,8 @@ public class DTAnalytics {
                 }
             }
             return instance;
+        } catch (DTException e) {
+            throw e;
         } catch (Throwable t) {
             System.out.println("[DT Java] Failed to init DTAnalytics!");
             t.printStackTrace();
@@ -56,7 +58,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean track(String dtId, String acId, String eventName, Map<String, Object> properties) {
-        return add(dtId, acId, eventName, "track", properties);
+        return DTBase.track(dtId, acId, eventName, properties, SDK_TYPE);
     }
 
     /**
@@ -68,7 +70,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userSet(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_set", "user", properties);
+        return DTBase.userSet(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -81,7 +83,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userSetOnce(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_set_once", "user", properties);
+        return DTBase.userSetOnce(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -94,7 +96,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userAdd(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_add", "user", properties);
+        return DTBase.userAdd(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -107,16 +109,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userUnset(String dtId, String acId, Map<String, Object> properties) {
-        Map<String, Object> props = new HashMap<>();
-        for (Map.Entry<String, Object> entry : properties.entrySet()) {
-            String key = entry.getKey();
-            if (key.startsWith("#")) {
-                props.put(key, entry.getValue());
-            } else {
-                props.put(key, 0);
-            }
-        }
-        return add(dtId, acId, "#user_unset", "user", props);
+        return DTBase.userUnset(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -128,7 +121,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userDelete(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_delete", "user", properties);
+        return DTBase.userDelete(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -141,7 +134,7 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userAppend(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_append", "user", properties);
+        return DTBase.userAppend(dtId, acId, properties, SDK_TYPE);
     }
 
     /**
@@ -154,7 +147,38 @@ public class DTAnalytics {
      * @return True if given event and properties is valid or False if there are invalid and will not be processed.
      */
     public boolean userUniqAppend(String dtId, String acId, Map<String, Object> properties) {
-        return add(dtId, acId, "#user_uniq_append", "user", properties);
+        return DTBase.userUniqAppend(dtId, acId, properties, SDK_TYPE);
+    }
+
+    /**
+     * Snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason), "bytes_written",
+     * "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+     * Counters are monotonic since the process started.
+     *
+     * @return the metrics, numbers are Long.
+     */
+    public Map<String, Object> stats() {
+        return DTBase.stats();
+    }
+
+    /**
+     * The runtime metrics in Prometheus text exposition format, e.g. "dt_events_accepted_total 10".
+     *
+     * @return the metrics text.
+     */
+    public String prometheusMetrics() {
+        return DTBase.prometheusMetrics();
+    }
+
+    /**
+     * Serve the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to scrape.
+     * Only one server is allowed, which can also be started by "metrics_addr" in the init config.
+     *
+     * @param addr the address to listen, e.g. "127.0.0.1:9464".
+     * @return the bound address.
+     */
+    public String serveMetrics(String addr) {
+        return DTBase.serveMetrics(addr);
     }
 
     /**
@@ -171,21 +195,180 @@ public class DTAnalytics {
         DTBase.close();
     }
 
+    /**
+     * Close the DTAnalytics within the timeout, the events not sent in time are dropped and reported to the error
+     * listener, e.g. by a hung upload. The timeout only applies to the consumers uploading by workers, the log
+     * consumer writes all the events to the file before returning.
+     *
+     * @param timeoutMillis the timeout in milliseconds.
+     * @return the numbers of events "sent" and "remaining" while closing, numbers are Long.
+     */
+    public Map<String, Object> closeWithTimeout(long timeoutMillis) {
+        return DTBase.closeWithTimeout(timeoutMillis);
+    }
+
+    /**
+     * Create a context to track events of the user, which fills dtId, acId and the user-scoped common properties.
+     * The user-scoped common properties take precedence over the dynamic and static ones.
+     * Remember to close the context once it's no longer used.
+     *
+     * @param dtId The device-scoped id.
+     * @param acId The account-scoped id.
+     * @param properties user-scoped common properties, added to each event of the user.
+     * @return DTUserContext, or null if given properties are invalid (unless throwing on error).
+     */
+    public DTUserContext withUser(String dtId, String acId, Map<String, Object> properties) {
+        long handle = DTBase.newUserContext(dtId, acId, properties == null ? new HashMap<>() : properties);
+        return handle == 0 ? null : new DTUserContext(handle);
+    }
+
+    /**
+     * Replace all the static common properties, which will be added to each event.
+     *
+     * @param properties the common properties.
+     */
+    public void setStaticCommonProperties(Map<String, Object> properties) {
+        DTBase.setStaticCommonProperties(properties);
+    }
+
+    /**
+     * Merge into the static common properties, the given ones override those with the same key.
+     * Hence, different modules can contribute their own common properties without clobbering each other.
+     *
+     * @param properties the common properties to be merged.
+     */
+    public void mergeStaticCommonProperties(Map<String, Object> properties) {
+        DTBase.mergeStaticCommonProperties(properties);
+    }
+
+    /**
+     * Remove the static common property with given key.
+     *
+     * @param key key of the common property.
+     */
+    public void unsetStaticCommonProperty(String key) {
+        DTBase.unsetStaticCommonProperty(key);
+    }
+
+    /**
+     * Get a copy of the static common properties.
+     *
+     * @return the common properties, numbers are either Long or Double.
+     */
+    public Map<String, Object> getStaticCommonProperties() {
+        return DTBase.getStaticCommonProperties();
+    }
+
+    /**
+     * Remove all the static common properties.
+     */
+    public void clearStaticCommonProperties() {
+        DTBase.clearStaticCommonProperties();
+    }
+
+    /**
+     * Set a provider of common properties for each event, e.g. current request id.
+     * Properties of the event take precedence over the dynamic ones, which take precedence over the static ones.
+     * The provider should be fast and must not call the APIs of DTAnalytics.
+     * Exceptions thrown by the provider are logged and the event is tracked without dynamic common properties.
+     *
+     * @param provider Supplier of the properties.
+     */
+    public void setDynamicCommonProperties(Supplier<Map<String, Object>> provider) {
+        DTBase.setDynamicCommonProperties(provider);
+    }
+
+    /**
+     * Remove the provider set by setDynamicCommonProperties.
+     */
+    public void clearDynamicCommonProperties() {
+        DTBase.clearDynamicCommonProperties();
+    }
+
+    /**
+     * Set a listener receiving each error, e.g. to count the dropped events.
+     * The listener should be fast and must not call the APIs of DTAnalytics, exceptions thrown are logged.
+     *
+     * @param listener Listener of the errors.
+     * @param redact If set to true, the event only keeps meta properties identifying it, like "#event_name".
+     */
+    public void setErrorListener(DTErrorListener listener, boolean redact) {
+        DTBase.setErrorListener(listener, redact);
+    }
+
+    /**
+     * Remove the listener set by setErrorListener.
+     */
+    public void clearErrorListener() {
+        DTBase.clearErrorListener();
+    }
+
     /**
      * To enable and disable the logging.
      */
     public static void toggleLogger(boolean enable) {
         DTBase.toggleLogger(enable);
     }
-    
-    private boolean add(String dtId, String acId, String eventName, String eventType, Map<String, Object> properties) {
-        Map<String, Object> event = new HashMap<>(properties);
-        event.put("#dt_id", dtId);
-        event.put("#acid", acId);
-        event.put("#event_name", eventName);
-        event.put("#event_type", eventType);
-        event.put("#sdk_type", DTAnalytics.SDK_TYPE);
-        
-        return DTBase.addEvent(event);
-    }
-}
\ No newline at end of file
+
+    /**
+     * To write the logs to stderr, which is the default.
+     */
+    public static void logToStderr() {
+        DTBase.logToStderr();
+    }
+
+    /**
+     * To write the logs to the file, which is rotated by size.
+     *
+     * @param path Path of the log file, the rotated ones are named "{path}.1" (the latest) to "{path}.{maxFiles}".
+     * @param maxBytes Max size of the log file in bytes.
+     * @param maxFiles Max number of the rotated files to keep.
+     */
+    public static void logToFile(String path, long maxBytes, int maxFiles) {
+        DTBase.logToFile(path, maxBytes, maxFiles);
+    }
+
+    /**
+     * To forward the logs to SLF4J, with the logger named "ai.datatower.sdk".
+     *
+     * @throws IllegalStateException If SLF4J is not on the classpath.
+     */
+    public static void logToSlf4j() {
+        DTBase.setLogListener(DTLogListener.slf4j("ai.datatower.sdk"));
+    }
+
+    /**
+     * Minimum level of the logs.
+     *
+     * @param level "debug" (default), "info", "warning", "error" or "silent", which disables even the errors.
+     *              Logs below "warning" are only written if the logging is enabled.
+     */
+    public static void setLogLevel(String level) {
+        DTBase.setLogLevel(level);
+    }
+
+    /**
+     * Format of the logs written to stderr and file.
+     *
+     * @param format "text" (default) or "json", which writes a JSON object per line with
+     *               "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+     */
+    public static void setLogFormat(String format) {
+        DTBase.setLogFormat(format);
+    }
+
+    /**
+     * To forward the logs to the listener, which may be invoked by any thread.
+     */
+    public static void setLogListener(DTLogListener listener) {
+        DTBase.setLogListener(listener);
+    }
+
+    /**
+     * To throw DTException on error, instead of logging and returning false (or default value).
+     * Disabled by default.
+     */
+    public static void setThrowOnError(boolean enable) {
+        DTBase.setThrowOnError(enable);
+    }
+}
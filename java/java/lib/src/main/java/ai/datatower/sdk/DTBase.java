@@ -4,16 +4,45 @@ import java.io.*;
 import java.nio.file.Files;
 import java.util.Map;
 import java.util.concurrent.atomic.AtomicBoolean;
+import java.util.function.Supplier;
 
 class DTBase {
     static native boolean init(Map<String, Object> config);
     static native boolean addEvent(Map<String, Object> event);
     static native boolean addEventStr(String event);
+    static native boolean track(String dtId, String acId, String eventName, Map<String, Object> properties, String sdkType);
+    static native boolean userSet(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userSetOnce(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userAdd(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userUnset(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userDelete(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userAppend(String dtId, String acId, Map<String, Object> properties, String sdkType);
+    static native boolean userUniqAppend(String dtId, String acId, Map<String, Object> properties, String sdkType);
     static native void flush();
     static native void close();
+    static native Map<String, Object> closeWithTimeout(long timeoutMillis);
+    static native Map<String, Object> stats();
+    static native String prometheusMetrics();
+    static native String serveMetrics(String addr);
     static native void toggleLogger(boolean enable);
+    static native void logToStderr();
+    static native void logToFile(String path, long maxBytes, int maxFiles);
+    static native void setLogListener(DTLogListener listener);
+    static native void setLogFormat(String format);
+    static native void setLogLevel(String level);
+    static native void setThrowOnError(boolean enable);
     static native void setStaticCommonProperties(Map<String, Object> properties);
     static native void clearStaticCommonProperties();
+    static native void mergeStaticCommonProperties(Map<String, Object> properties);
+    static native void unsetStaticCommonProperty(String key);
+    static native Map<String, Object> getStaticCommonProperties();
+    static native void setDynamicCommonProperties(Supplier<Map<String, Object>> provider);
+    static native void clearDynamicCommonProperties();
+    static native void setErrorListener(DTErrorListener listener, boolean redact);
+    static native void clearErrorListener();
+    static native long newUserContext(String dtId, String acId, Map<String, Object> properties);
+    static native boolean addUserContextEvent(long handle, String eventName, String eventType, Map<String, Object> properties, String sdkType);
+    static native void releaseUserContext(long handle);
 
     static AtomicBoolean loaded = new AtomicBoolean(false);
 
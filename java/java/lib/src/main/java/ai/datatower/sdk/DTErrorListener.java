@@ -0,0 +1,16 @@
+package ai.datatower.sdk;
+
+import java.util.Map;
+
+/**
+ * Receives each error, see DTAnalytics.setErrorListener.
+ */
+@FunctionalInterface
+public interface DTErrorListener {
+    /**
+     * @param error The error, which is not thrown.
+     * @param event The event, null if absent.
+     * @param category "add" (dropped while adding), "flush" (failed to flush or close) or "write" (failed to write).
+     */
+    void onError(DTException error, Map<String, Object> event, String category);
+}
@@ -0,0 +1,74 @@
+package ai.datatower.sdk;
+
+/**
+ * Error reported by the core, only thrown if enabled by DTAnalytics.setThrowOnError.
+ * Catch the subclasses to handle specific errors, or check the code which is stable across SDKs.
+ */
+public class DTException extends RuntimeException {
+    public static final int CODE_VERIFY = 1;
+    public static final int CODE_INTERNAL = 2;
+    public static final int CODE_RUNTIME = 3;
+    public static final int CODE_NETWORK = 4;
+    public static final int CODE_REMOTE = 5;
+    public static final int CODE_HOST = 6;
+
+    private final int code;
+
+    DTException(String message) {
+        this(CODE_RUNTIME, message);
+    }
+
+    DTException(int code, String message) {
+        super(message);
+        this.code = code;
+    }
+
+    public int getCode() {
+        return code;
+    }
+
+    /**
+     * Given event or properties are invalid.
+     */
+    public static class VerifyException extends DTException {
+        VerifyException(String message) {
+            super(CODE_VERIFY, message);
+        }
+    }
+
+    /**
+     * Due to internal reason, e.g. failed to write the log file.
+     */
+    public static class InternalException extends DTException {
+        InternalException(String message) {
+            super(CODE_INTERNAL, message);
+        }
+    }
+
+    /**
+     * Due to network reason.
+     */
+    public static class NetworkException extends DTException {
+        NetworkException(String message) {
+            super(CODE_NETWORK, message);
+        }
+    }
+
+    /**
+     * Due to remote reason.
+     */
+    public static class RemoteException extends DTException {
+        RemoteException(String message) {
+            super(CODE_REMOTE, message);
+        }
+    }
+
+    /**
+     * Given arguments can't be converted, e.g. null or unsupported types.
+     */
+    public static class HostException extends DTException {
+        HostException(String message) {
+            super(CODE_HOST, message);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+package ai.datatower.sdk;
+
+import java.lang.reflect.Method;
+
+/**
+ * Receives each log, see DTAnalytics.setLogListener.
+ */
+@FunctionalInterface
+public interface DTLogListener {
+    /**
+     * @param level "debug", "info", "warning" or "error".
+     * @param message The message.
+     */
+    void onLog(String level, String message);
+
+    /**
+     * Forwards the logs to SLF4J, which is looked up at runtime, hence no dependency is introduced.
+     *
+     * @param name Name of the SLF4J logger.
+     * @throws IllegalStateException If SLF4J is not on the classpath.
+     */
+    static DTLogListener slf4j(String name) {
+        try {
+            Object logger = Class.forName("org.slf4j.LoggerFactory")
+                    .getMethod("getLogger", String.class)
+                    .invoke(null, name);
+            Class<?> loggerClass = Class.forName("org.slf4j.Logger");
+            Method debug = loggerClass.getMethod("debug", String.class);
+            Method info = loggerClass.getMethod("info", String.class);
+            Method warn = loggerClass.getMethod("warn", String.class);
+            Method error = loggerClass.getMethod("error", String.class);
+            return (level, message) -> {
+                Method method;
+                switch (level) {
+                    case "debug": method = debug; break;
+                    case "warning": method = warn; break;
+                    case "error": method = error; break;
+                    default: method = info; break;
+                }
+                try {
+                    method.invoke(logger, message);
+                } catch (ReflectiveOperationException e) {
+                    System.err.println(message);
+                }
+            };
+        } catch (ReflectiveOperationException e) {
+            throw new IllegalStateException("SLF4J is not found", e);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+package ai.datatower.sdk;
+
+import java.util.Map;
+
+/**
+ * Tracks events of a user, with dtId, acId and user-scoped common properties filled.
+ * Use {@link DTAnalytics#withUser} to create, and close it once it's no longer used.
+ */
+public class DTUserContext implements AutoCloseable {
+    private long handle;
+
+    DTUserContext(long handle) {
+        this.handle = handle;
+    }
+
+    /**
+     * Track an event of this user, see {@link DTAnalytics#track}.
+     */
+    public boolean track(String eventName, Map<String, Object> properties) {
+        return add(eventName, "track", properties);
+    }
+
+    /**
+     * Set properties of this user, see {@link DTAnalytics#userSet}.
+     */
+    public boolean userSet(Map<String, Object> properties) {
+        return add("#user_set", "user", properties);
+    }
+
+    /**
+     * Set properties of this user without overriding, see {@link DTAnalytics#userSetOnce}.
+     */
+    public boolean userSetOnce(Map<String, Object> properties) {
+        return add("#user_set_once", "user", properties);
+    }
+
+    /**
+     * Arithmetic add properties of this user, see {@link DTAnalytics#userAdd}.
+     */
+    public boolean userAdd(Map<String, Object> properties) {
+        return add("#user_add", "user", properties);
+    }
+
+    /**
+     * Unset properties of this user, see {@link DTAnalytics#userUnset}.
+     */
+    public boolean userUnset(Map<String, Object> properties) {
+        return add("#user_unset", "user", properties);
+    }
+
+    /**
+     * Delete this user, see {@link DTAnalytics#userDelete}.
+     */
+    public boolean userDelete(Map<String, Object> properties) {
+        return add("#user_delete", "user", properties);
+    }
+
+    /**
+     * Append values to properties of this user, see {@link DTAnalytics#userAppend}.
+     */
+    public boolean userAppend(Map<String, Object> properties) {
+        return add("#user_append", "user", properties);
+    }
+
+    /**
+     * Append values to properties of this user without duplications, see {@link DTAnalytics#userUniqAppend}.
+     */
+    public boolean userUniqAppend(Map<String, Object> properties) {
+        return add("#user_uniq_append", "user", properties);
+    }
+
+    @Override
+    public synchronized void close() {
+        if (handle != 0) {
+            DTBase.releaseUserContext(handle);
+            handle = 0;
+        }
+    }
+
+    private synchronized boolean add(String eventName, String eventType, Map<String, Object> properties) {
+        if (handle == 0) {
+            System.out.println("[DT Java] DTUserContext is closed!");
+            return false;
+        }
+        return DTBase.addUserContextEvent(handle, eventName, eventType, properties, DTAnalytics.SDK_TYPE);
+    }
+}
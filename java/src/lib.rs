@@ -1,78 +1,223 @@
 use Ordering::Relaxed;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use jni::JNIEnv;
-use jni::objects::{JClass, JObject, JString};
-use jni::sys::jboolean;
-use serde_json::Value;
+use jni::objects::{JClass, JObject, JString, JValue};
+use jni::sys::{jboolean, jint, jlong};
+use common::event::tracking::assemble;
+use common::event::user_context::UserContext;
+use serde_json::{Map, Value};
 use common::log_error;
 use common::util::error::DTError;
-use common::util::result::{dissolve, dissolve_bool};
-use crate::parser::jmap2map;
+use common::util::logger::{fmt_text, install_default_logger, Level, level_name, LogFormat, LogSink, parse_log_level, set_log_format, set_log_level, set_log_sink};
+use common::util::result::{dissolve_bool, dissolve_or_default, THROW_ON_ERROR};
+use crate::parser::{jmap2map, map2jmap};
 
 type JniError = jni::errors::Error;
-type JniResult<T> = Result<T, JniError>;
+type JniResult<T> = std::result::Result<T, JniError>;
+type Result<T> = common::util::error::Result<T>;
+
+static CLASS_EXCEPTION: &str = "ai/datatower/sdk/DTException";
 
 #[no_mangle]
 pub extern "system" fn Java_ai_datatower_sdk_DTBase_init<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, config: JObject<'local>) -> jboolean {
-    let raw_config = env.get_map(&config).expect("Couldn't get config map");
-    let Ok(config) = jmap2map(&mut env, raw_config) else {
-        log_error!("Failed to parse config map");
-        return jboolean::from(false);
-    };
-    let result = dissolve_bool::<(), DTError>(
-        common::init_by_config(config)
-    ).unwrap_or(false);
-    jboolean::from(result)
+    install_default_logger();
+    let result = jobject2map(&mut env, config).and_then(common::init_by_config);
+    dissolve_jboolean(&mut env, result)
 }
 
 #[no_mangle]
 pub extern "system" fn Java_ai_datatower_sdk_DTBase_addEvent<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, event: JObject<'local>) -> jboolean {
-    let event = env.get_map(&event).expect("Couldn't get event/properties");
-    let Ok(event) = jmap2map(&mut env, event) else {
-        log_error!("Failed to parse event/properties");
-        return jboolean::from(false);
-    };
-    let result = dissolve_bool::<(), DTError>(
-        common::add(event)
-    ).unwrap_or(false);
-    jboolean::from(result)
+    let result = jobject2map(&mut env, event).and_then(common::add);
+    dissolve_jboolean(&mut env, result)
 }
 
 #[no_mangle]
 pub extern "system" fn Java_ai_datatower_sdk_DTBase_addEventStr<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, event: JString<'local>) -> jboolean {
-    let Ok(event) = env.get_string(&event) else {
-        log_error!("Failed to get event");
-        return jboolean::from(false);
-    };
-    let event_str: String = event.into();
-    let event = match serde_json::from_str::<Value>(event_str.as_str()) {
-        Ok(json) => match json {
-            Value::Object(map) => map,
-            _ => {
-                log_error!("Failed to parse init config! Given: {json:?}");
-                return jboolean::from(false);
-            },
-        },
-        Err(err) => {
-            log_error!("Failed to parse event, {err}");
-            return jboolean::from(false);
+    let result = jstring2string(&mut env, &event).and_then(|event_str| {
+        match serde_json::from_str::<Value>(event_str.as_str()) {
+            Ok(Value::Object(map)) => Ok(map),
+            Ok(json) => Err(DTError::HostError(format!("Failed to parse event! Given: {json:?}"))),
+            Err(err) => Err(DTError::HostError(format!("Failed to parse event, {err}"))),
         }
-    };
+    }).and_then(common::add);
+    dissolve_jboolean(&mut env, result)
+}
 
-    let result = dissolve_bool::<(), DTError>(
-        common::add(event)
-    ).unwrap_or(false);
-    jboolean::from(result)
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_track<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, event_name: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = (|| {
+        let (dt_id, event_name, sdk_type) = (
+            jstring2string(&mut env, &dt_id)?, jstring2string(&mut env, &event_name)?, jstring2string(&mut env, &sdk_type)?
+        );
+        let ac_id = jstring2option(&mut env, &ac_id)?;
+        common::track(dt_id, ac_id, &event_name, jobject2map(&mut env, properties)?, &sdk_type)
+    })();
+    dissolve_jboolean(&mut env, result)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_datatower_sdk_DTBase_flush<'local>(_env: JNIEnv<'local>, _class: JClass<'local>) {
-    dissolve::<(), DTError>(common::flush()).unwrap();
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userSet<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_set);
+    dissolve_jboolean(&mut env, result)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_datatower_sdk_DTBase_close<'local>(_env: JNIEnv<'local>, _class: JClass<'local>) {
-    dissolve::<(), DTError>(common::close()).unwrap();
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userSetOnce<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_set_once);
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userAdd<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_add);
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userUnset<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_unset);
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userDelete<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_delete);
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userAppend<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_append);
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_userUniqAppend<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    let result = with_user_args(&mut env, dt_id, ac_id, properties, sdk_type, common::user_uniq_append);
+    dissolve_jboolean(&mut env, result)
+}
+
+fn with_user_args<'local, F>(env: &mut JNIEnv<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>, f: F) -> Result<()>
+    where F: FnOnce(String, Option<String>, Map<String, Value>, &str) -> Result<()>
+{
+    let dt_id = jstring2string(env, &dt_id)?;
+    let ac_id = jstring2option(env, &ac_id)?;
+    let sdk_type = jstring2string(env, &sdk_type)?;
+    f(dt_id, ac_id, jobject2map(env, properties)?, &sdk_type)
+}
+
+fn jstring2string<'local>(env: &mut JNIEnv<'local>, s: &JString<'local>) -> Result<String> {
+    jstring2option(env, s)?.ok_or_else(|| DTError::HostError(String::from("Given string is null!")))
+}
+
+/// Returns None if it's null.
+fn jstring2option<'local>(env: &mut JNIEnv<'local>, s: &JString<'local>) -> Result<Option<String>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    env.get_string(s)
+        .map(|s| Some(s.into()))
+        .map_err(|e| DTError::HostError(format!("Failed to get string, {e}")))
+}
+
+fn jobject2map<'local>(env: &mut JNIEnv<'local>, map: JObject<'local>) -> Result<Map<String, Value>> {
+    if map.is_null() {
+        return Err(DTError::HostError(String::from("Given map is null!")));
+    }
+    env.get_map(&map)
+        .and_then(|map| jmap2map(env, map))
+        .map_err(|e| DTError::HostError(format!("Failed to parse event/properties, {e}")))
+}
+
+/// Returns false if failed, and throws the exception as well in throw mode.
+fn dissolve_jboolean<T>(env: &mut JNIEnv, result: Result<T>) -> jboolean {
+    match dissolve_bool(result) {
+        Ok(success) => jboolean::from(success),
+        Err(e) => {
+            throw(env, &e);
+            jboolean::from(false)
+        }
+    }
+}
+
+/// Returns default value if failed, and throws the exception as well in throw mode.
+fn dissolve_or_throw<T: Default>(env: &mut JNIEnv, result: Result<T>) -> T {
+    dissolve_or_default(result).unwrap_or_else(|e| {
+        throw(env, &e);
+        T::default()
+    })
+}
+
+/// Throws the subclass of DTException by the code.
+fn throw(env: &mut JNIEnv, e: &DTError) {
+    if let Err(err) = env.throw_new(exception_class(e), e.to_string()) {
+        log_error!("Failed to throw {e}, {err}");
+    }
+}
+
+fn exception_class(e: &DTError) -> &'static str {
+    match e.code() {
+        DTError::CODE_VERIFY => "ai/datatower/sdk/DTException$VerifyException",
+        DTError::CODE_INTERNAL => "ai/datatower/sdk/DTException$InternalException",
+        DTError::CODE_NETWORK => "ai/datatower/sdk/DTException$NetworkException",
+        DTError::CODE_REMOTE => "ai/datatower/sdk/DTException$RemoteException",
+        DTError::CODE_HOST => "ai/datatower/sdk/DTException$HostException",
+        _ => CLASS_EXCEPTION,
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_flush<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) {
+    dissolve_or_throw(&mut env, common::flush());
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_close<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) {
+    dissolve_or_throw(&mut env, common::close());
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_closeWithTimeout<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, timeout_millis: jlong) -> JObject<'local> {
+    let result = common::close_with_timeout(Duration::from_millis(timeout_millis.max(0) as u64));
+    match dissolve_or_throw(&mut env, result.map(Some)) {
+        Some(outcome) => map2jmap(&mut env, &outcome.to_map()).unwrap_or_else(|e| {
+            log_error!("Failed to convert close outcome, {e}");
+            JObject::null()
+        }),
+        None => JObject::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_stats<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) -> JObject<'local> {
+    map2jmap(&mut env, &common::stats()).unwrap_or_else(|e| {
+        log_error!("Failed to convert stats, {e}");
+        JObject::null()
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_prometheusMetrics<'local>(env: JNIEnv<'local>, _class: JClass<'local>) -> JString<'local> {
+    env.new_string(common::prometheus_metrics()).unwrap_or_else(|e| {
+        log_error!("Failed to convert metrics, {e}");
+        JString::default()
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_serveMetrics<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, addr: JString<'local>) -> JString<'local> {
+    let result = match env.get_string(&addr) {
+        Ok(addr) => common::serve_metrics(&String::from(addr)).map(|addr| Some(addr.to_string())),
+        Err(e) => Err(DTError::HostError(format!("Failed to get addr, {e}"))),
+    };
+    match dissolve_or_throw(&mut env, result) {
+        Some(bound) => env.new_string(bound).unwrap_or_else(|e| {
+            log_error!("Failed to convert addr, {e}");
+            JString::default()
+        }),
+        None => JString::default(),
+    }
 }
 
 #[no_mangle]
@@ -80,26 +225,215 @@ pub extern "system" fn Java_ai_datatower_sdk_DTBase_toggleLogger<'local>(_env: J
     common::util::logger::LOG_ENABLED.store(enable != 0, Relaxed);
 }
 
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_logToStderr<'local>(_env: JNIEnv<'local>, _class: JClass<'local>) {
+    set_log_sink(LogSink::Stderr);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_logToFile<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, path: JString<'local>, max_bytes: jlong, max_files: jint) {
+    let result = match env.get_string(&path) {
+        Ok(path) => LogSink::file(String::from(path), max_bytes.max(0) as u64, max_files.max(0) as usize),
+        Err(e) => Err(DTError::HostError(format!("Failed to get path, {e}"))),
+    };
+    if let Some(sink) = dissolve_or_throw(&mut env, result.map(Some)) {
+        set_log_sink(sink);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setLogFormat<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, format: JString<'local>) {
+    let result = match env.get_string(&format) {
+        Ok(format) => LogFormat::parse(&String::from(format)),
+        Err(e) => Err(DTError::HostError(format!("Failed to get format, {e}"))),
+    };
+    if let Some(format) = dissolve_or_throw(&mut env, result.map(Some)) {
+        set_log_format(format);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setLogLevel<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, level: JString<'local>) {
+    let result = match env.get_string(&level) {
+        Ok(level) => parse_log_level(&String::from(level)),
+        Err(e) => Err(DTError::HostError(format!("Failed to get level, {e}"))),
+    };
+    if let Some(level) = dissolve_or_throw(&mut env, result.map(Some)) {
+        set_log_level(level);
+    }
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setLogListener<'local>(env: JNIEnv<'local>, _class: JClass<'local>, listener: JObject<'local>) {
+    let (Ok(vm), Ok(listener)) = (env.get_java_vm(), env.new_global_ref(listener)) else {
+        log_error!("Failed to hold the log listener");
+        return;
+    };
+    set_log_sink(LogSink::Callback(Box::new(move |level, msg| {
+        let result = vm.attach_current_thread().and_then(|mut env| {
+            let level = env.new_string(level_name(level))?;
+            let message = env.new_string(msg)?;
+            let result = env.call_method(&listener, "onLog", "(Ljava/lang/String;Ljava/lang/String;)V",
+                                         &[JValue::Object(&level), JValue::Object(&message)]);
+            if result.is_err() {
+                let _ = env.exception_describe();
+                let _ = env.exception_clear();
+            }
+            result.map(|_| ())
+        });
+        if let Err(err) = result {
+            // Not logged by SDK, which would come back here.
+            eprintln!("{}", fmt_text(level, msg));
+            eprintln!("{}", fmt_text(Level::Error, &format!("Log listener failed, {err}")));
+        }
+    })));
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setThrowOnError<'local>(_env: JNIEnv<'local>, _class: JClass<'local>, enable: jboolean) {
+    THROW_ON_ERROR.store(enable != 0, Relaxed);
+}
+
 #[no_mangle]
 pub extern "system" fn Java_ai_datatower_sdk_DTBase_setStaticCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, properties: JObject<'local>) {
-    let event = env.get_map(&properties).expect("Couldn't get event/properties");
-    let Ok(properties) = jmap2map(&mut env, event) else {
-        log_error!("Failed to parse event/properties");
+    let result = jobject2map(&mut env, properties).and_then(common::set_static_common_props);
+    dissolve_or_throw(&mut env, result);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_clearStaticCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) {
+    dissolve_or_throw(&mut env, common::clear_static_common_props());
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_mergeStaticCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, properties: JObject<'local>) {
+    let result = jobject2map(&mut env, properties).and_then(common::merge_static_common_props);
+    dissolve_or_throw(&mut env, result);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_unsetStaticCommonProperty<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, key: JString<'local>) {
+    let result = jstring2string(&mut env, &key).and_then(|key| common::unset_static_common_prop(&key));
+    dissolve_or_throw(&mut env, result);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_getStaticCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) -> JObject<'local> {
+    let properties = dissolve_or_throw(&mut env, common::get_static_common_props());
+    map2jmap(&mut env, &properties).unwrap_or_else(|e| {
+        log_error!("Failed to convert common properties, {e}");
+        JObject::null()
+    })
+}
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setDynamicCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, provider: JObject<'local>) {
+    let (Ok(vm), Ok(provider)) = (env.get_java_vm(), env.new_global_ref(provider)) else {
+        log_error!("Failed to hold the dynamic common properties provider");
+        return;
+    };
+    let result = common::set_dynamic_common_props(move || {
+        let mut env = vm.attach_current_thread()
+            .map_err(|e| DTError::HostError(format!("Failed to attach thread, {e}")))?;
+        let props = match env.call_method(&provider, "get", "()Ljava/lang/Object;", &[]).and_then(|it| it.l()) {
+            Ok(props) => props,
+            Err(e) => {
+                let _ = env.exception_describe();
+                let _ = env.exception_clear();
+                return Err(DTError::HostError(format!("Dynamic common properties provider failed, {e}")));
+            }
+        };
+        if props.is_null() {
+            return Ok(Map::new());
+        }
+        env.get_map(&props)
+            .and_then(|props| jmap2map(&mut env, props))
+            .map_err(|e| DTError::HostError(format!("Failed to parse dynamic common properties, {e}")))
+    });
+    dissolve_or_throw(&mut env, result);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_clearDynamicCommonProperties<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) {
+    dissolve_or_throw(&mut env, common::clear_dynamic_common_props());
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_setErrorListener<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, listener: JObject<'local>, redact: jboolean) {
+    let (Ok(vm), Ok(listener)) = (env.get_java_vm(), env.new_global_ref(listener)) else {
+        log_error!("Failed to hold the error listener");
         return;
     };
-    dissolve::<(), DTError>(common::set_static_common_props(properties)).unwrap();
+    let result = common::set_error_hook(move |e, event, category| {
+        let result = vm.attach_current_thread().and_then(|mut env| {
+            let message = env.new_string(e.to_string())?;
+            let error = env.new_object(exception_class(e), "(Ljava/lang/String;)V", &[JValue::Object(&message)])?;
+            let event = match event {
+                Some(event) => map2jmap(&mut env, event)?,
+                None => JObject::null(),
+            };
+            let category = env.new_string(category.as_str())?;
+            let result = env.call_method(&listener, "onError", "(Lai/datatower/sdk/DTException;Ljava/util/Map;Ljava/lang/String;)V",
+                                         &[JValue::Object(&error), JValue::Object(&event), JValue::Object(&category)]);
+            if result.is_err() {
+                let _ = env.exception_describe();
+                let _ = env.exception_clear();
+            }
+            result.map(|_| ())
+        });
+        if let Err(err) = result {
+            log_error!("Error listener failed, {err}");
+        }
+    }, redact != 0);
+    dissolve_or_throw(&mut env, result);
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_clearErrorListener<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>) {
+    dissolve_or_throw(&mut env, common::clear_error_hook());
+}
+
+/// Returns the handle of the context, 0 if failed. It should be released by releaseUserContext.
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_newUserContext<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, dt_id: JString<'local>, ac_id: JString<'local>, properties: JObject<'local>) -> jlong {
+    let result = (|| {
+        let dt_id = jstring2string(&mut env, &dt_id)?;
+        let ac_id = jstring2option(&mut env, &ac_id)?;
+        common::with_user(dt_id, ac_id, jobject2map(&mut env, properties)?)
+    })();
+    dissolve_or_throw(&mut env, result.map(|ctx| Some(Box::new(ctx))))
+        .map(|ctx| Box::into_raw(ctx) as jlong)
+        .unwrap_or(0)
 }
 
 #[no_mangle]
-pub extern "system" fn Java_ai_datatower_sdk_DTBase_clearStaticCommonProperties<'local>(_env: JNIEnv<'local>, _class: JClass<'local>) {
-    dissolve::<(), DTError>(common::clear_static_common_props()).unwrap();
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_addUserContextEvent<'local>(mut env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong, event_name: JString<'local>, event_type: JString<'local>, properties: JObject<'local>, sdk_type: JString<'local>) -> jboolean {
+    if handle == 0 {
+        return jboolean::from(false);
+    }
+    // Safety: the handle is created by newUserContext and not released yet, guarded by DTUserContext.
+    let ctx = unsafe { &*(handle as *const UserContext) };
+    let result = (|| {
+        let (event_name, event_type, sdk_type) = (
+            jstring2string(&mut env, &event_name)?, jstring2string(&mut env, &event_type)?, jstring2string(&mut env, &sdk_type)?
+        );
+        ctx.add(assemble(&event_name, &event_type, jobject2map(&mut env, properties)?, &sdk_type))
+    })();
+    dissolve_jboolean(&mut env, result)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_ai_datatower_sdk_DTBase_releaseUserContext<'local>(_env: JNIEnv<'local>, _class: JClass<'local>, handle: jlong) {
+    if handle != 0 {
+        drop(unsafe { Box::from_raw(handle as *mut UserContext) });
+    }
 }
 
 mod parser {
     use serde_json::{Map, Value};
     use jni::JNIEnv;
-    use jni::objects::{JList, JMap, JObject, JString};
+    use jni::objects::{JList, JMap, JObject, JString, JValue};
     use common::log_error;
+    use common::util::datetime::fmt_millis_iso8601;
     use super::JniResult;
 
     static CLASS_STRING: &str = "java/lang/String";
@@ -113,6 +447,8 @@ mod parser {
     static CLASS_SHORT: &str = "java/lang/Short";
     static CLASS_BYTE: &str = "java/lang/Byte";
     static CLASS_CHAR: &str = "java/lang/Character";
+    static CLASS_DATE: &str = "java/util/Date";
+    static CLASS_INSTANT: &str = "java/time/Instant";
 
     pub(super) fn jmap2map<'local>(env: &mut JNIEnv<'local>, jmap: JMap) -> JniResult<Map<String, Value>> {
         let mut map = Map::new();
@@ -130,6 +466,42 @@ mod parser {
         Ok(map)
     }
 
+    /// Converts to HashMap, with numbers as Long or Double and arrays as ArrayList.
+    pub(super) fn map2jmap<'local>(env: &mut JNIEnv<'local>, map: &Map<String, Value>) -> JniResult<JObject<'local>> {
+        let jmap = env.new_object("java/util/HashMap", "()V", &[])?;
+        for (k, v) in map {
+            let key = env.new_string(k)?;
+            let value = value2jobject(env, v)?;
+            env.call_method(&jmap, "put", "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/lang/Object;",
+                            &[JValue::Object(&key), JValue::Object(&value)])?;
+            env.delete_local_ref(key)?;
+            env.delete_local_ref(value)?;
+        }
+        Ok(jmap)
+    }
+
+    fn value2jobject<'local>(env: &mut JNIEnv<'local>, value: &Value) -> JniResult<JObject<'local>> {
+        match value {
+            Value::Null => Ok(JObject::null()),
+            Value::Bool(b) => env.call_static_method(CLASS_BOOLEAN, "valueOf", "(Z)Ljava/lang/Boolean;", &[JValue::Bool(*b as u8)])?.l(),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => env.call_static_method(CLASS_LONG, "valueOf", "(J)Ljava/lang/Long;", &[JValue::Long(i)])?.l(),
+                None => env.call_static_method(CLASS_DOUBLE, "valueOf", "(D)Ljava/lang/Double;", &[JValue::Double(n.as_f64().unwrap_or_default())])?.l(),
+            },
+            Value::String(s) => Ok(JObject::from(env.new_string(s)?)),
+            Value::Array(arr) => {
+                let jlist = env.new_object("java/util/ArrayList", "()V", &[])?;
+                for item in arr {
+                    let item = value2jobject(env, item)?;
+                    env.call_method(&jlist, "add", "(Ljava/lang/Object;)Z", &[JValue::Object(&item)])?;
+                    env.delete_local_ref(item)?;
+                }
+                Ok(jlist)
+            },
+            Value::Object(map) => map2jmap(env, map),
+        }
+    }
+
     fn jlist2value<'local>(env: &mut JNIEnv<'local>, jlist: JList) -> JniResult<Value> {
         let mut list: Vec<Value> = Vec::new();
         let mut iterator = jlist.iter(env)?;
@@ -196,9 +568,17 @@ mod parser {
             let value = env.call_method(&jobject, "charValue", "()C", &[])?;
             env.delete_local_ref(jobject)?;
             Ok(Some(Value::from(value.c()?.to_owned())))
+        } else if env.is_instance_of(&jobject, CLASS_DATE)? {
+            let value = env.call_method(&jobject, "getTime", "()J", &[])?;
+            env.delete_local_ref(jobject)?;
+            Ok(fmt_millis_iso8601(value.j()?).map(Value::from))
+        } else if env.is_instance_of(&jobject, CLASS_INSTANT)? {
+            let value = env.call_method(&jobject, "toEpochMilli", "()J", &[])?;
+            env.delete_local_ref(jobject)?;
+            Ok(fmt_millis_iso8601(value.j()?).map(Value::from))
         } else {
             // might be array type.
-            log_error!("Only accepting primitives, List and Map as value type! (note: currently Array is not supported)");
+            log_error!("Only accepting primitives, Date, Instant, List and Map as value type! (note: currently Array is not supported)");
             if !key.is_empty() {
                 log_error!("The key \"{key}\" and associated value will be ignored!")
             }
@@ -14,6 +14,32 @@ local dt_base = loadBaseLib()
 
 local Util = {}
 local DTLog = {}
+local DTError = {}
+DTError.__index = DTError
+DTError.__tostring = function(err)
+    return "[DT Lua][" .. tostring(err.code) .. "] " .. tostring(err.message)
+end
+
+--- Raises the error table {code, message} returned by dt_base, which is only returned in throw mode.
+local function raise(err)
+    if err ~= nil then
+        error(setmetatable(err, DTError))
+    end
+end
+
+--- Returns the result, or raises the error of dt_base.
+local function check(ret, err)
+    raise(err)
+    return ret
+end
+
+--- Returns the result of the upload in pcall, or raises the error of dt_base.
+local function unwrap(ok, ret, err)
+    raise(err)
+    if ok then
+        return ret
+    end
+end
 
 local function class(base, _ctor)
     local c = {}
@@ -57,36 +83,25 @@ end
 ---@param eventType string
 ---@param eventName string
 ---@param properties table
----@param superProperties table
----@param dynamicSuperPropertiesTracker function
-local function upload(dtId, acId, eventType, eventName, properties, superProperties, dynamicSuperPropertiesTracker, debug)
-    local dynamicSuperProperties = {}
-    if dynamicSuperPropertiesTracker ~= nil and type(dynamicSuperPropertiesTracker) == "function" then
-        dynamicSuperProperties = dynamicSuperPropertiesTracker()
-    end
-
-    if acId ~= nil and string.len(acId) ~= 0 then
-        properties["#acid"] = tostring(acId)
-    end
-
-    if dtId ~= nil and string.len(dtId) ~= 0 then
-        properties["#dt_id"] = tostring(dtId)
-    end
-
-    properties["#event_type"] = eventType
-    properties["#event_name"] = tostring(eventName)
-
+---@param ctx any user context created by dt_base.with_user, optional
+local function upload(dtId, acId, eventType, eventName, properties, debug, ctx)
     if debug ~= nil and type(debug) == type(true) then
         properties["#debug"] = debug
     end
-    if eventType == "track" then
-        properties = Util.mergeTables(properties, superProperties)
-        properties = Util.mergeTables(properties, dynamicSuperProperties)
+    if acId ~= nil then
+        acId = tostring(acId)
     end
-    properties["#sdk_type"] = DTAnalytics.platform
 
-    result = dt_base.add_event(properties)
-    return result
+    local result, err
+    if ctx ~= nil then
+        result, err = ctx:add_event(tostring(eventName), eventType, properties, DTAnalytics.platform)
+    elseif eventType == "track" then
+        result, err = dt_base.track(tostring(dtId), acId, tostring(eventName), properties, DTAnalytics.platform)
+    else
+        -- "#user_set" is reported by dt_base.user_set, etc.
+        result, err = dt_base[string.sub(eventName, 2)](tostring(dtId), acId, properties, DTAnalytics.platform)
+    end
+    return result, err
 end
 
 ---
@@ -98,10 +113,8 @@ DTAnalytics = class(function(self, consumer, debug)
         DTLog.error("consumer params is invalidate.")
         return
     end
-    self.superProperties = {}
-    self.dynamicSuperPropertiesTracker = nil
     self.debug = debug
-    dt_base.init(consumer.consumerProps)
+    check(dt_base.init(consumer.consumerProps))
 
     DTLog.info("SDK init success")
 end)
@@ -113,11 +126,62 @@ function DTAnalytics.enableLog(enable)
     dt_base.enable_log(enable)
 end
 
---- Set common properties
+--- Write the logs to stderr, which is the default.
+function DTAnalytics.logToStderr()
+    dt_base.log_to_stderr()
+end
+
+--- Write the logs to the file, which is rotated by size.
+---@param path string Path of the log file, the rotated ones are named "{path}.1" (the latest) to "{path}.{maxFiles}".
+---@param maxBytes number Max size of the log file in bytes.
+---@param maxFiles number Max number of the rotated files to keep.
+function DTAnalytics.logToFile(path, maxBytes, maxFiles)
+    raise(dt_base.log_to_file(path, maxBytes, maxFiles))
+end
+
+--- Minimum level of the logs, "debug" (default), "info", "warning", "error" or "silent", which disables even the errors.
+--- Logs below "warning" are only written if the logging is enabled.
+---@param level string
+function DTAnalytics.setLogLevel(level)
+    raise(dt_base.set_log_level(level))
+end
+
+--- Format of the logs written to stderr and file, "text" (default) or "json", which writes a JSON object per line
+--- with "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+---@param format string
+function DTAnalytics.setLogFormat(format)
+    raise(dt_base.set_log_format(format))
+end
+
+--- Forward the logs to the callback, with the level ("debug", "info", "warning" or "error") and the message.
+--- Logs hit outside the calls to DTAnalytics (e.g. by the threads of core) go to stderr instead.
+---@param callback function
+function DTAnalytics.setLogCallback(callback)
+    if callback ~= nil and type(callback) == "function" then
+        dt_base.set_log_callback(callback)
+    end
+end
+
+--- Forward the logs to print.
+function DTAnalytics.logToPrint()
+    dt_base.set_log_callback(function(level, message)
+        print("[DT Core][" .. os.date("%Y-%m-%d %H:%M:%S") .. "][" .. level .. "] " .. message)
+    end)
+end
+
+--- Raise error table {code, message} on error instead of logging and returning nil (or default value).
+--- The code is stable across SDKs, e.g. 1 for invalid event or properties. Disabled by default.
+---@param enable boolean
+function DTAnalytics.setThrowOnError(enable)
+    dt_base.set_throw_on_error(enable)
+end
+
+--- Set common properties, merged into the existing ones.
+--- Properties of the event take precedence over the common ones.
 ---@param params table
 function DTAnalytics:setSuperProperties(params)
     if (type(params) == "table") then
-        self.superProperties = Util.mergeTables(self.superProperties, params)
+        raise(dt_base.merge_static_common_properties(params))
     end
 end
 
@@ -128,7 +192,6 @@ function DTAnalytics:setSuperProperty(key, value)
     if (key ~= nil) then
         local params = {}
         params[key] = value
-        DTLog.info(params[key])
         self:setSuperProperties(params)
     end
 end
@@ -139,7 +202,7 @@ function DTAnalytics:removeSuperProperty(key)
     if key == nil then
         return nil
     end
-    self.superProperties[key] = nil
+    raise(dt_base.unset_static_common_property(key))
 end
 
 --- Find common properties with key
@@ -148,18 +211,18 @@ function DTAnalytics:getSuperProperty(key)
     if key == nil then
         return nil
     end
-    return self.superProperties[key]
+    return check(dt_base.get_static_common_properties())[key]
 end
 
---- Get all properties
+--- Get all properties, a copy of the common properties
 ---@return table
 function DTAnalytics:getSuperProperties()
-    return self.superProperties
+    return check(dt_base.get_static_common_properties())
 end
 
 --- Clear common properties
 function DTAnalytics:clearSuperProperties()
-    self.superProperties = {}
+    raise(dt_base.clear_static_common_properties())
 end
 
 --- Set user properties. Would overwrite existing names
@@ -167,10 +230,7 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userSet(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_set", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_set", properties, self.debug))
 end
 
 --- Set user properties, if such property had been set before, this message would be neglected.
@@ -178,10 +238,7 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userSetOnce(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_set_once", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_set_once", properties, self.debug))
 end
 
 --- To accumulate operations against the property
@@ -189,10 +246,7 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userAdd(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_add", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_add", properties, self.debug))
 end
 
 --- To add user properties of array type
@@ -200,10 +254,7 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userAppend(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_append", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_append", properties, self.debug))
 end
 
 --- Append user properties to array type by unique.
@@ -211,10 +262,7 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userUniqAppend(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_uniq_append", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_uniq_append", properties, self.debug))
 end
 
 --- Clear the user properties of users
@@ -222,28 +270,14 @@ end
 ---@param dtId string
 ---@param properties table
 function DTAnalytics:userUnset(acId, dtId, properties)
-    local unSetProperties = {}
-    for key, value in pairs(properties) do
-        if Util.startWith(key, '#')then
-            unSetProperties[key] = value
-        else
-            unSetProperties[key] = 0
-        end
-    end
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_unset", unSetProperties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_unset", properties, self.debug))
 end
 
 --- Delete a user, This operation cannot be undone
 ---@param acId string
 ---@param dtId string
 function DTAnalytics:userDelete(acId, dtId, properties)
-    local ok, ret = pcall(upload, dtId, acId, "user", "#user_delete", properties, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "user", "#user_delete", properties, self.debug))
 end
 
 --- Report ordinary event
@@ -252,23 +286,56 @@ end
 ---@param eventName string
 ---@param properties table
 function DTAnalytics:track(acId, dtId, eventName, properties)
-    local ok, ret = pcall(upload, dtId, acId, "track", eventName, properties, self.superProperties, self.dynamicSuperPropertiesTracker, self.debug)
-    if ok then
-        return ret
-    end
+    return unwrap(pcall(upload, dtId, acId, "track", eventName, properties, self.debug))
 end
 
 --- Flush data
 function DTAnalytics:flush()
-    dt_base.flush()
+    raise(dt_base.flush())
+end
+
+--- Snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason), "bytes_written",
+--- "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+--- Counters are monotonic since the process started.
+---@return table
+function DTAnalytics:stats()
+    return dt_base.stats()
+end
+
+--- The runtime metrics in Prometheus text exposition format, e.g. "dt_events_accepted_total 10".
+---@return string
+function DTAnalytics:prometheusMetrics()
+    return dt_base.prometheus_metrics()
+end
+
+--- Serve the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to scrape.
+--- Only one server is allowed, which can also be started by "metrics_addr" in the init config.
+---@param addr string the address to listen, e.g. "127.0.0.1:9464"
+---@return string the bound address
+function DTAnalytics:serveMetrics(addr)
+    local bound, err = dt_base.serve_metrics(addr)
+    raise(err)
+    return bound
 end
 
 --- Close SDK
 function DTAnalytics:close()
-    dt_base.close()
+    raise(dt_base.close())
     DTLog.info("SDK closed!")
 end
 
+--- Close SDK within the timeout, the events not sent in time are dropped and reported to the error hook,
+--- e.g. by a hung upload. The timeout only applies to the consumers uploading by workers, LogConsumer writes all the
+--- events to the file before returning.
+---@param timeoutMs number
+---@return table the numbers of events "sent" and "remaining" while closing
+function DTAnalytics:closeWithTimeout(timeoutMs)
+    local outcome, err = dt_base.close_with_timeout(timeoutMs)
+    raise(err)
+    DTLog.info("SDK closed!")
+    return outcome
+end
+
 
 --- Construct LogConsumer
 ---@param self any
@@ -286,14 +353,117 @@ DTAnalytics.DTLogConsumer = class(function(self, logPath, batchNum, fileSize, fi
     }
 end)
 
---- Set dynamic common properties
+--- Set dynamic common properties, the callback is invoked for each event and should return a table.
+--- Properties of the event take precedence over the dynamic ones.
+--- Errors raised by the callback are logged and the event is tracked without dynamic common properties.
 ---@param callback function
 function DTAnalytics:setDynamicSuperProperties(callback)
-    if callback ~= nil then
-        self.dynamicSuperPropertiesTracker = callback
+    if callback ~= nil and type(callback) == "function" then
+        raise(dt_base.set_dynamic_common_properties(callback))
+    end
+end
+
+--- Clear dynamic common properties
+function DTAnalytics:clearDynamicSuperProperties()
+    raise(dt_base.clear_dynamic_common_properties())
+end
+
+--- Set the hook receiving each error hit during the calls, e.g. to count the dropped events.
+--- The callback receives the error {code, message}, the event (nil if absent) and the category,
+--- i.e. "add", "flush" or "write". It should be fast and must not call the APIs of DTAnalytics.
+--- Errors raised by the callback are logged.
+---@param callback function
+---@param redact boolean If true, the event only keeps meta properties identifying it, like "#event_name".
+function DTAnalytics:setErrorHook(callback, redact)
+    if callback ~= nil and type(callback) == "function" then
+        raise(dt_base.set_error_hook(function(err, event, category)
+            callback(setmetatable(err, DTError), event, category)
+        end, redact == true))
+    end
+end
+
+--- Clear the error hook
+function DTAnalytics:clearErrorHook()
+    raise(dt_base.clear_error_hook())
+end
+
+
+--- Create a context to report events of the user, which fills acId, dtId and the user-scoped common properties.
+--- The user-scoped common properties take precedence over the dynamic and static ones.
+---@param acId string
+---@param dtId string
+---@param properties table user-scoped common properties, added to each event of the user
+---@return any DTUserContext, or nil if given properties are invalid
+function DTAnalytics:withUser(acId, dtId, properties)
+    if dtId == nil then
+        DTLog.error("dtId of user context is required.")
+        return nil
+    end
+    local ctx = check(dt_base.with_user(tostring(dtId), acId ~= nil and tostring(acId) or nil, properties))
+    if ctx == nil then
+        return nil
     end
+    return DTAnalytics.DTUserContext(ctx, self.debug)
+end
+
+--- Reports events of a user, use DTAnalytics:withUser to create
+DTAnalytics.DTUserContext = class(function(self, ctx, debug)
+    self.ctx = ctx
+    self.debug = debug
+end)
+
+local function uploadWithContext(self, eventType, eventName, properties)
+    return unwrap(pcall(upload, nil, nil, eventType, eventName, properties, self.debug, self.ctx))
+end
+
+--- Report ordinary event of this user
+---@param eventName string
+---@param properties table
+function DTAnalytics.DTUserContext:track(eventName, properties)
+    return uploadWithContext(self, "track", eventName, properties)
+end
+
+--- Set properties of this user
+---@param properties table
+function DTAnalytics.DTUserContext:userSet(properties)
+    return uploadWithContext(self, "user", "#user_set", properties)
+end
+
+--- Set properties of this user, if such property had been set before, this message would be neglected.
+---@param properties table
+function DTAnalytics.DTUserContext:userSetOnce(properties)
+    return uploadWithContext(self, "user", "#user_set_once", properties)
+end
+
+--- To accumulate operations against the property of this user
+---@param properties table
+function DTAnalytics.DTUserContext:userAdd(properties)
+    return uploadWithContext(self, "user", "#user_add", properties)
 end
 
+--- To add properties of array type of this user
+---@param properties table
+function DTAnalytics.DTUserContext:userAppend(properties)
+    return uploadWithContext(self, "user", "#user_append", properties)
+end
+
+--- Append properties to array type by unique of this user
+---@param properties table
+function DTAnalytics.DTUserContext:userUniqAppend(properties)
+    return uploadWithContext(self, "user", "#user_uniq_append", properties)
+end
+
+--- Clear the properties of this user
+---@param properties table
+function DTAnalytics.DTUserContext:userUnset(properties)
+    return uploadWithContext(self, "user", "#user_unset", properties)
+end
+
+--- Delete this user, This operation cannot be undone
+---@param properties table
+function DTAnalytics.DTUserContext:userDelete(properties)
+    return uploadWithContext(self, "user", "#user_delete", properties)
+end
 
 DTAnalytics.platform = "dt_lua_sdk"
 
@@ -1,9 +1,24 @@
+use std::cell::Cell;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use mlua::prelude::*;
-use mlua::{Table, Value};
+use mlua::{Function, Table, Value};
 use serde_json::Map;
+use common::event::tracking::assemble;
 use common::log_error;
-use common::util::result::{dissolve, dissolve_bool};
+use common::util::error::DTError;
+use common::util::error_hook::ErrorCategory;
+use common::util::logger::{fmt_text, install_default_logger, Level, level_name, LogFormat, LogSink, parse_log_level, set_log_sink};
+use common::util::result::{dissolve, dissolve_bool, dissolve_or_default, THROW_ON_ERROR};
+
+static DYNAMIC_PROPS_KEY: &str = "dt_dynamic_common_properties";
+static ERROR_HOOK_KEY: &str = "dt_error_hook";
+static LOG_CALLBACK_KEY: &str = "dt_log_callback";
+
+thread_local! {
+    // Lua state of the ongoing call, for invoking the Lua callback from core.
+    static CURRENT_LUA: Cell<Option<*const Lua>> = const { Cell::new(None) };
+}
 
 #[mlua::lua_module]
 fn dt_core_lua(lua: &Lua) -> LuaResult<LuaTable> {
@@ -12,25 +27,246 @@ fn dt_core_lua(lua: &Lua) -> LuaResult<LuaTable> {
     exports.set("add_event", lua.create_function(add_event)?)?;
     exports.set("flush", lua.create_function(flush)?)?;
     exports.set("close", lua.create_function(close)?)?;
+    exports.set("close_with_timeout", lua.create_function(close_with_timeout)?)?;
+    exports.set("stats", lua.create_function(stats)?)?;
+    exports.set("prometheus_metrics", lua.create_function(prometheus_metrics)?)?;
+    exports.set("serve_metrics", lua.create_function(serve_metrics)?)?;
     exports.set("enable_log", lua.create_function(toggle_logger)?)?;
+    exports.set("log_to_stderr", lua.create_function(log_to_stderr)?)?;
+    exports.set("log_to_file", lua.create_function(log_to_file)?)?;
+    exports.set("set_log_callback", lua.create_function(set_log_callback)?)?;
+    exports.set("set_log_format", lua.create_function(set_log_format)?)?;
+    exports.set("set_log_level", lua.create_function(set_log_level)?)?;
+    exports.set("set_throw_on_error", lua.create_function(set_throw_on_error)?)?;
+    exports.set("merge_static_common_properties", lua.create_function(merge_static_common_properties)?)?;
+    exports.set("unset_static_common_property", lua.create_function(unset_static_common_property)?)?;
+    exports.set("get_static_common_properties", lua.create_function(get_static_common_properties)?)?;
+    exports.set("clear_static_common_properties", lua.create_function(clear_static_common_properties)?)?;
+    exports.set("track", lua.create_function(track)?)?;
+    exports.set("user_set", lua.create_function(user_set)?)?;
+    exports.set("user_set_once", lua.create_function(user_set_once)?)?;
+    exports.set("user_add", lua.create_function(user_add)?)?;
+    exports.set("user_unset", lua.create_function(user_unset)?)?;
+    exports.set("user_delete", lua.create_function(user_delete)?)?;
+    exports.set("user_append", lua.create_function(user_append)?)?;
+    exports.set("user_uniq_append", lua.create_function(user_uniq_append)?)?;
+    exports.set("with_user", lua.create_function(with_user)?)?;
+    exports.set("set_dynamic_common_properties", lua.create_function(set_dynamic_common_properties)?)?;
+    exports.set("clear_dynamic_common_properties", lua.create_function(clear_dynamic_common_properties)?)?;
+    exports.set("set_error_hook", lua.create_function(set_error_hook)?)?;
+    exports.set("clear_error_hook", lua.create_function(clear_error_hook)?)?;
     Ok(exports)
 }
 
-fn init(_: &Lua, table: Table) -> LuaResult<bool> {
-    dissolve_bool(common::init_by_config(MyTable(table).into()))
+fn init<'lua>(lua: &'lua Lua, table: Table<'lua>) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    install_default_logger();
+    dissolve_bool_or_table(lua, common::init_by_config(MyTable(table).into()))
 }
 
-fn add_event(_: &Lua, table: Table) -> LuaResult<bool> {
+fn add_event<'lua>(lua: &'lua Lua, table: Table<'lua>) -> LuaResult<(bool, Option<Table<'lua>>)> {
     let map: Map<String, serde_json::Value> = MyTable(table).into();
-    dissolve_bool(common::add(map))
+    call_with_lua(lua, || common::add(map))
+}
+
+fn track<'lua>(lua: &'lua Lua, (dt_id, ac_id, event_name, table, sdk_type): (String, Option<String>, String, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::track(dt_id, ac_id, &event_name, props, &sdk_type))
+}
+
+fn user_set<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_set(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_set_once<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_set_once(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_add<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_add(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_unset<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_unset(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_delete<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_delete(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_append<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_append(dt_id, ac_id, props, &sdk_type))
+}
+
+fn user_uniq_append<'lua>(lua: &'lua Lua, (dt_id, ac_id, table, sdk_type): (String, Option<String>, Table<'lua>, String)) -> LuaResult<(bool, Option<Table<'lua>>)> {
+    let props = MyTable(table).into();
+    call_with_lua(lua, || common::user_uniq_append(dt_id, ac_id, props, &sdk_type))
+}
+
+fn call_with_lua<F>(lua: &Lua, f: F) -> LuaResult<(bool, Option<Table<'_>>)> where F: FnOnce() -> common::util::error::Result<()> {
+    dissolve_bool_or_table(lua, with_lua(lua, f))
+}
+
+/// Keeps the Lua state during the call, for the dynamic common properties callback and the error hook.
+fn with_lua<T, F>(lua: &Lua, f: F) -> T where F: FnOnce() -> T {
+    CURRENT_LUA.with(|it| it.set(Some(lua as *const Lua)));
+    let result = f();
+    CURRENT_LUA.with(|it| it.set(None));
+    result
+}
+
+/// Returns false and the error table in throw mode if failed.
+fn dissolve_bool_or_table<T>(lua: &Lua, result: common::util::error::Result<T>) -> LuaResult<(bool, Option<Table<'_>>)> {
+    match dissolve_bool(result) {
+        Ok(success) => Ok((success, None)),
+        Err(e) => Ok((false, Some(error_table(lua, &e)?))),
+    }
+}
+
+/// Returns the error table in throw mode if failed.
+fn dissolve_or_table<T>(lua: &Lua, result: common::util::error::Result<T>) -> LuaResult<Option<Table<'_>>> {
+    match dissolve(result) {
+        Ok(_) => Ok(None),
+        Err(e) => Ok(Some(error_table(lua, &e)?)),
+    }
+}
+
+/// Error table {code = DTError::code(), message = ...}, for the Lua SDK to raise.
+fn error_table<'lua>(lua: &'lua Lua, e: &DTError) -> LuaResult<Table<'lua>> {
+    let table = lua.create_table_with_capacity(0, 2)?;
+    table.set("code", e.code())?;
+    table.set("message", e.to_string())?;
+    Ok(table)
+}
+
+/// Returns nil (and the error table in throw mode) if given properties are invalid.
+fn with_user<'lua>(lua: &'lua Lua, (dt_id, ac_id, table): (String, Option<String>, Option<Table<'lua>>)) -> LuaResult<(Option<UserContext>, Option<Table<'lua>>)> {
+    let props = table.map(|it| MyTable(it).into()).unwrap_or_default();
+    match dissolve_or_default(common::with_user(dt_id, ac_id, props).map(|ctx| Some(UserContext(ctx)))) {
+        Ok(ctx) => Ok((ctx, None)),
+        Err(e) => Ok((None, Some(error_table(lua, &e)?))),
+    }
+}
+
+struct UserContext(common::event::user_context::UserContext);
+
+impl LuaUserData for UserContext {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("add_event", |lua, this, (event_name, event_type, table, sdk_type): (String, String, Table, String)| {
+            let props = MyTable(table).into();
+            call_with_lua(lua, || this.0.add(assemble(&event_name, &event_type, props, &sdk_type)))
+        });
+    }
+}
+
+fn merge_static_common_properties<'lua>(lua: &'lua Lua, table: Table<'lua>) -> LuaResult<Option<Table<'lua>>> {
+    dissolve_or_table(lua, common::merge_static_common_props(MyTable(table).into()))
+}
+
+fn unset_static_common_property(lua: &Lua, key: String) -> LuaResult<Option<Table<'_>>> {
+    dissolve_or_table(lua, common::unset_static_common_prop(&key))
+}
+
+fn get_static_common_properties<'lua>(lua: &'lua Lua, _: ()) -> LuaResult<(Table<'lua>, Option<Table<'lua>>)> {
+    match dissolve_or_default(common::get_static_common_props()) {
+        Ok(props) => Ok((map2table(lua, &props)?, None)),
+        Err(e) => Ok((lua.create_table()?, Some(error_table(lua, &e)?))),
+    }
+}
+
+fn clear_static_common_properties(lua: &Lua, _: ()) -> LuaResult<Option<Table<'_>>> {
+    dissolve_or_table(lua, common::clear_static_common_props())
+}
+
+fn set_dynamic_common_properties<'lua>(lua: &'lua Lua, callback: Function<'lua>) -> LuaResult<Option<Table<'lua>>> {
+    lua.set_named_registry_value(DYNAMIC_PROPS_KEY, callback)?;
+    dissolve_or_table(lua, common::set_dynamic_common_props(get_dynamic_props))
+}
+
+fn clear_dynamic_common_properties(lua: &Lua, _: ()) -> LuaResult<Option<Table<'_>>> {
+    lua.unset_named_registry_value(DYNAMIC_PROPS_KEY)?;
+    dissolve_or_table(lua, common::clear_dynamic_common_props())
+}
+
+/// Invoked by core within add_event(), hence by the thread owning the Lua state.
+fn get_dynamic_props() -> common::util::error::Result<Map<String, serde_json::Value>> {
+    let Some(lua) = CURRENT_LUA.with(|it| it.get()) else {
+        return Ok(Map::new());
+    };
+    // Safety: the pointer is only set during add_event(), where the Lua state is borrowed.
+    let lua = unsafe { &*lua };
+    let props = lua.named_registry_value::<Option<Function>>(DYNAMIC_PROPS_KEY)
+        .and_then(|callback| match callback {
+            Some(callback) => callback.call::<_, Option<Table<'_>>>(()),
+            None => Ok(None),
+        })
+        .map_err(|e| DTError::HostError(format!("Dynamic common properties callback failed: {e}")))?;
+    Ok(props.map(|props| MyTable(props).into()).unwrap_or_default())
 }
 
-fn flush(_: &Lua, _: ()) -> LuaResult<()> {
-    dissolve(common::flush())
+fn set_error_hook<'lua>(lua: &'lua Lua, (callback, redact): (Function<'lua>, Option<bool>)) -> LuaResult<Option<Table<'lua>>> {
+    lua.set_named_registry_value(ERROR_HOOK_KEY, callback)?;
+    dissolve_or_table(lua, common::set_error_hook(on_error, redact.unwrap_or(false)))
 }
 
-fn close(_: &Lua, _: ()) -> LuaResult<()> {
-    dissolve(common::close())
+fn clear_error_hook(lua: &Lua, _: ()) -> LuaResult<Option<Table<'_>>> {
+    lua.unset_named_registry_value(ERROR_HOOK_KEY)?;
+    dissolve_or_table(lua, common::clear_error_hook())
+}
+
+/// Invoked by core on the thread hitting the error, skipped unless it's during a call from Lua.
+fn on_error(e: &DTError, event: Option<&Map<String, serde_json::Value>>, category: ErrorCategory) {
+    let Some(lua) = CURRENT_LUA.with(|it| it.get()) else {
+        return;
+    };
+    // Safety: the pointer is only set during the calls from Lua, where the Lua state is borrowed.
+    let lua = unsafe { &*lua };
+    let result = lua.named_registry_value::<Option<Function>>(ERROR_HOOK_KEY).and_then(|callback| {
+        let Some(callback) = callback else {
+            return Ok(());
+        };
+        let event = event.map(|event| map2table(lua, event)).transpose()?;
+        callback.call::<_, ()>((error_table(lua, e)?, event, category.as_str()))
+    });
+    if let Err(err) = result {
+        log_error!("Error hook failed, {err}");
+    }
+}
+
+fn flush(lua: &Lua, _: ()) -> LuaResult<Option<Table<'_>>> {
+    dissolve_or_table(lua, with_lua(lua, common::flush))
+}
+
+fn close(lua: &Lua, _: ()) -> LuaResult<Option<Table<'_>>> {
+    dissolve_or_table(lua, with_lua(lua, common::close))
+}
+
+fn close_with_timeout(lua: &Lua, timeout_ms: u64) -> LuaResult<(Option<Table<'_>>, Option<Table<'_>>)> {
+    let timeout = Duration::from_millis(timeout_ms);
+    match dissolve_or_default(with_lua(lua, || common::close_with_timeout(timeout).map(Some))) {
+        Ok(outcome) => Ok((outcome.map(|it| map2table(lua, &it.to_map())).transpose()?, None)),
+        Err(e) => Ok((None, Some(error_table(lua, &e)?))),
+    }
+}
+
+fn stats(lua: &Lua, _: ()) -> LuaResult<Table<'_>> {
+    map2table(lua, &common::stats())
+}
+
+fn prometheus_metrics(_: &Lua, _: ()) -> LuaResult<String> {
+    Ok(common::prometheus_metrics())
+}
+
+fn serve_metrics(lua: &Lua, addr: String) -> LuaResult<(Option<String>, Option<Table<'_>>)> {
+    match dissolve_or_default(common::serve_metrics(&addr).map(|addr| Some(addr.to_string()))) {
+        Ok(bound) => Ok((bound, None)),
+        Err(e) => Ok((None, Some(error_table(lua, &e)?))),
+    }
 }
 
 fn toggle_logger(_: &Lua, enable: bool) -> LuaResult<()> {
@@ -38,6 +274,77 @@ fn toggle_logger(_: &Lua, enable: bool) -> LuaResult<()> {
     Ok(())
 }
 
+fn log_to_stderr(_: &Lua, _: ()) -> LuaResult<()> {
+    set_log_sink(LogSink::Stderr);
+    Ok(())
+}
+
+fn log_to_file(lua: &Lua, (path, max_bytes, max_files): (String, u64, usize)) -> LuaResult<Option<Table<'_>>> {
+    match dissolve_or_default(LogSink::file(path, max_bytes, max_files).map(Some)) {
+        Ok(sink) => {
+            if let Some(sink) = sink {
+                set_log_sink(sink);
+            }
+            Ok(None)
+        },
+        Err(e) => Ok(Some(error_table(lua, &e)?)),
+    }
+}
+
+fn set_log_format(lua: &Lua, format: String) -> LuaResult<Option<Table<'_>>> {
+    match dissolve_or_default(LogFormat::parse(&format).map(Some)) {
+        Ok(format) => {
+            if let Some(format) = format {
+                common::util::logger::set_log_format(format);
+            }
+            Ok(None)
+        },
+        Err(e) => Ok(Some(error_table(lua, &e)?)),
+    }
+}
+
+fn set_log_level(lua: &Lua, level: String) -> LuaResult<Option<Table<'_>>> {
+    match dissolve_or_default(parse_log_level(&level).map(Some)) {
+        Ok(level) => {
+            if let Some(level) = level {
+                common::util::logger::set_log_level(level);
+            }
+            Ok(None)
+        },
+        Err(e) => Ok(Some(error_table(lua, &e)?)),
+    }
+}
+
+fn set_log_callback<'lua>(lua: &'lua Lua, callback: Function<'lua>) -> LuaResult<()> {
+    lua.set_named_registry_value(LOG_CALLBACK_KEY, callback)?;
+    set_log_sink(LogSink::Callback(Box::new(on_log)));
+    Ok(())
+}
+
+/// Logs hit outside the calls from Lua go to stderr, since the Lua state is not thread-safe.
+fn on_log(level: Level, msg: &str) {
+    let Some(lua) = CURRENT_LUA.with(|it| it.get()) else {
+        eprintln!("{}", fmt_text(level, msg));
+        return;
+    };
+    // Safety: the pointer is only set during the calls from Lua, where the Lua state is borrowed.
+    let lua = unsafe { &*lua };
+    let result = lua.named_registry_value::<Option<Function>>(LOG_CALLBACK_KEY).and_then(|callback| match callback {
+        Some(callback) => callback.call::<_, ()>((level_name(level), msg)),
+        None => Ok(()),
+    });
+    if let Err(err) = result {
+        // Not logged by SDK, which would come back here.
+        eprintln!("{}", fmt_text(level, msg));
+        eprintln!("{}", fmt_text(Level::Error, &format!("Log callback failed, {err}")));
+    }
+}
+
+fn set_throw_on_error(_: &Lua, enable: bool) -> LuaResult<()> {
+    THROW_ON_ERROR.store(enable, Ordering::Relaxed);
+    Ok(())
+}
+
 struct MyTable<'a>(Table<'a>);
 
 impl Into<Map<String, serde_json::Value>> for MyTable<'_> {
@@ -127,3 +434,31 @@ impl Into<Option<serde_json::Value>> for MyValue<'_> {
         }
     }
 }
+
+fn map2table<'lua>(lua: &'lua Lua, map: &Map<String, serde_json::Value>) -> LuaResult<Table<'lua>> {
+    let table = lua.create_table_with_capacity(0, map.len())?;
+    for (k, v) in map {
+        table.set(k.as_str(), json2value(lua, v)?)?;
+    }
+    Ok(table)
+}
+
+fn json2value<'lua>(lua: &'lua Lua, value: &serde_json::Value) -> LuaResult<Value<'lua>> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Boolean(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i as LuaInteger),
+            None => Value::Number(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::String(lua.create_string(s)?),
+        serde_json::Value::Array(arr) => {
+            let table = lua.create_table_with_capacity(arr.len(), 0)?;
+            for v in arr {
+                table.raw_push(json2value(lua, v)?)?;
+            }
+            Value::Table(table)
+        },
+        serde_json::Value::Object(map) => Value::Table(map2table(lua, map)?),
+    })
+}
@@ -14,7 +14,80 @@ export function userAppend(dtId: string, acId: string, properties: Record<string
 export function userUniqAppend(dtId: string, acId: string, properties: Record<string, any>): boolean
 export function flush(): void
 export function close(): void
+/**
+ * Closes within the timeout, the events not sent in time are dropped and reported to the error hook,
+ * e.g. by a hung upload. Returns the numbers of events sent and remaining while closing, null if failed.
+ * The timeout only applies to the consumers uploading by workers, the log consumer writes all the events to the file
+ * before returning.
+ */
+export function closeWithTimeout(timeoutMs: number): { sent: number, remaining: number } | null
+/**
+ * Snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason), "bytes_written",
+ * "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+ * Counters are monotonic since the process started.
+ */
+export function stats(): Record<string, any>
+/** The runtime metrics in Prometheus text exposition format, e.g. "dt_events_accepted_total 10". */
+export function prometheusMetrics(): string
+/**
+ * Serves the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to scrape,
+ * e.g. on "127.0.0.1:9464". Only one server is allowed, which can also be started by "metrics_addr" in the init
+ * config. Returns the bound address.
+ */
+export function serveMetrics(addr: string): string
 export function toggleLogger(enable: boolean): void
+export function logToStderr(): void
+/** The rotated files are named "{path}.1" (the latest) to "{path}.{maxFiles}". */
+export function logToFile(path: string, maxBytes: number, maxFiles: number): void
+/**
+ * "debug" (default), "info", "warning", "error" or "silent", which disables even the errors.
+ * Logs below "warning" are only written if the logging is enabled.
+ */
+export function setLogLevel(level: string): void
+/**
+ * "text" (default) or "json", which writes a JSON object per line with
+ * "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+ */
+export function setLogFormat(format: string): void
+/**
+ * The callback receives the level ("debug", "info", "warning" or "error") and the message,
+ * it's invoked on the JS thread asynchronously.
+ */
+export function setLogCallback(callback: (level: string, message: string) => void): void
+/** Forwards the logs to `console.debug`, `console.info`, `console.warn` and `console.error` by the level. */
+export function logToConsole(): void
+/**
+ * To throw Error with `code` (e.g. "DT_ERR_VERIFY") and `errno` (the numeric code) on error,
+ * instead of logging and returning false (or default value).
+ */
+export function setThrowOnError(enable: boolean): void
+/** Returns null if given properties are invalid. */
+export function withUser(dtId: string, acId?: string | undefined | null, properties?: Record<string, any> | undefined | null): UserContext | null
+export function setStaticCommonProperties(properties: Record<string, any>): void
+export function mergeStaticCommonProperties(properties: Record<string, any>): void
+export function unsetStaticCommonProperty(key: string): void
+export function getStaticCommonProperties(): Record<string, any>
+export function clearStaticCommonProperties(): void
+export function setDynamicCommonProperties(callback: () => Record<string, any>): void
+export function clearDynamicCommonProperties(): void
+/**
+ * The callback receives the error (with `code` and `errno`), the event (null if absent)
+ * and the category, i.e. "add", "flush" or "write".
+ * It's only invoked for the errors hit during the calls from JS, since the JS thread is required.
+ */
+export function setErrorHook(callback: (error: Error, event: Record<string, any> | null, category: string) => void, redact?: boolean | undefined | null): void
+export function clearErrorHook(): void
 export class Consumer {
   static DTLogConsumer(path: string, maxBatchLen: number, namePrefix?: string | undefined | null, maxFileSizeBytes?: number | undefined | null): Consumer
 }
+/** Tracks events of a user, with "#dt_id", "#acid" and user-scoped common properties filled. */
+export class UserContext {
+  track(eventName: string, properties: Record<string, any>): boolean
+  userSet(properties: Record<string, any>): boolean
+  userSetOnce(properties: Record<string, any>): boolean
+  userAdd(properties: Record<string, any>): boolean
+  userUnset(properties: Record<string, any>): boolean
+  userDelete(properties: Record<string, any>): boolean
+  userAppend(properties: Record<string, any>): boolean
+  userUniqAppend(properties: Record<string, any>): boolean
+}
@@ -310,7 +310,7 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { init, track, userSet, userSetOnce, userAdd, userUnset, userDelete, userAppend, userUniqAppend, flush, close, toggleLogger, Consumer } = nativeBinding
+const { init, track, userSet, userSetOnce, userAdd, userUnset, userDelete, userAppend, userUniqAppend, flush, close, closeWithTimeout, stats, prometheusMetrics, serveMetrics, toggleLogger, logToStderr, logToFile, setLogLevel, setLogFormat, setLogCallback, logToConsole, setThrowOnError, withUser, setStaticCommonProperties, mergeStaticCommonProperties, unsetStaticCommonProperty, getStaticCommonProperties, clearStaticCommonProperties, setDynamicCommonProperties, clearDynamicCommonProperties, setErrorHook, clearErrorHook, Consumer, UserContext } = nativeBinding
 
 module.exports.init = init
 module.exports.track = track
@@ -323,5 +323,27 @@ module.exports.userAppend = userAppend
 module.exports.userUniqAppend = userUniqAppend
 module.exports.flush = flush
 module.exports.close = close
+module.exports.closeWithTimeout = closeWithTimeout
+module.exports.stats = stats
+module.exports.prometheusMetrics = prometheusMetrics
+module.exports.serveMetrics = serveMetrics
 module.exports.toggleLogger = toggleLogger
+module.exports.logToStderr = logToStderr
+module.exports.logToFile = logToFile
+module.exports.setLogLevel = setLogLevel
+module.exports.setLogFormat = setLogFormat
+module.exports.setLogCallback = setLogCallback
+module.exports.logToConsole = logToConsole
+module.exports.setThrowOnError = setThrowOnError
+module.exports.withUser = withUser
+module.exports.setStaticCommonProperties = setStaticCommonProperties
+module.exports.mergeStaticCommonProperties = mergeStaticCommonProperties
+module.exports.unsetStaticCommonProperty = unsetStaticCommonProperty
+module.exports.getStaticCommonProperties = getStaticCommonProperties
+module.exports.clearStaticCommonProperties = clearStaticCommonProperties
+module.exports.setDynamicCommonProperties = setDynamicCommonProperties
+module.exports.clearDynamicCommonProperties = clearDynamicCommonProperties
+module.exports.setErrorHook = setErrorHook
+module.exports.clearErrorHook = clearErrorHook
 module.exports.Consumer = Consumer
+module.exports.UserContext = UserContext
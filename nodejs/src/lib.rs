@@ -1,92 +1,502 @@
+use std::cell::Cell;
+use std::sync::Mutex;
 use std::sync::atomic::Ordering;
+use std::time::Duration;
+use napi::{Env, JsFunction, JsObject, JsString, JsUnknown, Ref, ValueType};
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
 use napi_derive::napi;
 use serde_json::{Map, Value};
+use common::event::tracking::{assemble, TYPE_TRACK, TYPE_USER};
+use common::util::datetime::fmt_millis_iso8601;
 use common::util::error::DTError;
-use common::util::result::{dissolve, dissolve_bool};
+use common::util::error_hook::ErrorCategory;
+use common::util::logger::{fmt_text, install_default_logger, Level, level_name, LogFormat, LogSink, parse_log_level, set_log_sink};
+use common::util::result::{dissolve_bool, dissolve_or_default, THROW_ON_ERROR};
 
 static SDK_NAME: &'static str = "dt_server_sdk_nodejs";
 
-static TYPE_EVENT: &'static str = "track";
-static TYPE_USER: &'static str = "user";
+static DYNAMIC_PROPS_CALLBACK: Mutex<Option<Ref<()>>> = Mutex::new(None);
+static ERROR_HOOK_CALLBACK: Mutex<Option<Ref<()>>> = Mutex::new(None);
+
+thread_local! {
+    // Env of the ongoing call from JS, for invoking the JS callback from core.
+    static CURRENT_ENV: Cell<Option<napi::sys::napi_env>> = const { Cell::new(None) };
+}
 
 
 #[napi]
-fn init(consumer: &Consumer, debug: Option<bool>) -> bool {
+fn init(env: Env, consumer: &Consumer, debug: Option<bool>) -> bool {
     let mut config = consumer.get_config();
     config.insert("_debug".to_string(), Value::from(debug.unwrap_or(false)));
-    dissolve_bool::<(), DTError>(common::init_by_config(config)).unwrap_or(false)
+    install_default_logger();
+    call_with_env(&env, || common::init_by_config(config))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, eventName: string, properties: Record<string, any>")]
+fn track(env: Env, dt_id: String, ac_id: String, event_name: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::track(dt_id, Some(ac_id), &event_name, props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_set(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_set(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_set_once(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_set_once(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_add(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_add(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_unset(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_unset(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_delete(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_delete(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_append(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_append(dt_id, Some(ac_id), props, SDK_NAME))
+}
+
+#[napi(ts_args_type = "dtId: string, acId: string, properties: Record<string, any>")]
+fn user_uniq_append(env: Env, dt_id: String, ac_id: String, properties: JsObject) -> bool {
+    with_props(&env, properties, |props| common::user_uniq_append(dt_id, Some(ac_id), props, SDK_NAME))
 }
 
 #[napi]
-fn track(dt_id: String, ac_id: String, event_name: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, event_name, TYPE_EVENT, properties)
+fn flush(env: Env) -> () {
+    dissolve_or_throw(&env, with_env(&env, common::flush))
 }
 
 #[napi]
-fn user_set(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_set".to_string(), TYPE_USER, properties)
+fn close(env: Env) -> () {
+    dissolve_or_throw(&env, with_env(&env, common::close))
+}
+
+/// Closes within the timeout, the events not sent in time are dropped and reported to the error hook,
+/// e.g. by a hung upload. Returns the numbers of events sent and remaining while closing, null if failed.
+/// The timeout only applies to the consumers uploading by workers, the log consumer writes all the events to the file
+/// before returning.
+#[napi(ts_return_type = "{ sent: number, remaining: number } | null")]
+fn close_with_timeout(env: Env, timeout_ms: i64) -> Option<Map<String, Value>> {
+    let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+    dissolve_or_throw(&env, with_env(&env, || common::close_with_timeout(timeout).map(Some))).map(|it| it.to_map())
+}
+
+/// Snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason), "bytes_written",
+/// "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+/// Counters are monotonic since the process started.
+#[napi(ts_return_type = "Record<string, any>")]
+fn stats() -> Map<String, Value> {
+    common::stats()
 }
 
+/// The runtime metrics in Prometheus text exposition format, e.g. "dt_events_accepted_total 10".
 #[napi]
-fn user_set_once(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_set_once".to_string(), TYPE_USER, properties)
+fn prometheus_metrics() -> String {
+    common::prometheus_metrics()
 }
 
+/// Serves the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to scrape,
+/// e.g. on "127.0.0.1:9464". Only one server is allowed, which can also be started by "metrics_addr" in the init
+/// config. Returns the bound address.
 #[napi]
-fn user_add(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_add".to_string(), TYPE_USER, properties)
+fn serve_metrics(env: Env, addr: String) -> String {
+    dissolve_or_throw(&env, common::serve_metrics(&addr).map(|addr| addr.to_string()))
 }
 
 #[napi]
-fn user_unset(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_unset".to_string(), TYPE_USER, properties)
+fn toggle_logger(enable: bool) -> () {
+    common::util::logger::LOG_ENABLED.store(enable, Ordering::Relaxed);
+    ()
 }
 
 #[napi]
-fn user_delete(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_delete".to_string(), TYPE_USER, properties)
+fn log_to_stderr() -> () {
+    set_log_sink(LogSink::Stderr);
 }
 
+/// The rotated files are named "{path}.1" (the latest) to "{path}.{maxFiles}".
 #[napi]
-fn user_append(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_append".to_string(), TYPE_USER, properties)
+fn log_to_file(env: Env, path: String, max_bytes: i64, max_files: u32) -> () {
+    let result = LogSink::file(path, max_bytes.max(0) as u64, max_files as usize);
+    if let Some(sink) = dissolve_or_throw(&env, result.map(Some)) {
+        set_log_sink(sink);
+    }
 }
 
+/// "debug" (default), "info", "warning", "error" or "silent", which disables even the errors.
+/// Logs below "warning" are only written if the logging is enabled.
 #[napi]
-fn user_uniq_append(dt_id: String, ac_id: String, properties: Map<String, Value>) -> bool {
-    add_event(dt_id, ac_id, "#user_uniq_append".to_string(), TYPE_USER, properties)
+fn set_log_level(env: Env, level: String) -> () {
+    if let Some(level) = dissolve_or_throw(&env, parse_log_level(&level).map(Some)) {
+        common::util::logger::set_log_level(level);
+    }
 }
 
+/// "text" (default) or "json", which writes a JSON object per line with
+/// "level", "timestamp", "module", "message" and the structured fields like "batch_size".
 #[napi]
-fn flush() -> () {
-    dissolve::<(), DTError>(common::flush()).unwrap_or(())
+fn set_log_format(env: Env, format: String) -> () {
+    if let Some(format) = dissolve_or_throw(&env, LogFormat::parse(&format).map(Some)) {
+        common::util::logger::set_log_format(format);
+    }
 }
 
+/// The callback receives the level ("debug", "info", "warning" or "error") and the message,
+/// it's invoked on the JS thread asynchronously.
+#[napi(ts_args_type = "callback: (level: string, message: string) => void")]
+fn set_log_callback(env: Env, callback: JsFunction) -> () {
+    let result = create_tsfn(&env, &callback, |ctx: ThreadSafeCallContext<(Level, String)>| {
+        Ok(vec![ctx.env.create_string(level_name(ctx.value.0))?, ctx.env.create_string(&ctx.value.1)?])
+    });
+    match result {
+        Ok(tsfn) => set_log_sink(LogSink::Callback(Box::new(move |level, msg| {
+            tsfn.call((level, msg.to_string()), ThreadsafeFunctionCallMode::NonBlocking);
+        }))),
+        Err(e) => common::log_error!("Failed to set log callback, {e}"),
+    }
+}
+
+/// Forwards the logs to `console.debug`, `console.info`, `console.warn` and `console.error` by the level.
 #[napi]
-fn close() -> () {
-    dissolve::<(), DTError>(common::close()).unwrap_or(())
+fn log_to_console(env: Env) -> () {
+    let result = env.get_global()
+        .and_then(|global| global.get_named_property::<JsObject>("console"))
+        .and_then(|console| {
+            let mut tsfns = Vec::with_capacity(4);
+            for name in ["debug", "info", "warn", "error"] {
+                let method = console.get_named_property::<JsFunction>(name)?;
+                tsfns.push(create_tsfn(&env, &method, |ctx: ThreadSafeCallContext<String>| {
+                    Ok(vec![ctx.env.create_string(&ctx.value)?])
+                })?);
+            }
+            Ok(tsfns)
+        });
+    match result {
+        Ok(tsfns) => set_log_sink(LogSink::Callback(Box::new(move |level, msg| {
+            let i = match level {
+                Level::Error => 3,
+                Level::Warn => 2,
+                Level::Info => 1,
+                Level::Debug | Level::Trace => 0,
+            };
+            tsfns[i].call(fmt_text(level, msg), ThreadsafeFunctionCallMode::NonBlocking);
+        }))),
+        Err(e) => common::log_error!("Failed to log to console, {e}"),
+    }
 }
 
+/// Doesn't keep the event loop alive, so that the process can exit.
+fn create_tsfn<T, F>(env: &Env, callback: &JsFunction, f: F) -> napi::Result<ThreadsafeFunction<T, ErrorStrategy::Fatal>>
+    where T: 'static, F: 'static + Send + FnMut(ThreadSafeCallContext<T>) -> napi::Result<Vec<JsString>> {
+    let mut tsfn = callback.create_threadsafe_function(0, f)?;
+    tsfn.unref(env)?;
+    Ok(tsfn)
+}
+
+/// To throw Error with `code` (e.g. "DT_ERR_VERIFY") and `errno` (the numeric code) on error,
+/// instead of logging and returning false (or default value).
 #[napi]
-fn toggle_logger(enable: bool) -> () {
-    common::util::logger::LOG_ENABLED.store(enable, Ordering::Relaxed);
+fn set_throw_on_error(enable: bool) -> () {
+    THROW_ON_ERROR.store(enable, Ordering::Relaxed);
     ()
 }
 
-fn add_event(dt_id: String, ac_id: String, event_name: String, event_type: &'static str, properties: Map<String, Value>) -> bool {
-    let mut event = Map::with_capacity(properties.len() + 6);
+fn with_props<F>(env: &Env, properties: JsObject, f: F) -> bool where F: FnOnce(Map<String, Value>) -> common::util::error::Result<()> {
+    match obj2map(env, &properties) {
+        Ok(properties) => call_with_env(env, || f(properties)),
+        Err(e) => call_with_env(env, || Err(DTError::HostError(format!("Failed to parse properties, {e}")))),
+    }
+}
+
+fn call_with_env<F>(env: &Env, f: F) -> bool where F: FnOnce() -> common::util::error::Result<()> {
+    dissolve_bool(with_env(env, f)).unwrap_or_else(|e| {
+        throw(env, &e);
+        false
+    })
+}
+
+/// Keeps the env during the call, for the dynamic common properties callback and the error hook.
+fn with_env<T, F>(env: &Env, f: F) -> T where F: FnOnce() -> T {
+    CURRENT_ENV.with(|it| it.set(Some(env.raw())));
+    let result = f();
+    CURRENT_ENV.with(|it| it.set(None));
+    result
+}
+
+/// Returns default value if failed, and throws the error as well in throw mode.
+fn dissolve_or_throw<T: Default>(env: &Env, result: common::util::error::Result<T>) -> T {
+    dissolve_or_default(result).unwrap_or_else(|e| {
+        throw(env, &e);
+        T::default()
+    })
+}
+
+fn throw(env: &Env, e: &DTError) {
+    if let Err(err) = create_error(env, e).and_then(|error| env.throw(error)) {
+        common::log_error!("Failed to throw {e}, {err}");
+    }
+}
+
+fn create_error(env: &Env, e: &DTError) -> napi::Result<JsObject> {
+    let code = match e.code() {
+        DTError::CODE_VERIFY => "DT_ERR_VERIFY",
+        DTError::CODE_INTERNAL => "DT_ERR_INTERNAL",
+        DTError::CODE_NETWORK => "DT_ERR_NETWORK",
+        DTError::CODE_REMOTE => "DT_ERR_REMOTE",
+        DTError::CODE_HOST => "DT_ERR_ARGUMENT",
+        _ => "DT_ERR_RUNTIME",
+    };
+    let mut error = env.create_error(napi::Error::from_reason(e.to_string()))?;
+    error.set_named_property("code", env.create_string(code)?)?;
+    error.set_named_property("errno", env.create_int32(e.code())?)?;
+    Ok(error)
+}
+
+/// Returns null if given properties are invalid.
+#[napi(ts_args_type = "dtId: string, acId?: string | undefined | null, properties?: Record<string, any> | undefined | null")]
+fn with_user(env: Env, dt_id: String, ac_id: Option<String>, properties: Option<JsObject>) -> Option<UserContext> {
+    let result = properties.map(|it| obj2map(&env, &it)).transpose()
+        .map_err(|e| DTError::HostError(format!("Failed to parse properties, {e}")))
+        .and_then(|props| common::with_user(dt_id, ac_id, props.unwrap_or_default()));
+    dissolve_or_throw(&env, result.map(|ctx| Some(UserContext { ctx })))
+}
+
+/// Tracks events of a user, with "#dt_id", "#acid" and user-scoped common properties filled.
+#[napi]
+pub struct UserContext {
+    ctx: common::event::user_context::UserContext,
+}
+
+#[napi]
+impl UserContext {
+    fn add_event(&self, env: &Env, event_name: String, event_type: &'static str, properties: JsObject) -> bool {
+        with_props(env, properties, |props| self.ctx.add(assemble(&event_name, event_type, props, SDK_NAME)))
+    }
+
+    #[napi(ts_args_type = "eventName: string, properties: Record<string, any>")]
+    pub fn track(&self, env: Env, event_name: String, properties: JsObject) -> bool {
+        self.add_event(&env, event_name, TYPE_TRACK, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_set(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_set".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_set_once(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_set_once".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_add(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_add".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_unset(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_unset".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_delete(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_delete".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_append(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_append".to_string(), TYPE_USER, properties)
+    }
+
+    #[napi(ts_args_type = "properties: Record<string, any>")]
+    pub fn user_uniq_append(&self, env: Env, properties: JsObject) -> bool {
+        self.add_event(&env, "#user_uniq_append".to_string(), TYPE_USER, properties)
+    }
+}
+
+#[napi(ts_args_type = "properties: Record<string, any>")]
+fn set_static_common_properties(env: Env, properties: JsObject) -> () {
+    let result = obj2map(&env, &properties)
+        .map_err(|e| DTError::HostError(format!("Failed to parse properties, {e}")))
+        .and_then(common::set_static_common_props);
+    dissolve_or_throw(&env, result)
+}
+
+#[napi(ts_args_type = "properties: Record<string, any>")]
+fn merge_static_common_properties(env: Env, properties: JsObject) -> () {
+    let result = obj2map(&env, &properties)
+        .map_err(|e| DTError::HostError(format!("Failed to parse properties, {e}")))
+        .and_then(common::merge_static_common_props);
+    dissolve_or_throw(&env, result)
+}
+
+#[napi]
+fn unset_static_common_property(env: Env, key: String) -> () {
+    dissolve_or_throw(&env, common::unset_static_common_prop(&key))
+}
+
+#[napi(ts_return_type = "Record<string, any>")]
+fn get_static_common_properties(env: Env) -> Map<String, Value> {
+    dissolve_or_throw(&env, common::get_static_common_props())
+}
+
+#[napi]
+fn clear_static_common_properties(env: Env) -> () {
+    dissolve_or_throw(&env, common::clear_static_common_props())
+}
 
-    for (k, v) in properties {
-        event.insert(k, v);
+#[napi(ts_args_type = "callback: () => Record<string, any>")]
+fn set_dynamic_common_properties(env: Env, callback: JsFunction) -> () {
+    let reference = match env.create_reference(callback) {
+        Ok(reference) => reference,
+        Err(e) => {
+            common::log_error!("Failed to set dynamic common properties, {e}");
+            return;
+        }
+    };
+    if let Some(mut old) = DYNAMIC_PROPS_CALLBACK.lock().unwrap().replace(reference) {
+        let _ = old.unref(env);
+    }
+    dissolve_or_throw(&env, common::set_dynamic_common_props(get_dynamic_props))
+}
+
+#[napi]
+fn clear_dynamic_common_properties(env: Env) -> () {
+    if let Some(mut old) = DYNAMIC_PROPS_CALLBACK.lock().unwrap().take() {
+        let _ = old.unref(env);
+    }
+    dissolve_or_throw(&env, common::clear_dynamic_common_props())
+}
+
+/// Invoked by core within add_event(), hence on the JS thread.
+fn get_dynamic_props() -> common::util::error::Result<Map<String, Value>> {
+    let Some(raw_env) = CURRENT_ENV.with(|it| it.get()) else {
+        return Ok(Map::new());
+    };
+    let env = unsafe { Env::from_raw(raw_env) };
+    let guard = DYNAMIC_PROPS_CALLBACK.lock().unwrap();
+    let Some(reference) = guard.as_ref() else {
+        return Ok(Map::new());
+    };
+    let props = env.get_reference_value::<JsFunction>(reference)
+        .and_then(|callback| callback.call_without_args(None))
+        .and_then(|props| props.coerce_to_object())
+        .and_then(|props| obj2map(&env, &props));
+    props.map_err(|e| DTError::HostError(format!("Dynamic common properties callback failed: {e}")))
+}
+
+/// The callback receives the error (with `code` and `errno`), the event (null if absent)
+/// and the category, i.e. "add", "flush" or "write".
+/// It's only invoked for the errors hit during the calls from JS, since the JS thread is required.
+#[napi(ts_args_type = "callback: (error: Error, event: Record<string, any> | null, category: string) => void, redact?: boolean | undefined | null")]
+fn set_error_hook(env: Env, callback: JsFunction, redact: Option<bool>) -> () {
+    let reference = match env.create_reference(callback) {
+        Ok(reference) => reference,
+        Err(e) => {
+            common::log_error!("Failed to set error hook, {e}");
+            return;
+        }
+    };
+    if let Some(mut old) = ERROR_HOOK_CALLBACK.lock().unwrap().replace(reference) {
+        let _ = old.unref(env);
+    }
+    dissolve_or_throw(&env, common::set_error_hook(on_error, redact.unwrap_or(false)))
+}
+
+#[napi]
+fn clear_error_hook(env: Env) -> () {
+    if let Some(mut old) = ERROR_HOOK_CALLBACK.lock().unwrap().take() {
+        let _ = old.unref(env);
+    }
+    dissolve_or_throw(&env, common::clear_error_hook())
+}
+
+/// Invoked by core on the thread hitting the error, skipped unless it's the JS thread.
+fn on_error(e: &DTError, event: Option<&Map<String, Value>>, category: ErrorCategory) {
+    let Some(raw_env) = CURRENT_ENV.with(|it| it.get()) else {
+        return;
+    };
+    let env = unsafe { Env::from_raw(raw_env) };
+    // Released before calling, the hook may set or clear itself.
+    let callback = {
+        let guard = ERROR_HOOK_CALLBACK.lock().unwrap();
+        let Some(reference) = guard.as_ref() else {
+            return;
+        };
+        env.get_reference_value::<JsFunction>(reference)
+    };
+    let result = callback.and_then(|callback| {
+        let error = create_error(&env, e)?.into_unknown();
+        let event = match event {
+            Some(event) => env.to_js_value(event)?,
+            None => env.get_null()?.into_unknown(),
+        };
+        let category = env.create_string(category.as_str())?.into_unknown();
+        callback.call(None, &[error, event, category])
+    });
+    if let Err(err) = result {
+        common::log_error!("Error hook failed, {err}");
     }
+}
+
+/// Converts JS object to map, with `Date` converted to ISO-8601 string.
+fn obj2map(env: &Env, obj: &JsObject) -> napi::Result<Map<String, Value>> {
+    let date_ctor = env.get_global()?.get_named_property::<JsFunction>("Date")?;
+    obj2map_aux(env, obj, &date_ctor)
+}
 
-    event.insert(String::from("#dt_id"), serde_json::Value::from(dt_id));
-    event.insert(String::from("#acid"), serde_json::Value::from(ac_id));
-    event.insert(String::from("#event_name"), serde_json::Value::from(event_name));
-    event.insert(String::from("#event_type"), serde_json::Value::from(event_type));
-    event.insert(String::from("#sdk_type"), serde_json::Value::from(SDK_NAME));
+fn obj2map_aux(env: &Env, obj: &JsObject, date_ctor: &JsFunction) -> napi::Result<Map<String, Value>> {
+    let keys = obj.get_property_names()?;
+    let len = keys.get_array_length()?;
+    let mut map = Map::with_capacity(len as usize);
+    for i in 0..len {
+        let key = keys.get_element::<JsString>(i)?.into_utf8()?.into_owned()?;
+        let value = obj.get_named_property_unchecked::<JsUnknown>(&key)?;
+        map.insert(key, js2value(env, value, date_ctor)?);
+    }
+    Ok(map)
+}
 
-    dissolve_bool::<(), DTError>(common::add(event)).unwrap_or(false)
+fn js2value(env: &Env, value: JsUnknown, date_ctor: &JsFunction) -> napi::Result<Value> {
+    if value.get_type()? != ValueType::Object {
+        return env.from_js_value(value);
+    }
+    if value.instanceof(date_ctor)? {
+        let date = value.coerce_to_object()?;
+        let millis = date.get_named_property::<JsFunction>("getTime")?
+            .call_without_args(Some(&date))?
+            .coerce_to_number()?
+            .get_double()?;
+        // NaN for an invalid Date
+        if !millis.is_finite() {
+            return Err(napi::Error::from_reason("Invalid Date"));
+        }
+        return match fmt_millis_iso8601(millis as i64) {
+            Some(date) => Ok(Value::from(date)),
+            None => Err(napi::Error::from_reason(format!("Date out of range: {millis}"))),
+        };
+    }
+    if value.is_array()? {
+        let arr = value.coerce_to_object()?;
+        let len = arr.get_array_length()?;
+        let mut list = Vec::with_capacity(len as usize);
+        for i in 0..len {
+            list.push(js2value(env, arr.get_element::<JsUnknown>(i)?, date_ctor)?);
+        }
+        return Ok(Value::from(list));
+    }
+    Ok(Value::from(obj2map_aux(env, &value.coerce_to_object()?, date_ctor)?))
 }
 
 #[napi]
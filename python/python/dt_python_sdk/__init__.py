@@ -1,6 +1,23 @@
-from .datatowerai_sdk import DTAnalytics, DTLogConsumer
+from .datatowerai_sdk import (
+    DTAnalytics,
+    DTLogConsumer,
+    DTUserContext,
+    DTException,
+    DTVerifyException,
+    DTInternalException,
+    DTNetworkException,
+    DTRemoteException,
+    DTHostException,
+)
 
 __all__ = [
     "DTAnalytics",
     "DTLogConsumer",
+    "DTUserContext",
+    "DTException",
+    "DTVerifyException",
+    "DTInternalException",
+    "DTNetworkException",
+    "DTRemoteException",
+    "DTHostException",
 ]
@@ -1,12 +1,46 @@
+import logging
 from abc import ABC, abstractmethod
-from typing import Any, Dict, Optional
+from typing import Any, Callable, Dict, Optional
 
 from .dt_core_base_py import (
     init as dt_init,
-    add_event as dt_add_event,
+    track as dt_track,
+    user_set as dt_user_set,
+    user_set_once as dt_user_set_once,
+    user_add as dt_user_add,
+    user_unset as dt_user_unset,
+    user_delete as dt_user_delete,
+    user_append as dt_user_append,
+    user_uniq_append as dt_user_uniq_append,
     flush as dt_flush,
     close as dt_close,
-    toggle_logger as dt_toggle_logger
+    close_with_timeout as dt_close_with_timeout,
+    stats as dt_stats,
+    prometheus_metrics as dt_prometheus_metrics,
+    serve_metrics as dt_serve_metrics,
+    toggle_logger as dt_toggle_logger,
+    log_to_stderr as dt_log_to_stderr,
+    log_to_file as dt_log_to_file,
+    set_log_callback as dt_set_log_callback,
+    set_log_format as dt_set_log_format,
+    set_log_level as dt_set_log_level,
+    set_throw_on_error as dt_set_throw_on_error,
+    set_static_common_properties as dt_set_static_common_properties,
+    clear_static_common_properties as dt_clear_static_common_properties,
+    merge_static_common_properties as dt_merge_static_common_properties,
+    unset_static_common_property as dt_unset_static_common_property,
+    get_static_common_properties as dt_get_static_common_properties,
+    set_dynamic_common_properties as dt_set_dynamic_common_properties,
+    clear_dynamic_common_properties as dt_clear_dynamic_common_properties,
+    set_error_hook as dt_set_error_hook,
+    clear_error_hook as dt_clear_error_hook,
+    with_user as dt_with_user,
+    DTException,
+    DTVerifyException,
+    DTInternalException,
+    DTNetworkException,
+    DTRemoteException,
+    DTHostException,
 )
 
 __SDK_NAME__ = "dt_server_sdk_python"
@@ -29,18 +63,6 @@ class DTAnalytics:
         config["_debug"] = debug
         dt_init(config)
 
-    def __add(self, dt_id: str, acid: Optional[str], event_name: str, event_type: str,
-              properties: Dict[str, Any]) -> bool:
-        event = dict(properties)
-        event["#dt_id"] = dt_id
-        if acid is not None:
-            event["#acid"] = acid
-        event["#event_name"] = event_name
-        event["#event_type"] = event_type
-        event["#sdk_type"] = __SDK_NAME__
-
-        return dt_add_event(event)
-
     def track(self, dt_id: str, acid: Optional[str], event_name: str, properties: Dict[str, Any]) -> bool:
         """ Track an event.
 
@@ -50,7 +72,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, event_name, "track", properties)
+        return dt_track(dt_id, acid, event_name, properties, __SDK_NAME__)
 
     def user_set(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Set user properties for the user with given dtId and acId.
@@ -60,7 +82,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_set", "user", properties)
+        return dt_user_set(dt_id, acid, properties, __SDK_NAME__)
 
     def user_set_once(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Set user properties for the user with given dtId and acId.
@@ -71,7 +93,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_set_once", "user", properties)
+        return dt_user_set_once(dt_id, acid, properties, __SDK_NAME__)
 
     def user_add(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Arithmetic add the value of property by given number for user with given dtId and acId.
@@ -82,7 +104,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_add", "user", properties)
+        return dt_user_add(dt_id, acid, properties, __SDK_NAME__)
 
     def user_unset(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Unset properties for user with given dtId and acId.
@@ -93,13 +115,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        props_dict = {}
-        for k, v in properties.items():
-            if k.startswith("#"):
-                props_dict[k] = v
-            else:
-                props_dict[k] = 0
-        return self.__add(dt_id, acid, "#user_unset", "user", props_dict)
+        return dt_user_unset(dt_id, acid, properties, __SDK_NAME__)
 
     def user_delete(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Delete the user with given dtId and acId.
@@ -109,7 +125,7 @@ class DTAnalytics:
         :param properties: preset properties of this event.
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_delete", "user", properties)
+        return dt_user_delete(dt_id, acid, properties, __SDK_NAME__)
 
     def user_append(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Append values to property for the user with given dtId and acId.
@@ -120,7 +136,7 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_append", "user", properties)
+        return dt_user_append(dt_id, acid, properties, __SDK_NAME__)
 
     def user_uniq_append(self, dt_id: str, acid: Optional[str], properties: Dict[str, Any]) -> bool:
         """ Append values to property without duplications for the user with given dtId and acId.
@@ -131,7 +147,77 @@ class DTAnalytics:
         :param properties: properties of this event. (preset properties are scoped by event name, and has type constraints)
         :return: True if given event and properties is valid or False if there are invalid and will not be processed.
         """
-        return self.__add(dt_id, acid, "#user_uniq_append", "user", properties)
+        return dt_user_uniq_append(dt_id, acid, properties, __SDK_NAME__)
+
+    def with_user(self, dt_id: str, acid: Optional[str], properties: Optional[Dict[str, Any]] = None) -> Optional["DTUserContext"]:
+        """ Create a context to track events of the user, which fills dt_id, acid and the user-scoped common properties.
+        The user-scoped common properties take precedence over the dynamic and static ones.
+
+        :param dt_id: The device-scoped id.
+        :param acid: The account-scoped id.
+        :param properties: user-scoped common properties, added to each event of the user.
+        :return: DTUserContext, or None if given properties are invalid.
+        """
+        ctx = dt_with_user(dt_id, acid, properties or {})
+        return None if ctx is None else DTUserContext(ctx)
+
+    def set_static_common_properties(self, properties: Dict[str, Any]):
+        """ Replace all the static common properties, which will be added to each event.
+
+        :param properties: the common properties.
+        """
+        dt_set_static_common_properties(properties)
+
+    def merge_static_common_properties(self, properties: Dict[str, Any]):
+        """ Merge into the static common properties, the given ones override those with the same key.
+        Hence, different modules can contribute their own common properties without clobbering each other.
+
+        :param properties: the common properties to be merged.
+        """
+        dt_merge_static_common_properties(properties)
+
+    def unset_static_common_property(self, key: str):
+        """ Remove the static common property with given key.
+
+        :param key: key of the common property.
+        """
+        dt_unset_static_common_property(key)
+
+    def get_static_common_properties(self) -> Dict[str, Any]:
+        """ Get a copy of the static common properties. """
+        return dt_get_static_common_properties()
+
+    def clear_static_common_properties(self):
+        """ Remove all the static common properties. """
+        dt_clear_static_common_properties()
+
+    def set_dynamic_common_properties(self, callback: Callable[[], Dict[str, Any]]):
+        """ Set a callback to provide common properties for each event, e.g. current request id.
+        Properties of the event take precedence over the dynamic ones, which take precedence over the static ones.
+        The callback should be fast and must not call the APIs of DTAnalytics.
+        Exceptions raised by the callback are logged and the event is tracked without dynamic common properties.
+
+        :param callback: Callable without arguments that returns a dict of properties.
+        """
+        dt_set_dynamic_common_properties(callback)
+
+    def clear_dynamic_common_properties(self):
+        """ Remove the callback set by set_dynamic_common_properties. """
+        dt_clear_dynamic_common_properties()
+
+    def set_error_hook(self, callback: Callable[[DTException, Optional[Dict[str, Any]], str], None], redact: bool = False):
+        """ Set a callback receiving each error, e.g. to count the dropped events.
+
+        :param callback: Invoked with the error, the event (None if absent) and the category,
+            which is "add" (dropped while adding), "flush" (failed to flush or close) or "write" (failed to write).
+            It should be fast and must not call the APIs of DTAnalytics, exceptions raised are logged.
+        :param redact: If set to true, the event only keeps meta properties identifying it, like "#event_name".
+        """
+        dt_set_error_hook(callback, redact)
+
+    def clear_error_hook(self):
+        """ Remove the callback set by set_error_hook. """
+        dt_clear_error_hook()
 
     def toggle_logger(self, enable: bool):
         """ To enable and disable the logging. """
@@ -147,6 +233,63 @@ class DTAnalytics:
         """ To disable the logging. """
         dt_toggle_logger(False)
 
+    @staticmethod
+    def log_to_stderr():
+        """ To write the logs to stderr, which is the default. """
+        dt_log_to_stderr()
+
+    @staticmethod
+    def log_to_file(path: str, max_bytes: int = 10 * 1024 * 1024, max_files: int = 5):
+        """ To write the logs to the file, which is rotated by size.
+
+        :param path: Path of the log file, the rotated ones are named "{path}.1" (the latest) to "{path}.{max_files}".
+        :param max_bytes: Max size of the log file in bytes.
+        :param max_files: Max number of the rotated files to keep.
+        """
+        dt_log_to_file(path, max_bytes, max_files)
+
+    @staticmethod
+    def log_to_python_logging(logger: Optional[logging.Logger] = None):
+        """ To forward the logs to the Python `logging`, which may be invoked by any thread.
+
+        :param logger: Logger to forward to, "datatower" by default.
+        """
+        logger = logger or logging.getLogger("datatower")
+        levels = {"debug": logging.DEBUG, "info": logging.INFO, "warning": logging.WARNING, "error": logging.ERROR}
+        dt_set_log_callback(lambda level, message: logger.log(levels.get(level, logging.INFO), message))
+
+    @staticmethod
+    def set_log_callback(callback: Callable[[str, str], None]):
+        """ To forward the logs to the callback, which may be invoked by any thread.
+
+        :param callback: Invoked with the level ("debug", "info", "warning" or "error") and the message.
+        """
+        dt_set_log_callback(callback)
+
+    @staticmethod
+    def set_log_level(level: str):
+        """ Minimum level of the logs.
+
+        :param level: "debug" (default), "info", "warning", "error" or "silent", which disables even the errors.
+            Logs below "warning" are only written if the logging is enabled.
+        """
+        dt_set_log_level(level)
+
+    @staticmethod
+    def set_log_format(log_format: str):
+        """ Format of the logs written to stderr and file.
+
+        :param log_format: "text" (default) or "json", which writes a JSON object per line with
+            "level", "timestamp", "module", "message" and the structured fields like "batch_size".
+        """
+        dt_set_log_format(log_format)
+
+    @staticmethod
+    def set_throw_on_error(enable: bool):
+        """ To raise DTException (or its subclasses, with the stable `code`) on error,
+        instead of logging and returning False (or default value). Disabled by default. """
+        dt_set_throw_on_error(enable)
+
     def flush(self):
         """ Flush the data buffer manually. """
         dt_flush()
@@ -155,6 +298,71 @@ class DTAnalytics:
         """ Close the DTAnalytics, remember to call this before the program finishes to preventing data loss! """
         dt_close()
 
+    def close_with_timeout(self, timeout_ms: int) -> Optional[Dict[str, int]]:
+        """ Close the DTAnalytics within the timeout, the events not sent in time are dropped and reported to the error
+        hook, e.g. by a hung upload. The timeout only applies to the consumers uploading by workers, the log consumer
+        writes all the events to the file before returning.
+        :return: the numbers of events sent and remaining while closing, e.g. {"sent": 100, "remaining": 0}, or None
+            if failed. """
+        return dt_close_with_timeout(timeout_ms)
+
+    def stats(self) -> Dict[str, Any]:
+        """ Snapshot of the runtime metrics, e.g. "events_accepted", "events_rejected" (by reason), "bytes_written",
+        "upload_failures", "queue_depth" and "last_flush_time" (milliseconds since epoch, 0 if never).
+        Counters are monotonic since the process started. """
+        return dt_stats()
+
+    def prometheus_metrics(self) -> str:
+        """ The runtime metrics in Prometheus text exposition format, e.g. "dt_events_accepted_total 10". """
+        return dt_prometheus_metrics()
+
+    def serve_metrics(self, addr: str = "127.0.0.1:9464") -> str:
+        """ Serve the runtime metrics at "/metrics" by a tiny HTTP server in background, for Prometheus to scrape.
+        Only one server is allowed, which can also be started by "metrics_addr" in the init config.
+        :return: the bound address, e.g. "127.0.0.1:9464". """
+        return dt_serve_metrics(addr)
+
+
+class DTUserContext:
+    def __init__(self, ctx):
+        """ Use DTAnalytics.with_user to create. """
+        self.__ctx = ctx
+
+    def __add(self, event_name: str, event_type: str, properties: Dict[str, Any]) -> bool:
+        return self.__ctx.add_event(event_name, event_type, properties, __SDK_NAME__)
+
+    def track(self, event_name: str, properties: Dict[str, Any]) -> bool:
+        """ Track an event of this user, see DTAnalytics.track. """
+        return self.__add(event_name, "track", properties)
+
+    def user_set(self, properties: Dict[str, Any]) -> bool:
+        """ Set properties of this user, see DTAnalytics.user_set. """
+        return self.__add("#user_set", "user", properties)
+
+    def user_set_once(self, properties: Dict[str, Any]) -> bool:
+        """ Set properties of this user without overriding, see DTAnalytics.user_set_once. """
+        return self.__add("#user_set_once", "user", properties)
+
+    def user_add(self, properties: Dict[str, Any]) -> bool:
+        """ Arithmetic add properties of this user, see DTAnalytics.user_add. """
+        return self.__add("#user_add", "user", properties)
+
+    def user_unset(self, properties: Dict[str, Any]) -> bool:
+        """ Unset properties of this user, see DTAnalytics.user_unset. """
+        return self.__add("#user_unset", "user", properties)
+
+    def user_delete(self, properties: Dict[str, Any]) -> bool:
+        """ Delete this user, see DTAnalytics.user_delete. """
+        return self.__add("#user_delete", "user", properties)
+
+    def user_append(self, properties: Dict[str, Any]) -> bool:
+        """ Append values to properties of this user, see DTAnalytics.user_append. """
+        return self.__add("#user_append", "user", properties)
+
+    def user_uniq_append(self, properties: Dict[str, Any]) -> bool:
+        """ Append values to properties of this user without duplications, see DTAnalytics.user_uniq_append. """
+        return self.__add("#user_uniq_append", "user", properties)
+
 
 class DTLogConsumer(Consumer):
     def __init__(self, path, max_batch_len, name_prefix, max_file_size_bytes):
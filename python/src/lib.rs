@@ -1,20 +1,54 @@
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use pyo3::prelude::*;
-use pythonize::depythonize;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use pythonize::{depythonize, pythonize};
 use serde_json::{Map, Value};
-use common::util::result::{dissolve, dissolve_bool};
+use common::event::tracking::assemble;
+use common::util::error::DTError;
+use common::util::logger::{fmt_text, install_default_logger, Level, level_name, LogFormat, LogSink, parse_log_level, set_log_sink};
+use common::util::result::{dissolve, dissolve_bool, dissolve_or_default, THROW_ON_ERROR};
 
 /// A Python module implemented in Rust.
 #[pymodule]
 #[pyo3(name="dt_core_base_py")]
-fn dt_core_python(_py: Python, m: &PyModule) -> PyResult<()> {
+fn dt_core_python(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(init, m)?)?;
     m.add_function(wrap_pyfunction!(add_event, m)?)?;
+    m.add_function(wrap_pyfunction!(track, m)?)?;
+    m.add_function(wrap_pyfunction!(user_set, m)?)?;
+    m.add_function(wrap_pyfunction!(user_set_once, m)?)?;
+    m.add_function(wrap_pyfunction!(user_add, m)?)?;
+    m.add_function(wrap_pyfunction!(user_unset, m)?)?;
+    m.add_function(wrap_pyfunction!(user_delete, m)?)?;
+    m.add_function(wrap_pyfunction!(user_append, m)?)?;
+    m.add_function(wrap_pyfunction!(user_uniq_append, m)?)?;
     m.add_function(wrap_pyfunction!(flush, m)?)?;
     m.add_function(wrap_pyfunction!(close, m)?)?;
+    m.add_function(wrap_pyfunction!(close_with_timeout, m)?)?;
+    m.add_function(wrap_pyfunction!(stats, m)?)?;
+    m.add_function(wrap_pyfunction!(prometheus_metrics, m)?)?;
+    m.add_function(wrap_pyfunction!(serve_metrics, m)?)?;
     m.add_function(wrap_pyfunction!(toggle_logger, m)?)?;
+    m.add_function(wrap_pyfunction!(log_to_stderr, m)?)?;
+    m.add_function(wrap_pyfunction!(log_to_file, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_callback, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_format, m)?)?;
+    m.add_function(wrap_pyfunction!(set_log_level, m)?)?;
+    m.add_function(wrap_pyfunction!(set_throw_on_error, m)?)?;
     m.add_function(wrap_pyfunction!(set_static_common_properties, m)?)?;
     m.add_function(wrap_pyfunction!(clear_static_common_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_static_common_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(unset_static_common_property, m)?)?;
+    m.add_function(wrap_pyfunction!(get_static_common_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(set_dynamic_common_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(with_user, m)?)?;
+    m.add_class::<UserContext>()?;
+    m.add_function(wrap_pyfunction!(clear_dynamic_common_properties, m)?)?;
+    m.add_function(wrap_pyfunction!(set_error_hook, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_error_hook, m)?)?;
+    exceptions::register(py, m)?;
     Ok(())
 }
 
@@ -24,22 +58,95 @@ fn init(config: MyMap) -> PyResult<bool> {
         assert!(py.version_info() >= (3, 7, 0), "Only supports Python version 3.7.0 and up!")
     });
 
-    dissolve_bool(common::init_by_config(config.0))
+    install_default_logger();
+    dissolve_bool(common::init_by_config(config.0)).map_err(exceptions::raise)
 }
 
 #[pyfunction]
 fn add_event(event: MyMap) -> PyResult<bool> {
-    dissolve_bool(common::add(event.0))
+    dissolve_bool(common::add(event.0)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, event_name, properties, sdk_type))]
+fn track(dt_id: String, acid: Option<String>, event_name: &str, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::track(dt_id, acid, event_name, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_set(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_set(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_set_once(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_set_once(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_add(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_add(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_unset(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_unset(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_delete(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_delete(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_append(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_append(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, properties, sdk_type))]
+fn user_uniq_append(dt_id: String, acid: Option<String>, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+    dissolve_bool(common::user_uniq_append(dt_id, acid, properties.0, sdk_type)).map_err(exceptions::raise)
 }
 
 #[pyfunction]
 fn flush() -> PyResult<()> {
-    dissolve(common::flush())
+    dissolve(common::flush()).map_err(exceptions::raise)
 }
 
 #[pyfunction]
 fn close() -> PyResult<()> {
-    dissolve(common::close())
+    dissolve(common::close()).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn close_with_timeout(py: Python, timeout_ms: u64) -> PyResult<PyObject> {
+    let outcome = common::close_with_timeout(Duration::from_millis(timeout_ms)).map(Some);
+    match dissolve_or_default(outcome).map_err(exceptions::raise)? {
+        Some(outcome) => pythonize(py, &outcome.to_map()).map_err(PyErr::from),
+        None => Ok(py.None()),
+    }
+}
+
+#[pyfunction]
+fn stats(py: Python) -> PyResult<PyObject> {
+    pythonize(py, &common::stats()).map_err(PyErr::from)
+}
+
+#[pyfunction]
+fn prometheus_metrics() -> PyResult<String> {
+    Ok(common::prometheus_metrics())
+}
+
+#[pyfunction]
+fn serve_metrics(addr: &str) -> PyResult<String> {
+    dissolve_or_default(common::serve_metrics(addr).map(|addr| addr.to_string())).map_err(exceptions::raise)
 }
 
 #[pyfunction]
@@ -48,14 +155,185 @@ fn toggle_logger(enable: bool) -> PyResult<()> {
     Ok(())
 }
 
+#[pyfunction]
+fn log_to_stderr() -> PyResult<()> {
+    set_log_sink(LogSink::Stderr);
+    Ok(())
+}
+
+#[pyfunction]
+#[pyo3(signature = (path, max_bytes, max_files))]
+fn log_to_file(path: &str, max_bytes: u64, max_files: usize) -> PyResult<()> {
+    let sink = LogSink::file(path, max_bytes, max_files).map_err(exceptions::raise)?;
+    set_log_sink(sink);
+    Ok(())
+}
+
+/// The callback receives (level: str, message: str), level is "debug", "info", "warning" or "error".
+#[pyfunction]
+fn set_log_callback(callback: PyObject) -> PyResult<()> {
+    set_log_sink(LogSink::Callback(Box::new(move |level, msg| {
+        Python::with_gil(|py| {
+            if let Err(err) = callback.call1(py, (level_name(level), msg)) {
+                // Not logged by SDK, which would come back here.
+                eprintln!("{}", fmt_text(level, msg));
+                eprintln!("{}", fmt_text(Level::Error, &format!("Log callback failed: {err}")));
+            }
+        })
+    })));
+    Ok(())
+}
+
+#[pyfunction]
+fn set_log_format(format: &str) -> PyResult<()> {
+    let format = LogFormat::parse(format).map_err(exceptions::raise)?;
+    common::util::logger::set_log_format(format);
+    Ok(())
+}
+
+#[pyfunction]
+fn set_log_level(level: &str) -> PyResult<()> {
+    let level = parse_log_level(level).map_err(exceptions::raise)?;
+    common::util::logger::set_log_level(level);
+    Ok(())
+}
+
+#[pyfunction]
+fn set_throw_on_error(enable: bool) -> PyResult<()> {
+    THROW_ON_ERROR.store(enable, Ordering::Relaxed);
+    Ok(())
+}
+
 #[pyfunction]
 fn set_static_common_properties(props: MyMap) -> PyResult<()> {
-    dissolve(common::set_static_common_props(props.0))
+    dissolve(common::set_static_common_props(props.0)).map_err(exceptions::raise)
 }
 
 #[pyfunction]
 fn clear_static_common_properties() -> PyResult<()> {
-    dissolve(common::clear_static_common_props())
+    dissolve(common::clear_static_common_props()).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn merge_static_common_properties(props: MyMap) -> PyResult<()> {
+    dissolve(common::merge_static_common_props(props.0)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn unset_static_common_property(key: &str) -> PyResult<()> {
+    dissolve(common::unset_static_common_prop(key)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn get_static_common_properties(py: Python) -> PyResult<PyObject> {
+    let props = dissolve_or_default(common::get_static_common_props()).map_err(exceptions::raise)?;
+    pythonize(py, &props).map_err(PyErr::from)
+}
+
+#[pyfunction]
+fn set_dynamic_common_properties(callback: PyObject) -> PyResult<()> {
+    dissolve(common::set_dynamic_common_props(move || {
+        Python::with_gil(|py| {
+            let props = callback.call0(py)
+                .and_then(|props| props.extract::<MyMap>(py))
+                .map_err(|e| DTError::HostError(format!("Dynamic common properties callback failed: {e}")))?;
+            Ok(props.0)
+        })
+    })).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn clear_dynamic_common_properties() -> PyResult<()> {
+    dissolve(common::clear_dynamic_common_props()).map_err(exceptions::raise)
+}
+
+/// The callback receives (error: DTException, event: Optional[dict], category: str).
+#[pyfunction]
+#[pyo3(signature = (callback, redact=false))]
+fn set_error_hook(callback: PyObject, redact: bool) -> PyResult<()> {
+    dissolve(common::set_error_hook(move |e, event, category| {
+        Python::with_gil(|py| {
+            let event = match event.map(|event| pythonize(py, event)).transpose() {
+                Ok(event) => event,
+                Err(err) => {
+                    common::log_error!("Failed to convert event for error hook: {err}");
+                    None
+                }
+            };
+            let error = exceptions::to_exception(e).into_value(py);
+            if let Err(err) = callback.call1(py, (error, event, category.as_str())) {
+                common::log_error!("Error hook failed: {err}");
+            }
+        })
+    }, redact)).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+fn clear_error_hook() -> PyResult<()> {
+    dissolve(common::clear_error_hook()).map_err(exceptions::raise)
+}
+
+#[pyfunction]
+#[pyo3(signature = (dt_id, acid, props))]
+fn with_user(dt_id: String, acid: Option<String>, props: MyMap) -> PyResult<Option<UserContext>> {
+    dissolve_or_default(common::with_user(dt_id, acid, props.0).map(|ctx| Some(UserContext(ctx))))
+        .map_err(exceptions::raise)
+}
+
+#[pyclass]
+struct UserContext(common::event::user_context::UserContext);
+
+#[pymethods]
+impl UserContext {
+    fn add_event(&self, event_name: &str, event_type: &str, properties: MyMap, sdk_type: &str) -> PyResult<bool> {
+        dissolve_bool(self.0.add(assemble(event_name, event_type, properties.0, sdk_type))).map_err(exceptions::raise)
+    }
+}
+
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+    use pyo3::prelude::*;
+    use common::util::error::DTError;
+
+    create_exception!(dt_core_base_py, DTException, PyException, "Error reported by the core, with the stable `code`.");
+    create_exception!(dt_core_base_py, DTVerifyException, DTException, "Given event or properties are invalid.");
+    create_exception!(dt_core_base_py, DTInternalException, DTException, "Due to internal reason, e.g. failed to write the log file.");
+    create_exception!(dt_core_base_py, DTNetworkException, DTException, "Due to network reason.");
+    create_exception!(dt_core_base_py, DTRemoteException, DTException, "Due to remote reason.");
+    create_exception!(dt_core_base_py, DTHostException, DTException, "Given arguments can't be converted.");
+
+    pub(super) fn register(py: Python, m: &PyModule) -> PyResult<()> {
+        for (name, ty, code) in [
+            ("DTException", py.get_type::<DTException>(), DTError::CODE_RUNTIME),
+            ("DTVerifyException", py.get_type::<DTVerifyException>(), DTError::CODE_VERIFY),
+            ("DTInternalException", py.get_type::<DTInternalException>(), DTError::CODE_INTERNAL),
+            ("DTNetworkException", py.get_type::<DTNetworkException>(), DTError::CODE_NETWORK),
+            ("DTRemoteException", py.get_type::<DTRemoteException>(), DTError::CODE_REMOTE),
+            ("DTHostException", py.get_type::<DTHostException>(), DTError::CODE_HOST),
+        ] {
+            ty.setattr("code", code)?;
+            m.add(name, ty)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn raise(e: DTError) -> PyErr {
+        to_exception(&e)
+    }
+
+    /// Converts to the subclass of DTException by the code.
+    pub(super) fn to_exception(e: &DTError) -> PyErr {
+        let msg = e.to_string();
+        match e.code() {
+            DTError::CODE_VERIFY => DTVerifyException::new_err(msg),
+            DTError::CODE_INTERNAL => DTInternalException::new_err(msg),
+            DTError::CODE_NETWORK => DTNetworkException::new_err(msg),
+            DTError::CODE_REMOTE => DTRemoteException::new_err(msg),
+            DTError::CODE_HOST => DTHostException::new_err(msg),
+            _ => DTException::new_err(msg),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -63,9 +341,55 @@ struct MyMap(Map<String, Value>);
 
 impl<'py> FromPyObject<'py> for MyMap {
     fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        let converted = normalise_dates(ob)?;
+        let ob = match &converted {
+            Some(converted) => converted.as_ref(ob.py()),
+            None => ob,
+        };
         match depythonize(ob.downcast().unwrap()) {
             Ok(map) => Ok(MyMap(map)),
             Err(e) => Err(PyErr::from(e))
         }
     }
 }
+
+/// Converts `datetime.date` and `datetime.datetime` to ISO-8601 string recursively,
+/// returns None if nothing needs to be converted.
+fn normalise_dates(ob: &PyAny) -> PyResult<Option<PyObject>> {
+    static DATE_TYPE: GILOnceCell<PyObject> = GILOnceCell::new();
+    let py = ob.py();
+    let date_type = DATE_TYPE.get_or_try_init(py, || -> PyResult<PyObject> {
+        Ok(py.import("datetime")?.getattr("date")?.into())
+    })?.as_ref(py);
+
+    if ob.is_instance(date_type)? {
+        Ok(Some(ob.call_method0("isoformat")?.into()))
+    } else if let Ok(dict) = ob.downcast::<PyDict>() {
+        let mut copied: Option<&PyDict> = None;
+        for (k, v) in dict.iter() {
+            if let Some(converted) = normalise_dates(v)? {
+                let copied = match copied {
+                    Some(copied) => copied,
+                    None => copied.insert(dict.copy()?),
+                };
+                copied.set_item(k, converted)?;
+            }
+        }
+        Ok(copied.map(|it| it.into()))
+    } else if ob.is_instance_of::<PyList>() || ob.is_instance_of::<PyTuple>() {
+        let mut items: Vec<PyObject> = Vec::new();
+        let mut changed = false;
+        for item in ob.iter()? {
+            let item = item?;
+            if let Some(converted) = normalise_dates(item)? {
+                changed = true;
+                items.push(converted);
+            } else {
+                items.push(item.into());
+            }
+        }
+        Ok(if changed { Some(PyList::new(py, items).into()) } else { None })
+    } else {
+        Ok(None)
+    }
+}